@@ -0,0 +1,711 @@
+use chip8::DEFAULT_LOAD_ADDRESS;
+use instruction::Instruction;
+use platform::Platform;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::mem;
+
+/// Assembler for the CHIPPER dialect the sources in `games/SOURCES` are written in.
+///
+/// Programs are assembled at `0x200` into a flat binary. The source is case insensitive and
+/// supports:
+///
+/// - labels, written `NAME:` anywhere or `NAME` in the first column;
+/// - symbols defined with `NAME = EXPR` or `NAME EQU EXPR`;
+/// - expressions over decimal, `#hex`, `$binary` (`.` counts as 0) and `@octal` numbers,
+///   symbols and `?` (the current address), with the operators `| ^ & < > + - * / % ~` and
+///   parentheses (`<` and `>` are shifts);
+/// - `DB`, `DW` and `DA` (a quoted string) data, and `DS` to reserve bytes;
+/// - `OPTION BINARY`, `OPTION CHIP8`, `OPTION CHIP48`, `OPTION SCHIP10`, `OPTION SCHIP11`,
+///   `ALIGN ON|OFF` (whether instructions and words start at even addresses), `DEFINE`, `UNDEF`,
+///   `IFDEF`, `IFUND`, `ELSE`, `ENDIF` and `END`. The listing options `USED` and `XREF` are
+///   accepted and ignored.
+#[derive(Default)]
+pub struct Assembler {
+    defines: HashSet<String>,
+}
+
+impl Assembler {
+    pub fn new() -> Assembler {
+        Assembler::default()
+    }
+
+    /// Defines `symbol` for `IFDEF`, like a `DEFINE` at the start of the source.
+    pub fn define(&mut self, symbol: &str) {
+        self.defines.insert(symbol.to_uppercase());
+    }
+
+    pub fn assemble(&self, source: &str) -> Result<Vec<u8>, AssemblerError> {
+        // The first pass only collects the symbols, the second one emits the program with all of
+        // them known
+        let mut symbols = HashMap::new();
+        for &last_pass in &[false, true] {
+            let mut pass = Pass {
+                symbols,
+                defines: self.defines.clone(),
+                last_pass,
+                address: DEFAULT_LOAD_ADDRESS as i64,
+                output: Vec::new(),
+                platform: Platform::SuperChip,
+                align: true,
+                conditions: Vec::new(),
+                labels: Vec::new(),
+            };
+            for (number, line) in source.lines().enumerate() {
+                let result = match pass.line(line) {
+                    // Labels at the end of the program point past it
+                    Ok(false) => pass.define_labels().map(|_| false),
+                    result => result,
+                };
+                match result {
+                    Ok(true) => {}
+                    Ok(false) => break,
+                    Err(message) => {
+                        return Err(AssemblerError {
+                            line: number + 1,
+                            message,
+                        })
+                    }
+                }
+            }
+            if let Err(message) = pass.define_labels() {
+                return Err(AssemblerError {
+                    line: source.lines().count(),
+                    message,
+                });
+            }
+            if !pass.conditions.is_empty() {
+                return Err(AssemblerError {
+                    line: source.lines().count(),
+                    message: "IFDEF without ENDIF".to_string(),
+                });
+            }
+            if last_pass {
+                return Ok(pass.output);
+            }
+            symbols = pass.symbols;
+        }
+        unreachable!()
+    }
+}
+
+/// A line of the source that could not be assembled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssemblerError {
+    /// Line number, counted from 1.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssemblerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for AssemblerError {}
+
+// Directives and mnemonics, which can not be used as labels without a colon
+static KEYWORDS: [&str; 42] = [
+    "OPTION", "ALIGN", "USED", "XREF", "DEFINE", "UNDEF", "IFDEF", "IFUND", "ELSE", "ENDIF", "END",
+    "EQU", "DB", "DW", "DA", "DS", "CLS", "RET", "SCD", "SCR", "SCL", "EXIT", "LOW", "HIGH", "SYS",
+    "JP", "CALL", "SE", "SNE", "LD", "ADD", "OR", "AND", "XOR", "SUB", "SHR", "SUBN", "SHL", "RND",
+    "DRW", "SKP", "SKNP",
+];
+
+// State of one pass over the source
+struct Pass {
+    symbols: HashMap<String, i64>,
+    defines: HashSet<String>,
+    last_pass: bool,
+    address: i64,
+    output: Vec<u8>,
+    // Instruction set selected with OPTION
+    platform: Platform,
+    align: bool,
+    // Whether each enclosing IFDEF or ELSE block is assembled
+    conditions: Vec<bool>,
+    // Labels waiting for the next statement
+    labels: Vec<String>,
+}
+
+impl Pass {
+    // Returns false at END
+    fn line(&mut self, line: &str) -> Result<bool, String> {
+        let line = strip_comment(line);
+        let mut rest = line.trim();
+        if rest.is_empty() {
+            return Ok(true);
+        }
+
+        // A label ends with a colon, or starts in the first column when it is not a keyword. A
+        // symbol in the first column followed by = or EQU is a definition instead
+        let (first, after) = split_word(rest);
+        let mut label = None;
+        if let Some(colon) = first.find(':') {
+            label = Some(first[..colon].to_string());
+            rest = rest[(colon + 1)..].trim();
+        } else if is_definition(after) {
+            if self.active() {
+                let (_, expression) = split_word(after.trim());
+                let value = self.evaluate(expression)?;
+                self.define_symbol(first, value)?;
+            }
+            return Ok(true);
+        } else if !line.starts_with(char::is_whitespace)
+            && !KEYWORDS.contains(&first.to_uppercase().as_str())
+        {
+            label = Some(first.to_string());
+            rest = after.trim();
+        }
+
+        let (mnemonic, operands) = split_word(rest);
+        let mnemonic = mnemonic.to_uppercase();
+        let operands = operands.trim();
+        match mnemonic.as_str() {
+            "IFDEF" | "IFUND" | "ELSE" | "ENDIF" => {
+                if let (Some(name), true) = (label, self.active()) {
+                    self.labels.push(name);
+                }
+                self.condition(&mnemonic, operands)?;
+                return Ok(true);
+            }
+            _ if !self.active() => return Ok(true),
+            "=" | "EQU" => {
+                let name = label.ok_or("missing symbol name")?;
+                let value = self.evaluate(operands)?;
+                self.define_symbol(&name, value)?;
+                return Ok(true);
+            }
+            _ => {}
+        }
+
+        // Labels take the address of the next statement, once it is aligned
+        self.labels.extend(label);
+        if mnemonic.is_empty() {
+            return Ok(true);
+        }
+        if self.align && is_aligned_statement(&mnemonic) && self.address % 2 == 1 {
+            self.emit(&[0]);
+        }
+        self.define_labels()?;
+        self.statement(&mnemonic, operands)
+    }
+
+    fn define_labels(&mut self) -> Result<(), String> {
+        let address = self.address;
+        for name in mem::take(&mut self.labels) {
+            self.define_symbol(&name, address)?;
+        }
+        Ok(())
+    }
+
+    fn condition(&mut self, directive: &str, operand: &str) -> Result<(), String> {
+        match directive {
+            "IFDEF" | "IFUND" => {
+                let defined = self.defines.contains(&operand.to_uppercase());
+                let active = self.active() && defined == (directive == "IFDEF");
+                self.conditions.push(active);
+            }
+            "ELSE" => {
+                let depth = self.conditions.len();
+                let enclosing = depth < 2 || self.conditions[depth - 2];
+                let condition = self.conditions.last_mut().ok_or("ELSE without IFDEF")?;
+                *condition = enclosing && !*condition;
+            }
+            _ => {
+                self.conditions.pop().ok_or("ENDIF without IFDEF")?;
+            }
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, mnemonic: &str, operands: &str) -> Result<bool, String> {
+        match mnemonic {
+            "END" => return Ok(false),
+            "OPTION" => match operands.to_uppercase().as_str() {
+                "BINARY" => {}
+                "CHIP8" | "CHIP48" => self.platform = Platform::Chip8,
+                "SCHIP10" | "SCHIP11" => self.platform = Platform::SuperChip,
+                "HPASC" | "HPHEX" => return Err("only binary output is supported".to_string()),
+                option => return Err(format!("unknown option {}", option)),
+            },
+            "ALIGN" => self.align = on_off(operands)?,
+            "USED" | "XREF" => {}
+            "DEFINE" => {
+                self.defines.insert(operands.to_uppercase());
+            }
+            "UNDEF" => {
+                self.defines.remove(&operands.to_uppercase());
+            }
+            "DB" => {
+                for operand in split_operands(operands) {
+                    match string_literal(&operand)? {
+                        Some(string) => self.emit(&string),
+                        None => {
+                            let value = self.evaluate(&operand)?;
+                            let byte = byte(value)?;
+                            self.emit(&[byte]);
+                        }
+                    }
+                }
+            }
+            "DW" => {
+                for operand in split_operands(operands) {
+                    let value = self.evaluate(&operand)?;
+                    if !(-0x8000..=0xFFFF).contains(&value) {
+                        return Err(format!("word out of range: {}", value));
+                    }
+                    self.emit(&[(value >> 8) as u8, value as u8]);
+                }
+            }
+            "DA" => {
+                let string = string_literal(operands)?.ok_or("DA expects a quoted string")?;
+                self.emit(&string);
+            }
+            "DS" => {
+                let size = self.evaluate_now(operands)?;
+                if !(0..=0x10000).contains(&size) {
+                    return Err(format!("invalid size: {}", size));
+                }
+                self.emit(&vec![0; size as usize]);
+            }
+            _ => {
+                let operands = split_operands(operands);
+                let bytes = self.instruction(mnemonic, &operands)?;
+                self.emit(&bytes);
+            }
+        }
+        Ok(true)
+    }
+
+    fn instruction(&mut self, mnemonic: &str, operands: &[String]) -> Result<Vec<u8>, String> {
+        use self::Instruction::*;
+        let operands: Vec<Operand> = operands
+            .iter()
+            .map(|operand| Operand::parse(operand))
+            .collect();
+        let instruction = match (mnemonic, operands.as_slice()) {
+            ("SYS", [address]) => {
+                let address = self.address_value(address)?;
+                return Ok(vec![(address >> 8) as u8, address as u8]);
+            }
+            ("CLS", []) => ClearScreen,
+            ("RET", []) => Return,
+            ("SCD", [n]) => ScrollDown(self.nibble(n)?),
+            ("SCR", []) => ScrollRight,
+            ("SCL", []) => ScrollLeft,
+            ("EXIT", []) => Exit,
+            ("LOW", []) => LowRes,
+            ("HIGH", []) => HighRes,
+            ("JP", [Operand::Register(0), address]) => JumpOffset(self.address_value(address)?),
+            ("JP", [address]) => Jump(self.address_value(address)?),
+            ("CALL", [address]) => Call(self.address_value(address)?),
+            ("SE", [Operand::Register(x), Operand::Register(y)]) => SkipEqual(*x, *y),
+            ("SE", [Operand::Register(x), kk]) => SkipEqualByte(*x, self.byte_value(kk)?),
+            ("SNE", [Operand::Register(x), Operand::Register(y)]) => SkipNotEqual(*x, *y),
+            ("SNE", [Operand::Register(x), kk]) => SkipNotEqualByte(*x, self.byte_value(kk)?),
+            ("LD", [Operand::Register(x), Operand::Register(y)]) => Move(*x, *y),
+            ("LD", [Operand::Register(x), Operand::DelayTimer]) => GetDelay(*x),
+            ("LD", [Operand::Register(x), Operand::Key]) => WaitKey(*x),
+            ("LD", [Operand::Register(x), Operand::IndirectIndex]) => Load(*x),
+            ("LD", [Operand::Register(x), Operand::Flags]) => LoadFlags(*x),
+            ("LD", [Operand::Register(x), kk]) => LoadByte(*x, self.byte_value(kk)?),
+            ("LD", [Operand::Index, address]) => LoadIndex(self.address_value(address)?),
+            ("LD", [Operand::DelayTimer, Operand::Register(x)]) => SetDelay(*x),
+            ("LD", [Operand::SoundTimer, Operand::Register(x)]) => SetSound(*x),
+            ("LD", [Operand::Font, Operand::Register(x)]) => Font(*x),
+            ("LD", [Operand::BigFont, Operand::Register(x)]) => BigFont(*x),
+            ("LD", [Operand::Bcd, Operand::Register(x)]) => Bcd(*x),
+            ("LD", [Operand::IndirectIndex, Operand::Register(x)]) => Store(*x),
+            ("LD", [Operand::Flags, Operand::Register(x)]) => SaveFlags(*x),
+            ("ADD", [Operand::Register(x), Operand::Register(y)]) => Add(*x, *y),
+            ("ADD", [Operand::Register(x), kk]) => AddByte(*x, self.byte_value(kk)?),
+            ("ADD", [Operand::Index, Operand::Register(x)]) => AddIndex(*x),
+            ("OR", [Operand::Register(x), Operand::Register(y)]) => Or(*x, *y),
+            ("AND", [Operand::Register(x), Operand::Register(y)]) => And(*x, *y),
+            ("XOR", [Operand::Register(x), Operand::Register(y)]) => Xor(*x, *y),
+            ("SUB", [Operand::Register(x), Operand::Register(y)]) => Sub(*x, *y),
+            ("SUBN", [Operand::Register(x), Operand::Register(y)]) => SubReverse(*x, *y),
+            ("SHR", [Operand::Register(x)]) => ShiftRight(*x, 0),
+            ("SHR", [Operand::Register(x), Operand::Register(y)]) => ShiftRight(*x, *y),
+            ("SHL", [Operand::Register(x)]) => ShiftLeft(*x, 0),
+            ("SHL", [Operand::Register(x), Operand::Register(y)]) => ShiftLeft(*x, *y),
+            ("RND", [Operand::Register(x), kk]) => Random(*x, self.byte_value(kk)?),
+            ("DRW", [Operand::Register(x), Operand::Register(y), n]) => {
+                Draw(*x, *y, self.nibble(n)?)
+            }
+            ("SKP", [Operand::Register(x)]) => SkipKey(*x),
+            ("SKNP", [Operand::Register(x)]) => SkipNotKey(*x),
+            _ if KEYWORDS.contains(&mnemonic) => {
+                return Err(format!("invalid operands for {}", mnemonic))
+            }
+            _ => return Err(format!("unknown instruction {}", mnemonic)),
+        };
+
+        // The instruction set selected with OPTION decides what can be assembled
+        let bytes = instruction.encode();
+        if Instruction::decode(&bytes, self.platform) != Some(instruction) {
+            return Err(format!(
+                "{} is not available on {}",
+                mnemonic,
+                self.platform.name()
+            ));
+        }
+        Ok(bytes)
+    }
+
+    fn active(&self) -> bool {
+        self.conditions.last().cloned().unwrap_or(true)
+    }
+
+    fn emit(&mut self, bytes: &[u8]) {
+        self.output.extend_from_slice(bytes);
+        self.address += bytes.len() as i64;
+    }
+
+    fn define_symbol(&mut self, name: &str, value: i64) -> Result<(), String> {
+        let name = name.trim().to_uppercase();
+        if name.is_empty() {
+            return Err("missing symbol name".to_string());
+        }
+        // The second pass redefines every symbol, with forward references resolved
+        if !self.last_pass && self.symbols.contains_key(&name) {
+            return Err(format!("symbol {} already defined", name));
+        }
+        self.symbols.insert(name, value);
+        Ok(())
+    }
+
+    // Undefined symbols are an error in the last pass only
+    fn evaluate(&self, expression: &str) -> Result<i64, String> {
+        Expression::new(expression, self, !self.last_pass).parse()
+    }
+
+    // For the values that decide where the following code goes
+    fn evaluate_now(&self, expression: &str) -> Result<i64, String> {
+        Expression::new(expression, self, false).parse()
+    }
+
+    fn value(&self, operand: &Operand) -> Result<i64, String> {
+        match *operand {
+            Operand::Value(ref expression) => self.evaluate(expression),
+            _ => Err("expected a value".to_string()),
+        }
+    }
+
+    fn byte_value(&self, operand: &Operand) -> Result<u8, String> {
+        byte(self.value(operand)?)
+    }
+
+    fn nibble(&self, operand: &Operand) -> Result<u8, String> {
+        match self.value(operand)? {
+            value @ 0..=0xF => Ok(value as u8),
+            value => Err(format!("nibble out of range: {}", value)),
+        }
+    }
+
+    fn address_value(&self, operand: &Operand) -> Result<u16, String> {
+        match self.value(operand)? {
+            value @ 0..=0xFFF => Ok(value as u16),
+            value => Err(format!("address out of range: {:#x}", value)),
+        }
+    }
+}
+
+// Bytes can be written signed
+fn byte(value: i64) -> Result<u8, String> {
+    if (-0x80..=0xFF).contains(&value) {
+        Ok(value as u8)
+    } else {
+        Err(format!("byte out of range: {}", value))
+    }
+}
+
+fn on_off(operand: &str) -> Result<bool, String> {
+    match operand.to_uppercase().as_str() {
+        "ON" => Ok(true),
+        "OFF" => Ok(false),
+        operand => Err(format!("expected ON or OFF, found {}", operand)),
+    }
+}
+
+// Data bytes and strings may start at odd addresses
+fn is_aligned_statement(mnemonic: &str) -> bool {
+    !matches!(
+        mnemonic,
+        "" | "DB" | "DA" | "DS" | "OPTION" | "ALIGN" | "USED" | "XREF" | "DEFINE" | "UNDEF" | "END"
+    )
+}
+
+fn is_definition(after: &str) -> bool {
+    let (word, _) = split_word(after.trim());
+    word == "=" || word.eq_ignore_ascii_case("EQU")
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (index, character) in line.char_indices() {
+        match character {
+            '\'' | '"' => quoted = !quoted,
+            ';' if !quoted => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn split_word(text: &str) -> (&str, &str) {
+    match text.find(char::is_whitespace) {
+        Some(end) => (&text[..end], &text[end..]),
+        None => (text, ""),
+    }
+}
+
+// Operands are separated by commas outside of strings
+fn split_operands(operands: &str) -> Vec<String> {
+    let mut result = Vec::new();
+    if operands.trim().is_empty() {
+        return result;
+    }
+    let mut current = String::new();
+    let mut quoted = false;
+    for character in operands.chars() {
+        match character {
+            '\'' | '"' => {
+                quoted = !quoted;
+                current.push(character);
+            }
+            ',' if !quoted => result.push(current.trim().to_string()),
+            _ => current.push(character),
+        }
+        if character == ',' && !quoted {
+            current = String::new();
+        }
+    }
+    // A trailing comma does not add an operand
+    if !current.trim().is_empty() {
+        result.push(current.trim().to_string());
+    }
+    result
+}
+
+// 'text' or "text", with the quote doubled to include it
+fn string_literal(operand: &str) -> Result<Option<Vec<u8>>, String> {
+    let quote = match operand.chars().next() {
+        Some(quote @ '\'') | Some(quote @ '"') => quote,
+        _ => return Ok(None),
+    };
+    if operand.len() < 2 || !operand.ends_with(quote) {
+        return Err(format!("unterminated string {}", operand));
+    }
+    let doubled: String = [quote, quote].iter().collect();
+    let text = operand[1..(operand.len() - 1)].replace(&doubled, &quote.to_string());
+    if !text.is_ascii() {
+        return Err("strings must be ASCII".to_string());
+    }
+    Ok(Some(text.into_bytes()))
+}
+
+enum Operand {
+    Register(u8),
+    Index,
+    IndirectIndex,
+    DelayTimer,
+    SoundTimer,
+    Key,
+    Font,
+    BigFont,
+    Bcd,
+    Flags,
+    Value(String),
+}
+
+impl Operand {
+    fn parse(operand: &str) -> Operand {
+        let upper = operand.to_uppercase();
+        match upper.as_str() {
+            "I" => return Operand::Index,
+            "[I]" => return Operand::IndirectIndex,
+            "DT" => return Operand::DelayTimer,
+            "ST" => return Operand::SoundTimer,
+            "K" => return Operand::Key,
+            "F" => return Operand::Font,
+            "HF" => return Operand::BigFont,
+            "B" => return Operand::Bcd,
+            "R" => return Operand::Flags,
+            _ => {}
+        }
+        if let Some(register) = register(&upper) {
+            return Operand::Register(register);
+        }
+        Operand::Value(operand.to_string())
+    }
+}
+
+fn register(name: &str) -> Option<u8> {
+    let mut characters = name.chars();
+    match (characters.next(), characters.next(), characters.next()) {
+        (Some('V'), Some(digit), None) => digit.to_digit(16).map(|digit| digit as u8),
+        _ => None,
+    }
+}
+
+// Recursive descent over an expression, from the lowest precedence operator to the highest:
+// | ^ & (< >) (\ %) (+ -) (* /) and the unary - + ~
+struct Expression<'a> {
+    text: &'a [u8],
+    position: usize,
+    pass: &'a Pass,
+    // Undefined symbols evaluate to 0 instead of failing
+    lenient: bool,
+}
+
+impl<'a> Expression<'a> {
+    fn new(text: &'a str, pass: &'a Pass, lenient: bool) -> Expression<'a> {
+        Expression {
+            text: text.as_bytes(),
+            position: 0,
+            pass,
+            lenient,
+        }
+    }
+
+    fn parse(mut self) -> Result<i64, String> {
+        let value = self.binary(0)?;
+        self.skip_spaces();
+        if self.position < self.text.len() {
+            return Err(format!(
+                "invalid expression: {}",
+                String::from_utf8_lossy(self.text).trim()
+            ));
+        }
+        Ok(value)
+    }
+
+    fn binary(&mut self, level: usize) -> Result<i64, String> {
+        const LEVELS: [&[u8]; 7] = [b"|", b"^", b"&", b"<>", b"\\%", b"+-", b"*/"];
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+
+        let mut value = self.binary(level + 1)?;
+        loop {
+            self.skip_spaces();
+            let operator = match self.text.get(self.position) {
+                Some(operator) if LEVELS[level].contains(operator) => *operator,
+                _ => return Ok(value),
+            };
+            self.position += 1;
+            let right = self.binary(level + 1)?;
+            value = match operator {
+                b'|' => value | right,
+                b'^' => value ^ right,
+                b'&' => value & right,
+                b'<' => value.checked_shl(right as u32).unwrap_or(0),
+                b'>' => value.checked_shr(right as u32).unwrap_or(0),
+                b'+' => value.wrapping_add(right),
+                b'-' => value.wrapping_sub(right),
+                b'*' => value.wrapping_mul(right),
+                _ if right == 0 => return Err("division by zero".to_string()),
+                b'/' | b'\\' => value / right,
+                _ => value % right,
+            };
+        }
+    }
+
+    fn unary(&mut self) -> Result<i64, String> {
+        self.skip_spaces();
+        match self.text.get(self.position) {
+            Some(b'-') => {
+                self.position += 1;
+                Ok(self.unary()?.wrapping_neg())
+            }
+            Some(b'+') => {
+                self.position += 1;
+                self.unary()
+            }
+            Some(b'~') => {
+                self.position += 1;
+                Ok(!self.unary()?)
+            }
+            Some(b'(') => {
+                self.position += 1;
+                let value = self.binary(0)?;
+                self.skip_spaces();
+                if self.text.get(self.position) != Some(&b')') {
+                    return Err("missing )".to_string());
+                }
+                self.position += 1;
+                Ok(value)
+            }
+            Some(b'?') => {
+                self.position += 1;
+                Ok(self.pass.address)
+            }
+            Some(b'#') => self.number(16, 1),
+            Some(b'$') => self.number(2, 1),
+            Some(b'@') => self.number(8, 1),
+            Some(digit) if digit.is_ascii_digit() => self.number(10, 0),
+            Some(&character) if is_symbol_character(character) => self.symbol(),
+            _ => Err("expected a value".to_string()),
+        }
+    }
+
+    fn number(&mut self, radix: u32, prefix: usize) -> Result<i64, String> {
+        let start = self.position;
+        self.position += prefix;
+        let mut value: i64 = 0;
+        let mut digits = 0;
+        while let Some(&character) = self.text.get(self.position) {
+            // Binary numbers draw sprites, with dots for the unset pixels
+            let digit = match character {
+                b'.' if radix == 2 => 0,
+                _ => match (character as char).to_digit(radix) {
+                    Some(digit) => digit,
+                    None => break,
+                },
+            };
+            value = value.wrapping_mul(radix as i64).wrapping_add(digit as i64);
+            digits += 1;
+            self.position += 1;
+        }
+        if digits == 0 {
+            let text = String::from_utf8_lossy(&self.text[start..]);
+            return Err(format!("invalid number: {}", text.trim()));
+        }
+        Ok(value)
+    }
+
+    fn symbol(&mut self) -> Result<i64, String> {
+        let start = self.position;
+        while self
+            .text
+            .get(self.position)
+            .is_some_and(|&character| is_symbol_character(character))
+        {
+            self.position += 1;
+        }
+        let name = String::from_utf8_lossy(&self.text[start..self.position]).to_uppercase();
+        match self.pass.symbols.get(&name) {
+            Some(&value) => Ok(value),
+            None if self.lenient => Ok(0),
+            None => Err(format!("undefined symbol {}", name)),
+        }
+    }
+
+    fn skip_spaces(&mut self) {
+        while self
+            .text
+            .get(self.position)
+            .is_some_and(|character| character.is_ascii_whitespace())
+        {
+            self.position += 1;
+        }
+    }
+}
+
+fn is_symbol_character(character: u8) -> bool {
+    character.is_ascii_alphanumeric() || character == b'_'
+}
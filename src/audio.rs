@@ -0,0 +1,246 @@
+// Size in bytes of an XO-CHIP audio pattern (128 one bit samples)
+pub const PATTERN_SIZE: usize = 16;
+const PATTERN_BITS: f64 = (PATTERN_SIZE * 8) as f64;
+
+// Pitch register value at which the pattern is played at BASE_PLAYBACK_RATE bits per second
+pub const DEFAULT_PITCH: u8 = 64;
+const BASE_PLAYBACK_RATE: f64 = 4000.0;
+
+// Square wave played until a program loads its own pattern
+pub const DEFAULT_PATTERN: [u8; PATTERN_SIZE] = [
+    0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF,
+];
+
+/// Plays an XO-CHIP 1-bit audio pattern at the rate selected by the pitch register.
+///
+/// The position inside the pattern is kept between calls to `render`, so consecutive buffers join
+/// without discontinuities no matter how many samples each one has.
+pub struct PatternPlayer {
+    // Position inside the pattern, in bits
+    position: f64,
+}
+
+impl PatternPlayer {
+    pub fn new() -> PatternPlayer {
+        PatternPlayer { position: 0.0 }
+    }
+
+    /// Pattern bits played per second for the given pitch register value:
+    /// 4000 * 2 ^ ((pitch - 64) / 48).
+    pub fn playback_rate(pitch: u8) -> f64 {
+        BASE_PLAYBACK_RATE * 2f64.powf((pitch as f64 - DEFAULT_PITCH as f64) / 48.0)
+    }
+
+    /// Fills `out` with `pattern` played at `pitch`, sampled at `sample_rate` Hz. Set bits are
+    /// rendered as 1.0 and clear bits as -1.0.
+    pub fn render(
+        &mut self,
+        pattern: &[u8; PATTERN_SIZE],
+        pitch: u8,
+        sample_rate: u32,
+        out: &mut [f32],
+    ) {
+        let step = PatternPlayer::playback_rate(pitch) / sample_rate as f64;
+
+        for sample in out.iter_mut() {
+            let bit = self.position as usize;
+            let set = (pattern[bit / 8] >> (7 - bit % 8)) & 0x1 == 1;
+            *sample = if set { 1.0 } else { -1.0 };
+
+            self.position = (self.position + step) % PATTERN_BITS;
+        }
+    }
+
+    /// Restarts the pattern from its first bit.
+    pub fn reset(&mut self) {
+        self.position = 0.0;
+    }
+}
+
+impl Default for PatternPlayer {
+    fn default() -> PatternPlayer {
+        PatternPlayer::new()
+    }
+}
+
+/// Frequency in Hz of the tone played for the sound timer unless configured otherwise.
+pub const DEFAULT_TONE_FREQUENCY: f64 = 440.0;
+
+// Seconds the tone takes to fade in and out, short enough to sound like a sharp edge but long
+// enough to avoid the click of a jump between silence and full amplitude
+const RAMP_SECONDS: f64 = 0.002;
+
+/// Shape of the tone played while the sound timer of a CHIP-8 or SUPER-CHIP program is non-zero.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Waveform {
+    /// The harsh buzz of the original hardware. This is the default.
+    #[default]
+    Square,
+    Sine,
+    Triangle,
+}
+
+impl Waveform {
+    /// Names accepted by `from_name`.
+    pub const NAMES: [&'static str; 3] = ["square", "sine", "triangle"];
+
+    /// Returns the waveform with the given name (see `NAMES`).
+    pub fn from_name(name: &str) -> Option<Waveform> {
+        match name {
+            "square" => Some(Waveform::Square),
+            "sine" => Some(Waveform::Sine),
+            "triangle" => Some(Waveform::Triangle),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Waveform::Square => "square",
+            Waveform::Sine => "sine",
+            Waveform::Triangle => "triangle",
+        }
+    }
+
+    // Amplitude at `phase`, the position in the period from 0.0 to 1.0, starting on a rising edge
+    fn sample(self, phase: f64) -> f64 {
+        match self {
+            Waveform::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Sine => (phase * 2.0 * std::f64::consts::PI).sin(),
+            Waveform::Triangle => {
+                if phase < 0.25 {
+                    phase * 4.0
+                } else if phase < 0.75 {
+                    2.0 - phase * 4.0
+                } else {
+                    phase * 4.0 - 4.0
+                }
+            }
+        }
+    }
+}
+
+/// The tone played for the sound timer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tone {
+    /// Frequency in Hz.
+    pub frequency: f64,
+    pub waveform: Waveform,
+}
+
+impl Default for Tone {
+    fn default() -> Tone {
+        Tone {
+            frequency: DEFAULT_TONE_FREQUENCY,
+            waveform: Waveform::default(),
+        }
+    }
+}
+
+/// Plays a `Tone` that is switched on and off, like the buzzer of the sound timer.
+///
+/// The tone starts at the first sample of the buffer rendered after it is switched on and stops at
+/// the first sample of the buffer rendered after it is switched off, fading in and out over 2 ms
+/// so the edges do not click. Each beep starts at the beginning of a period and the phase is kept
+/// between calls to `render`, so consecutive buffers join without discontinuities.
+pub struct ToneGenerator {
+    // Position inside the period, from 0.0 to 1.0
+    phase: f64,
+
+    // Amplitude of the envelope, from 0.0 (silent) to 1.0
+    level: f64,
+}
+
+impl ToneGenerator {
+    pub fn new() -> ToneGenerator {
+        ToneGenerator {
+            phase: 0.0,
+            level: 0.0,
+        }
+    }
+
+    /// Fills `out` with `tone` sampled at `sample_rate` Hz while `on`, with silence otherwise.
+    pub fn render(&mut self, tone: Tone, on: bool, sample_rate: u32, out: &mut [f32]) {
+        let step = tone.frequency / sample_rate as f64;
+        let ramp = 1.0 / (RAMP_SECONDS * sample_rate as f64);
+        let target = if on { 1.0 } else { 0.0 };
+
+        for sample in out.iter_mut() {
+            if self.level == 0.0 && !on {
+                // Silent, the next beep starts a new period
+                self.phase = 0.0;
+                *sample = 0.0;
+                continue;
+            }
+            self.level = if self.level < target {
+                (self.level + ramp).min(target)
+            } else {
+                (self.level - ramp).max(target)
+            };
+            *sample = (tone.waveform.sample(self.phase) * self.level) as f32;
+            self.phase = (self.phase + step) % 1.0;
+        }
+    }
+
+    /// Stops the tone at once.
+    pub fn reset(&mut self) {
+        self.phase = 0.0;
+        self.level = 0.0;
+    }
+}
+
+impl Default for ToneGenerator {
+    fn default() -> ToneGenerator {
+        ToneGenerator::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Bits 1010 0101, then clear bits
+    const PATTERN: [u8; PATTERN_SIZE] = [
+        0xA5, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00,
+    ];
+
+    #[test]
+    fn playback_rate_doubles_every_48_pitch_steps() {
+        assert_eq!(PatternPlayer::playback_rate(64), 4000.0);
+        assert_eq!(PatternPlayer::playback_rate(112), 8000.0);
+    }
+
+    #[test]
+    fn renders_one_bit_per_sample_at_the_playback_rate() {
+        let mut player = PatternPlayer::new();
+        let mut out = [0.0; 10];
+        player.render(&PATTERN, DEFAULT_PITCH, 4000, &mut out);
+        assert_eq!(
+            out,
+            [1.0, -1.0, 1.0, -1.0, -1.0, 1.0, -1.0, 1.0, -1.0, -1.0]
+        );
+    }
+
+    #[test]
+    fn renders_every_other_bit_at_half_the_playback_rate() {
+        let mut player = PatternPlayer::new();
+        let mut out = [0.0; 6];
+        player.render(&PATTERN, DEFAULT_PITCH, 2000, &mut out);
+        assert_eq!(out, [1.0, 1.0, -1.0, -1.0, -1.0, -1.0]);
+
+        // The position is kept between buffers
+        player.reset();
+        let mut first = [0.0; 3];
+        let mut second = [0.0; 3];
+        player.render(&PATTERN, DEFAULT_PITCH, 2000, &mut first);
+        player.render(&PATTERN, DEFAULT_PITCH, 2000, &mut second);
+        assert_eq!([first, second].concat(), out);
+    }
+}
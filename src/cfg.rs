@@ -0,0 +1,234 @@
+use instruction::Instruction;
+use platform::Platform;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Write};
+
+/// How control reaches a block from the previous one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EdgeKind {
+    /// Falls through to the next instruction, or returns there from a call.
+    Next,
+    Jump,
+    Call,
+    /// Skips the next instruction.
+    Skip,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Edge {
+    pub target: u16,
+    pub kind: EdgeKind,
+}
+
+/// Instructions executed one after the other, entered at `start` only.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BasicBlock {
+    pub start: u16,
+
+    /// Address following the last instruction.
+    pub end: u16,
+
+    pub instructions: Vec<(u16, Instruction)>,
+    pub successors: Vec<Edge>,
+
+    /// Whether the block ends with a `Bnnn` jump, whose target depends on V0.
+    pub unresolved: bool,
+}
+
+/// Control flow graph of a ROM, built by following the execution paths from the load address.
+///
+/// Jumps (`1nnn`), calls (`2nnn`) and the instruction after them, returns (`00EE`) and the skip
+/// instructions (`3xkk`, `4xkk`, `5xy0`, `9xy0`, `Ex9E`, `ExA1`) are followed. `Bnnn` jumps are
+/// computed at run time: the paths they lead to are not followed and the jumps are listed in
+/// `unresolved`. Whatever is not reached, such as sprites, is data.
+pub struct ControlFlowGraph {
+    pub entry: u16,
+    pub blocks: BTreeMap<u16, BasicBlock>,
+
+    /// Every reachable instruction by address.
+    pub instructions: BTreeMap<u16, Instruction>,
+
+    /// Addresses of the `Bnnn` jumps.
+    pub unresolved: Vec<u16>,
+
+    /// Reachable addresses that do not hold an instruction of the platform.
+    pub invalid: Vec<u16>,
+}
+
+impl ControlFlowGraph {
+    pub fn new(rom: &[u8], load_address: u16, platform: Platform) -> ControlFlowGraph {
+        let decode = |address: u16| {
+            let offset = address.wrapping_sub(load_address) as usize;
+            if address < load_address || offset >= rom.len() {
+                return None;
+            }
+            Instruction::decode(&rom[offset..], platform)
+        };
+
+        let mut instructions = BTreeMap::new();
+        let mut successors = BTreeMap::new();
+        let mut unresolved = Vec::new();
+        let mut invalid = BTreeSet::new();
+        let mut pending = vec![load_address];
+        while let Some(address) = pending.pop() {
+            if instructions.contains_key(&address) || invalid.contains(&address) {
+                continue;
+            }
+            let offset = address.wrapping_sub(load_address) as usize;
+            if address < load_address || offset >= rom.len() {
+                // Outside the ROM, like the interpreter routines called by 0nnn
+                continue;
+            }
+            let instruction = match decode(address) {
+                Some(instruction) => instruction,
+                None => {
+                    invalid.insert(address);
+                    continue;
+                }
+            };
+
+            use self::Instruction::*;
+            let next = address.wrapping_add(instruction.size() as u16);
+            let edge = |target, kind| Edge { target, kind };
+            let edges = match instruction {
+                Jump(target) => vec![edge(target, EdgeKind::Jump)],
+                Call(target) => vec![edge(target, EdgeKind::Call), edge(next, EdgeKind::Next)],
+                Return | Exit => Vec::new(),
+                JumpOffset(_) => {
+                    unresolved.push(address);
+                    Vec::new()
+                }
+                SkipEqualByte(..) | SkipNotEqualByte(..) | SkipEqual(..) | SkipNotEqual(..)
+                | SkipKey(_) | SkipNotKey(_) => {
+                    // XO-CHIP skips the whole long instruction
+                    let skipped = decode(next).map_or(2, Instruction::size);
+                    let after = next.wrapping_add(skipped as u16);
+                    vec![edge(next, EdgeKind::Next), edge(after, EdgeKind::Skip)]
+                }
+                _ => vec![edge(next, EdgeKind::Next)],
+            };
+            pending.extend(edges.iter().rev().map(|edge| edge.target));
+            instructions.insert(address, instruction);
+            successors.insert(address, edges);
+        }
+        unresolved.sort_unstable();
+
+        // Blocks start at the entry point, at every target and after every branch
+        let mut leaders: BTreeSet<u16> = successors
+            .values()
+            .filter(|edges: &&Vec<Edge>| edges.len() != 1 || edges[0].kind != EdgeKind::Next)
+            .flat_map(|edges| edges.iter().map(|edge| edge.target))
+            .collect();
+        leaders.insert(load_address);
+
+        let mut blocks = BTreeMap::new();
+        let mut current: Option<BasicBlock> = None;
+        for (&address, &instruction) in &instructions {
+            let mut block = match current.take() {
+                Some(block) if block.end == address && !leaders.contains(&address) => block,
+                previous => {
+                    // A block running into another one falls through to it
+                    if let Some(mut previous) = previous {
+                        previous.successors = vec![Edge {
+                            target: previous.end,
+                            kind: EdgeKind::Next,
+                        }];
+                        blocks.insert(previous.start, previous);
+                    }
+                    BasicBlock {
+                        start: address,
+                        end: address,
+                        instructions: Vec::new(),
+                        successors: Vec::new(),
+                        unresolved: false,
+                    }
+                }
+            };
+            block.instructions.push((address, instruction));
+            block.end = address.wrapping_add(instruction.size() as u16);
+
+            let edges = &successors[&address];
+            let falls_through = edges.len() == 1
+                && edges[0].kind == EdgeKind::Next
+                && instructions.contains_key(&block.end);
+            if falls_through {
+                current = Some(block);
+            } else {
+                block.successors = edges.clone();
+                block.unresolved = matches!(instruction, Instruction::JumpOffset(_));
+                blocks.insert(block.start, block);
+            }
+        }
+        if let Some(block) = current {
+            blocks.insert(block.start, block);
+        }
+
+        ControlFlowGraph {
+            entry: load_address,
+            blocks,
+            instructions,
+            unresolved,
+            invalid: invalid.into_iter().collect(),
+        }
+    }
+
+    /// Whether an instruction reachable from the entry point starts at `address`.
+    pub fn is_code(&self, address: u16) -> bool {
+        self.instructions.contains_key(&address)
+    }
+
+    /// Writes the graph in the Graphviz DOT language, one node per basic block listing its
+    /// instructions. Calls are dashed, skips dotted and blocks ending with an unresolved `Bnnn`
+    /// jump are red. Targets without a block, like jumps outside the ROM or into invalid opcodes,
+    /// get a dashed node with just their address.
+    pub fn write_dot<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(out, "digraph cfg {{")?;
+        writeln!(out, "    node [shape=box, fontname=\"monospace\"];")?;
+        for block in self.blocks.values() {
+            let mut label = String::new();
+            for &(address, instruction) in &block.instructions {
+                label.push_str(&format!("{:#05x}: {}\\l", address, instruction));
+            }
+            let style = if block.unresolved {
+                ", color=red"
+            } else if block.start == self.entry {
+                ", style=bold"
+            } else {
+                ""
+            };
+            writeln!(
+                out,
+                "    \"{:#05x}\" [label=\"{}\"{}];",
+                block.start, label, style
+            )?;
+        }
+        let missing: BTreeSet<u16> = self
+            .blocks
+            .values()
+            .flat_map(|block| block.successors.iter().map(|edge| edge.target))
+            .filter(|target| !self.blocks.contains_key(target))
+            .collect();
+        for target in missing {
+            writeln!(
+                out,
+                "    \"{:#05x}\" [label=\"{:#05x}: no code\", style=dashed];",
+                target, target
+            )?;
+        }
+        for block in self.blocks.values() {
+            for edge in &block.successors {
+                let style = match edge.kind {
+                    EdgeKind::Next | EdgeKind::Jump => "",
+                    EdgeKind::Call => " [style=dashed]",
+                    EdgeKind::Skip => " [style=dotted]",
+                };
+                writeln!(
+                    out,
+                    "    \"{:#05x}\" -> \"{:#05x}\"{};",
+                    block.start, edge.target, style
+                )?;
+            }
+        }
+        writeln!(out, "}}")
+    }
+}
@@ -1,12 +1,29 @@
-use rand::random;
+use audio::{DEFAULT_PATTERN, DEFAULT_PITCH, PATTERN_SIZE};
+use error::Chip8Error;
+use hash::fnv1a;
+use instruction::Instruction;
+use platform::Platform;
+use profiler::Profile;
+use quirks::Quirks;
+use rng::{RandomSource, ThreadRandom};
+use screen::Screen;
 use std::fs::File;
-use std::io::Error;
 use std::io::Read;
+use timing::{vip_cycles, TimingMode};
+use trace::{TraceEntry, TraceFilter};
+use watchpoint::{Access, WatchHit, Watchpoint};
 
 // VF
 const FLAG_REGISTER: usize = 15;
-const BYTES_WIDTH: u8 = 8;
-const BYTES_HEIGHT: u8 = 32;
+
+/// Where ROMs are loaded and execution starts.
+pub const DEFAULT_LOAD_ADDRESS: u16 = 0x200;
+
+/// Load address of programs written for the ETI-660.
+pub const ETI_660_LOAD_ADDRESS: u16 = 0x600;
+
+// Where the 10 byte high resolution digits start, right after the small font
+const BIG_FONTSET_ADDRESS: usize = 0x50;
 
 const FONTSET: [u8; 80] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
@@ -27,93 +44,275 @@ const FONTSET: [u8; 80] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
 
+const BIG_FONTSET: [u8; 160] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
+// End of the fonts, the first address after the big digits
+pub(crate) const FONT_AREA_END: u16 = (BIG_FONTSET_ADDRESS + BIG_FONTSET.len()) as u16;
+
 pub enum MachineState {
     SuccessfulExecution,
-    Draw(Box<[[u8; 8]; 32]>),
+    Draw(Box<Screen>),
     WaitForKeyboard,
+    Exit,
+    // Machine::run_frame_until stopped before executing the instruction at PC
+    Break,
 }
 
 // Represnts CHIP-8 current state
 pub struct State {
-    // Main memory
-    memory: [u8; 4096],
+    // Main memory, its size depends on the platform
+    pub(crate) memory: Vec<u8>,
 
     // CPU Registers
-    registers: [u8; 16],
+    pub(crate) registers: [u8; 16],
 
     // Call Stack
-    stack: [u16; 16],
+    pub(crate) stack: [u16; 16],
 
     // Index register
-    index: u16,
+    pub(crate) index: u16,
 
     // Program counter
-    pc: u16,
+    pub(crate) pc: u16,
+
+    // Where the ROM was loaded and its hash, identifying the program in save states
+    pub(crate) load_address: u16,
+    pub(crate) rom_hash: u64,
+
+    // Address and opcode of the instruction being executed
+    pub(crate) instruction_pc: u16,
+    pub(crate) opcode: u16,
 
     // Stack pointer
-    sp: usize,
+    pub(crate) sp: usize,
 
     // Screen bitmap
-    screen: [[u8; 8]; 32], // 32 rows of 8 u8 (64bits)
+    pub(crate) screen: Screen,
 
     // Keypad
-    keypad: u16,
+    pub(crate) keypad: u16,
 
     // Register where we are saving pressed key after WaitForKeyboard MachineSate
-    register_pressed_key: u8,
+    pub(crate) register_pressed_key: u8,
+
+    // Set by Fx0A, cleared when the pressed key is delivered through wait_key_press
+    pub(crate) waiting_key: bool,
 
     // Timers
-    delay_timer: u8,
-    sound_timer: u8,
+    pub(crate) delay_timer: u8,
+    pub(crate) sound_timer: u8,
+
+    // XO-CHIP audio pattern (F002) and playback pitch (Fx3A)
+    pub(crate) audio_pattern: [u8; PATTERN_SIZE],
+    pub(crate) pitch: u8,
+
+    // SUPER-CHIP RPL user flags (Fx75/Fx85)
+    pub(crate) rpl_flags: [u8; 16],
+
+    // Behaviour of the ambiguous opcodes
+    pub(crate) quirks: Quirks,
+
+    // Emulated instruction set
+    pub(crate) platform: Platform,
+
+    // Random numbers for Cxkk
+    pub(crate) rng: Box<dyn RandomSource>,
+
+    // Timing model and machine cycles spent so far under it
+    pub(crate) timing: TimingMode,
+    pub(crate) cycles: u64,
+
+    // Watched memory ranges and the accesses that triggered them since they were last taken
+    pub(crate) watchpoints: Vec<Watchpoint>,
+    pub(crate) watch_hits: Vec<WatchHit>,
+
+    // Instructions executed so far, numbering the trace entries
+    pub(crate) executed: u64,
+
+    // Instructions recorded while tracing is enabled, since they were last taken
+    pub(crate) trace_filter: Option<TraceFilter>,
+    pub(crate) trace: Vec<TraceEntry>,
+
+    // Executions counted while profiling
+    pub(crate) profile: Option<Profile>,
 }
 
 impl State {
-    pub fn new(filename: String) -> Result<State, Error> {
-        let mut memory: [u8; 4096] = [0x0; 4096];
+    pub fn new(filename: String, platform: Platform) -> Result<State, Chip8Error> {
+        State::from_reader(File::open(filename)?, platform, DEFAULT_LOAD_ADDRESS)
+    }
+
+    // Reads a whole ROM from any reader and loads it at load_address
+    pub fn from_reader<R: Read>(
+        mut reader: R,
+        platform: Platform,
+        load_address: u16,
+    ) -> Result<State, Chip8Error> {
+        let mut buffer = Vec::new();
+        reader.read_to_end(&mut buffer)?;
+        State::from_bytes(&buffer, platform, load_address)
+    }
+
+    // Loads the ROM at load_address, execution starts there
+    pub fn from_bytes(
+        rom: &[u8],
+        platform: Platform,
+        load_address: u16,
+    ) -> Result<State, Chip8Error> {
+        let mut memory: Vec<u8> = vec![0x0; platform.memory_size()];
 
         // Load the FONTSET
         memory[..0x50].copy_from_slice(&FONTSET[..0x50]);
+        memory[BIG_FONTSET_ADDRESS..(BIG_FONTSET_ADDRESS + BIG_FONTSET.len())]
+            .copy_from_slice(&BIG_FONTSET);
 
-        // Read CHIP-8 File
         // Allocate the rom in memory
-        let mut file = File::open(filename)?;
-        let mut buffer = Vec::new();
-        file.read_to_end(&mut buffer)?;
-        memory[512..(buffer.len() + 512)].copy_from_slice(&buffer[..]);
+        let start = load_address as usize;
+        let max = memory.len().saturating_sub(start);
+        if rom.len() > max {
+            return Err(Chip8Error::RomTooLarge {
+                size: rom.len(),
+                max,
+            });
+        }
+        memory[start..(start + rom.len())].copy_from_slice(rom);
 
         Ok(State {
-            pc: 0x200,
+            pc: load_address,
+            load_address,
+            rom_hash: fnv1a(rom),
+            instruction_pc: load_address,
+            opcode: 0x0,
             index: 0x0,
             sp: 0x0,
             registers: [0x0; 16],
             memory,
             stack: [0x0; 16],
-            screen: [[0x0; 8]; 32],
+            screen: Screen::new(),
             keypad: 0x0,
             register_pressed_key: 0x0,
+            waiting_key: false,
             delay_timer: 0x0,
             sound_timer: 0x0,
+            audio_pattern: DEFAULT_PATTERN,
+            pitch: DEFAULT_PITCH,
+            rpl_flags: [0x0; 16],
+            quirks: Quirks::default(),
+            platform,
+            rng: Box::new(ThreadRandom),
+            timing: TimingMode::default(),
+            cycles: 0,
+            watchpoints: Vec::new(),
+            watch_hits: Vec::new(),
+            executed: 0,
+            trace_filter: None,
+            trace: Vec::new(),
+            profile: None,
         })
     }
 
-    pub fn execute_instruction(&mut self) -> Result<MachineState, String> {
-        //self.print_registers();
+    pub fn execute_instruction(&mut self) -> Result<MachineState, Chip8Error> {
+        // Fx0A halts execution until a key is delivered
+        if self.waiting_key {
+            return Ok(MachineState::WaitForKeyboard);
+        }
+
         let opcode: u16 = self.get_opcode()?;
-        self.pc += 2;
+        self.instruction_pc = self.pc;
+        self.opcode = opcode;
+        self.record_trace();
+        if let Some(ref mut profile) = self.profile {
+            profile.record(self.instruction_pc, opcode, &self.memory, self.platform);
+        }
+        self.executed += 1;
+        self.pc = self.pc.wrapping_add(2);
+
+        if self.timing == TimingMode::CosmacVip {
+            self.cycles += vip_cycles(opcode);
+        }
 
         match self.break_opcode(opcode) {
             // 00E0 - CLS
             // Clear the display.
             (0x0, 0x0, 0xE, 0x0) => {
-                self.screen = [[0x0; 8]; 32];
+                self.screen.clear();
                 Ok(MachineState::SuccessfulExecution)
             }
 
+            // 00Cn - SCD nibble (SUPER-CHIP)
+            // Scroll display n lines down.
+            (0x0, 0x0, 0xC, n) if self.platform.has_super_chip() => {
+                self.screen.scroll_down(n as usize);
+                Ok(MachineState::Draw(Box::new(self.screen)))
+            }
+
+            // 00FB - SCR (SUPER-CHIP)
+            // Scroll display 4 pixels right.
+            (0x0, 0x0, 0xF, 0xB) if self.platform.has_super_chip() => {
+                self.screen.scroll_right(4);
+                Ok(MachineState::Draw(Box::new(self.screen)))
+            }
+
+            // 00FC - SCL (SUPER-CHIP)
+            // Scroll display 4 pixels left.
+            (0x0, 0x0, 0xF, 0xC) if self.platform.has_super_chip() => {
+                self.screen.scroll_left(4);
+                Ok(MachineState::Draw(Box::new(self.screen)))
+            }
+
+            // 00FD - EXIT (SUPER-CHIP)
+            // Exit the interpreter.
+            (0x0, 0x0, 0xF, 0xD) if self.platform.has_super_chip() => Ok(MachineState::Exit),
+
+            // 00FE - LOW (SUPER-CHIP)
+            // Disable high resolution graphic mode (64x32). The screen is cleared.
+            (0x0, 0x0, 0xF, 0xE) if self.platform.has_super_chip() => {
+                self.screen.set_hires(false);
+                Ok(MachineState::Draw(Box::new(self.screen)))
+            }
+
+            // 00Dn - SCU nibble (XO-CHIP)
+            // Scroll display n lines up.
+            (0x0, 0x0, 0xD, n) if self.platform.has_xo_chip() => {
+                self.screen.scroll_up(n as usize);
+                Ok(MachineState::Draw(Box::new(self.screen)))
+            }
+
+            // 00FF - HIGH (SUPER-CHIP)
+            // Enable high resolution graphic mode (128x64). The screen is cleared.
+            (0x0, 0x0, 0xF, 0xF) if self.platform.has_super_chip() => {
+                self.screen.set_hires(true);
+                Ok(MachineState::Draw(Box::new(self.screen)))
+            }
+
             // 00EE - RET
             // Return from a subroutine.
             // The interpreter sets the program counter to the address at the top of the stack, then subtracts 1
             // from the stack pointer.
             (0x0, 0x0, 0xE, 0xE) => {
+                if self.sp == 0 {
+                    return Err(Chip8Error::StackUnderflow {
+                        pc: self.instruction_pc,
+                        opcode,
+                    });
+                }
                 self.sp -= 1;
                 self.pc = self.stack[self.sp];
                 Ok(MachineState::SuccessfulExecution)
@@ -133,6 +332,12 @@ impl State {
             // The interpreter increments the stack pointer, then puts the current PC on the top of the stack. The PC
             // is then set to nnn.
             (0x2, _, _, _) => {
+                if self.sp == self.stack.len() {
+                    return Err(Chip8Error::StackOverflow {
+                        pc: self.instruction_pc,
+                        opcode,
+                    });
+                }
                 self.stack[self.sp] = self.pc;
                 self.sp += 1;
                 let address: u16 = opcode & 0x0FFF;
@@ -146,7 +351,7 @@ impl State {
             (0x3, r, _, _) => {
                 let kk: u8 = (opcode & 0x00FF) as u8;
                 if self.registers[r as usize] == kk {
-                    self.pc += 2;
+                    self.skip_next_instruction();
                 }
                 Ok(MachineState::SuccessfulExecution)
             }
@@ -157,7 +362,7 @@ impl State {
             (0x4, r, _, _) => {
                 let kk: u8 = (opcode & 0x00FF) as u8;
                 if self.registers[r as usize] != kk {
-                    self.pc += 2;
+                    self.skip_next_instruction();
                 }
                 Ok(MachineState::SuccessfulExecution)
             }
@@ -167,7 +372,31 @@ impl State {
             // The interpreter compares register Vx to register Vy, and if they are equal, increments the program counter by 2.
             (0x5, r1, r2, 0x0) => {
                 if self.registers[r1 as usize] == self.registers[r2 as usize] {
-                    self.pc += 2;
+                    self.skip_next_instruction();
+                }
+                Ok(MachineState::SuccessfulExecution)
+            }
+
+            // 5xy2 - SAVE Vx - Vy (XO-CHIP)
+            // Store registers Vx through Vy inclusive in memory starting at location I. If x > y they are stored in
+            // reverse order. I is not modified.
+            (0x5, x, y, 0x2) if self.platform.has_xo_chip() => {
+                self.check_memory_range(self.index as usize, x.abs_diff(y) as usize + 1)?;
+                for (offset, register) in Self::register_range(x, y).enumerate() {
+                    let address = self.index as usize + offset;
+                    self.write_memory(address, self.registers[register])?;
+                }
+                Ok(MachineState::SuccessfulExecution)
+            }
+
+            // 5xy3 - LOAD Vx - Vy (XO-CHIP)
+            // Read registers Vx through Vy inclusive from memory starting at location I. If x > y they are read in
+            // reverse order. I is not modified.
+            (0x5, x, y, 0x3) if self.platform.has_xo_chip() => {
+                self.check_memory_range(self.index as usize, x.abs_diff(y) as usize + 1)?;
+                for (offset, register) in Self::register_range(x, y).enumerate() {
+                    let address = self.index as usize + offset;
+                    self.registers[register] = self.read_memory(address)?;
                 }
                 Ok(MachineState::SuccessfulExecution)
             }
@@ -204,6 +433,9 @@ impl State {
             // Performs a bitwise OR on the values of Vx and Vy, then stores the result in Vx.
             (0x8, x, y, 0x1) => {
                 self.registers[x as usize] |= self.registers[y as usize];
+                if self.quirks.vf_reset {
+                    self.registers[FLAG_REGISTER] = 0;
+                }
                 Ok(MachineState::SuccessfulExecution)
             }
 
@@ -212,6 +444,9 @@ impl State {
             // Performs a bitwise AND on the values of Vx and Vy, then stores the result in Vx.
             (0x8, x, y, 0x2) => {
                 self.registers[x as usize] &= self.registers[y as usize];
+                if self.quirks.vf_reset {
+                    self.registers[FLAG_REGISTER] = 0;
+                }
                 Ok(MachineState::SuccessfulExecution)
             }
 
@@ -220,6 +455,9 @@ impl State {
             // Performs a bitwise exclusive OR on the values of Vx and Vy, then stores the result in Vx.
             (0x8, x, y, 0x3) => {
                 self.registers[x as usize] ^= self.registers[y as usize];
+                if self.quirks.vf_reset {
+                    self.registers[FLAG_REGISTER] = 0;
+                }
                 Ok(MachineState::SuccessfulExecution)
             }
 
@@ -237,49 +475,51 @@ impl State {
 
             // 8xy5 - SUB Vx, Vy
             // Set Vx = Vx - Vy, set VF = NOT borrow.
-            // If Vx > Vy, then VF is set to 1, otherwise 0. Then Vy is subtracted from Vx, and the results stored in Vx.
+            // If Vx >= Vy, then VF is set to 1, otherwise 0. Then Vy is subtracted from Vx, wrapping around, and the results stored in Vx.
             (0x8, x, y, 0x5) => {
-                if self.registers[x as usize] > self.registers[y as usize] {
-                    self.registers[FLAG_REGISTER] = 1;
-                    self.registers[x as usize] -= self.registers[y as usize]
-                } else {
-                    self.registers[FLAG_REGISTER] = 0;
-                    self.registers[x as usize] =
-                        self.registers[y as usize] - self.registers[x as usize];
-                }
+                let (vx, vy) = (self.registers[x as usize], self.registers[y as usize]);
+                self.registers[x as usize] = vx.wrapping_sub(vy);
+                self.registers[FLAG_REGISTER] = u8::from(vx >= vy);
                 Ok(MachineState::SuccessfulExecution)
             }
 
             // 8xy6 - SHR Vx {, Vy}
             // Set Vx = Vx SHR 1.
             // If the least-significant bit of Vx is 1, then VF is set to 1, otherwise 0. Then Vx is divided by 2.
-            (0x8, x, _, 0x6) => {
-                self.registers[FLAG_REGISTER] = self.registers[x as usize] & 0x1;
-                self.registers[x as usize] >>= 1;
+            // Without the shift quirk Vy is shifted instead and the result stored in Vx.
+            (0x8, x, y, 0x6) => {
+                let value = if self.quirks.shift {
+                    self.registers[x as usize]
+                } else {
+                    self.registers[y as usize]
+                };
+                self.registers[x as usize] = value >> 1;
+                self.registers[FLAG_REGISTER] = value & 0x1;
                 Ok(MachineState::SuccessfulExecution)
             }
 
             // 8xy7 - SUBN Vx, Vy
             // Set Vx = Vy - Vx, set VF = NOT borrow.
-            // If Vy > Vx, then VF is set to 1, otherwise 0. Then Vx is subtracted from Vy, and the results stored in Vx.
+            // If Vy >= Vx, then VF is set to 1, otherwise 0. Then Vx is subtracted from Vy, wrapping around, and the results stored in Vx.
             (0x8, x, y, 0x7) => {
-                if self.registers[y as usize] > self.registers[x as usize] {
-                    self.registers[FLAG_REGISTER] = 1;
-                    self.registers[x as usize] =
-                        self.registers[y as usize] - self.registers[x as usize];
-                } else {
-                    self.registers[FLAG_REGISTER] = 0;
-                    self.registers[x as usize] -= self.registers[y as usize];
-                }
+                let (vx, vy) = (self.registers[x as usize], self.registers[y as usize]);
+                self.registers[x as usize] = vy.wrapping_sub(vx);
+                self.registers[FLAG_REGISTER] = u8::from(vy >= vx);
                 Ok(MachineState::SuccessfulExecution)
             }
 
             //8xyE - SHL Vx {, Vy}
             // Set Vx = Vx SHL 1.
             // If the most-significant bit of Vx is 1, then VF is set to 1, otherwise to 0. Then Vx is multiplied by 2.
-            (0x8, x, _, 0xE) => {
-                self.registers[FLAG_REGISTER] = self.registers[x as usize] >> 7;
-                self.registers[x as usize] <<= 1;
+            // Without the shift quirk Vy is shifted instead and the result stored in Vx.
+            (0x8, x, y, 0xE) => {
+                let value = if self.quirks.shift {
+                    self.registers[x as usize]
+                } else {
+                    self.registers[y as usize]
+                };
+                self.registers[x as usize] = value << 1;
+                self.registers[FLAG_REGISTER] = value >> 7;
                 Ok(MachineState::SuccessfulExecution)
             }
 
@@ -288,7 +528,7 @@ impl State {
             // The values of Vx and Vy are compared, and if they are not equal, the program counter is increased by 2.
             (0x9, x, y, 0x0) => {
                 if self.registers[x as usize] != self.registers[y as usize] {
-                    self.pc += 2;
+                    self.skip_next_instruction();
                 }
                 Ok(MachineState::SuccessfulExecution)
             }
@@ -303,9 +543,11 @@ impl State {
             // Bnnn - JP V0, addr
             // Jump to location nnn + V0.
             // The program counter is set to nnn plus the value of V0.
-            (0xB, _, _, _) => {
+            // With the jump quirk the instruction is read as Bxnn and jumps to xnn plus Vx.
+            (0xB, x, _, _) => {
                 let address: u16 = opcode & 0x0FFF;
-                self.pc = address + self.registers[0b0_usize] as u16;
+                let register: usize = if self.quirks.jump { x as usize } else { 0 };
+                self.pc = address + self.registers[register] as u16;
                 Ok(MachineState::SuccessfulExecution)
             }
 
@@ -314,7 +556,7 @@ impl State {
             // from 0 to 255, which is then ANDed with the value kk. The results are stored in Vx
             (0xC, r, _, _) => {
                 let number: u8 = (opcode & 0x00FF) as u8;
-                let random_number = random::<u8>();
+                let random_number = self.rng.next_byte(&self.memory);
                 self.registers[r as usize] = number & random_number;
                 Ok(MachineState::SuccessfulExecution)
             }
@@ -324,38 +566,52 @@ impl State {
             // The interpreter reads n bytes from memory, starting at the address stored in I. These bytes are then displayed
             // as sprites on screen at coordinates (Vx, Vy). Sprites are XORed onto the existing screen. If this causes any
             // pixels to be erased, VF is set to 1, otherwise it is set to 0. If the sprite is positioned so part of it is
-            // outside the coordinates of the display, it wraps around to the opposite side of the screen.
+            // outside the coordinates of the display, it wraps around to the opposite side of the screen (or it is
+            // clipped with the clipping quirk).
+            // Dxy0 (SUPER-CHIP) draws a 16x16 sprite made of 32 bytes, two per row.
+            // On XO-CHIP the sprite is drawn on every selected plane, the data for each plane follows the previous one.
             (0xD, x, y, n) => {
-                // We use mod BYTES_(WIDTH|HEIGHT) to wrap around
-                let v_x: usize =
-                    ((self.registers[x as usize] / BYTES_WIDTH) % BYTES_WIDTH) as usize;
-                let initial_v_y: u8 = self.registers[y as usize];
-                let reminder: u8 = self.registers[x as usize] % BYTES_WIDTH;
+                // The starting position always wraps around
+                let v_x: usize = self.registers[x as usize] as usize % self.screen.width();
+                let v_y: usize = self.registers[y as usize] as usize % self.screen.height();
+                let (rows, width): (usize, usize) = if n == 0 && self.platform.has_super_chip() {
+                    (16, 16)
+                } else {
+                    (n as usize, 8)
+                };
+                let sprite_size: usize = rows * width / 8;
 
                 // Reset flag regiter (collision check)
                 self.registers[FLAG_REGISTER] = 0;
 
-                for i in 0..n {
-                    let sprite = self.memory[(self.index + i as u16) as usize];
-                    let v_y: usize = ((initial_v_y + i) % BYTES_HEIGHT) as usize;
+                for (plane_number, plane) in self.screen.selected().into_iter().enumerate() {
+                    let sprite_address = self.index as usize + plane_number * sprite_size;
 
-                    // Write left screen part
-                    let sprite_left = sprite >> reminder;
-                    let left_screen_part = self.screen[v_y][v_x];
-                    if left_screen_part & sprite_left > 0 {
-                        self.registers[FLAG_REGISTER] = 1
-                    }
-                    self.screen[v_y][v_x] ^= sprite_left;
-
-                    // Write right screen part
-                    if reminder != 0 {
-                        let v_x_right = ((v_x + 1) as u8 % BYTES_WIDTH) as usize;
-                        let sprite_right = sprite << (8 - reminder);
-                        let right_screen_part = self.screen[v_y][v_x_right];
-                        if right_screen_part & sprite_right > 0 {
-                            self.registers[FLAG_REGISTER] = 1
+                    for i in 0..rows {
+                        let mut row_y = v_y + i;
+                        if row_y >= self.screen.height() {
+                            if self.quirks.clipping {
+                                break;
+                            }
+                            row_y %= self.screen.height();
+                        }
+
+                        let sprite: u16 = if width == 16 {
+                            self.read_word(sprite_address + i * 2)?
+                        } else {
+                            self.read_memory(sprite_address + i)? as u16
+                        };
+
+                        if self.screen.draw_sprite_row(
+                            plane,
+                            v_x,
+                            row_y,
+                            sprite,
+                            width,
+                            self.quirks.clipping,
+                        ) {
+                            self.registers[FLAG_REGISTER] = 1;
                         }
-                        self.screen[v_y][v_x_right] ^= sprite_right;
                     }
                 }
 
@@ -368,7 +624,7 @@ impl State {
             // PC is increased by 2.
             (0xE, x, 0x9, 0xE) => {
                 if (self.keypad >> self.registers[x as usize]) & 0x1 == 1 {
-                    self.pc += 2;
+                    self.skip_next_instruction();
                 }
                 Ok(MachineState::SuccessfulExecution)
             }
@@ -379,7 +635,31 @@ impl State {
             // PC is increased by 2.
             (0xE, x, 0xA, 0x1) => {
                 if (self.keypad >> self.registers[x as usize]) & 0x1 != 1 {
-                    self.pc += 2;
+                    self.skip_next_instruction();
+                }
+                Ok(MachineState::SuccessfulExecution)
+            }
+
+            // F000 nnnn - LD I, long (XO-CHIP)
+            // Set I = nnnn, the 16 bit address stored in the two bytes following the instruction.
+            (0xF, 0x0, 0x0, 0x0) if self.platform.has_xo_chip() => {
+                self.index = self.fetch_word(self.pc as usize)?;
+                self.pc = self.pc.wrapping_add(2);
+                Ok(MachineState::SuccessfulExecution)
+            }
+
+            // Fn01 - PLANE n (XO-CHIP)
+            // Select the drawing planes with the bitmask n.
+            (0xF, n, 0x0, 0x1) if self.platform.has_xo_chip() => {
+                self.screen.select_planes(n);
+                Ok(MachineState::SuccessfulExecution)
+            }
+
+            // F002 - AUDIO (XO-CHIP)
+            // Load the 16 byte audio pattern starting at location I.
+            (0xF, 0x0, 0x0, 0x2) if self.platform.has_xo_chip() => {
+                for i in 0..PATTERN_SIZE {
+                    self.audio_pattern[i] = self.read_memory(self.index as usize + i)?;
                 }
                 Ok(MachineState::SuccessfulExecution)
             }
@@ -397,6 +677,7 @@ impl State {
             // All execution stops until a key is pressed, then the value of that key is stored in Vx.
             (0xF, x, 0x0, 0xA) => {
                 self.register_pressed_key = x;
+                self.waiting_key = true;
                 Ok(MachineState::WaitForKeyboard)
             }
 
@@ -420,7 +701,7 @@ impl State {
             // Set I = I + Vx.
             // The values of I and Vx are added, and the results are stored in I.
             (0xF, x, 0x1, 0xE) => {
-                self.index += self.registers[x as usize] as u16;
+                self.index = self.index.wrapping_add(self.registers[x as usize] as u16);
                 Ok(MachineState::SuccessfulExecution)
             }
 
@@ -428,7 +709,15 @@ impl State {
             // Set I = location of sprite for digit Vx.
             // The value of I is set to the location for the hexadecimal sprite corresponding to the value of Vx.
             (0xF, x, 0x2, 0x9) => {
-                self.index = (self.registers[x as usize] * 5).into();
+                self.index = (self.registers[x as usize] & 0xF) as u16 * 5;
+                Ok(MachineState::SuccessfulExecution)
+            }
+
+            // Fx30 - LD HF, Vx (SUPER-CHIP)
+            // Set I = location of the 10 byte high resolution sprite for digit Vx.
+            (0xF, x, 0x3, 0x0) if self.platform.has_super_chip() => {
+                self.index =
+                    (BIG_FONTSET_ADDRESS + (self.registers[x as usize] & 0xF) as usize * 10) as u16;
                 Ok(MachineState::SuccessfulExecution)
             }
 
@@ -437,49 +726,75 @@ impl State {
             // The interpreter takes the decimal value of Vx, and places the hundreds digit in memory at location in I,
             // the tens digit at location I+1, and the ones digit at location I+2.
             (0xF, x, 0x3, 0x3) => {
-                self.memory[self.index as usize] = self.registers[x as usize] / 100;
-                self.memory[(self.index + 1) as usize] = (self.registers[x as usize] % 100) / 10;
-                self.memory[(self.index + 2) as usize] = self.registers[x as usize] % 10;
+                let value = self.registers[x as usize];
+                let address = self.index as usize;
+                self.check_memory_range(address, 3)?;
+                self.write_memory(address, value / 100)?;
+                self.write_memory(address + 1, (value % 100) / 10)?;
+                self.write_memory(address + 2, value % 10)?;
                 Ok(MachineState::SuccessfulExecution)
             }
 
             // Fx55 - LD [I], Vx
             // Store registers V0 through Vx inclusive in memory starting at location I.
             // The interpreter copies the values of registers V0 through Vx into memory, starting at the address in I.
-            // I is set to I + X + 1 after operation -> Maybe not
+            // Depending on the load/store quirk I is left unchanged or set to I + X or I + X + 1 after operation.
             (0xF, x, 0x5, 0x5) => {
+                self.check_memory_range(self.index as usize, x as usize + 1)?;
                 for i in 0..(x + 1) {
-                    let index: usize = (self.index + i as u16) as usize;
-                    self.memory[(index)] = self.registers[i as usize];
+                    let index: usize = self.index as usize + i as usize;
+                    self.write_memory(index, self.registers[i as usize])?;
                 }
-                //self.index += (x + 1) as u16;
+                self.index = self.index.wrapping_add(self.quirks.load_store.amount(x));
                 Ok(MachineState::SuccessfulExecution)
             }
 
             // Fx65 - LD Vx, [I]
             // Read registers V0 through Vx incluse from memory starting at location I.
             // The interpreter reads values from memory starting at location I into registers V0 through Vx.
+            // Depending on the load/store quirk I is left unchanged or set to I + X or I + X + 1 after operation.
             (0xF, x, 0x6, 0x5) => {
+                self.check_memory_range(self.index as usize, x as usize + 1)?;
                 for i in 0..(x + 1) {
-                    self.registers[i as usize] = self.memory[(self.index + i as u16) as usize];
+                    self.registers[i as usize] =
+                        self.read_memory(self.index as usize + i as usize)?;
                 }
-                //self.index += (x + 1) as u16;
+                self.index = self.index.wrapping_add(self.quirks.load_store.amount(x));
                 Ok(MachineState::SuccessfulExecution)
             }
 
-            // Invalid opcodes
-            _ => {
-                let error_message: String = format!(
-                    "Critical error: attempted to execute {:x} (invalid opcode)",
-                    opcode
-                );
-                Err(error_message)
+            // Fx3A - PITCH Vx (XO-CHIP)
+            // Set the audio pattern playback pitch = Vx.
+            (0xF, x, 0x3, 0xA) if self.platform.has_xo_chip() => {
+                self.pitch = self.registers[x as usize];
+                Ok(MachineState::SuccessfulExecution)
+            }
+
+            // Fx75 - LD R, Vx (SUPER-CHIP)
+            // Store V0 through Vx in the RPL user flags.
+            (0xF, x, 0x7, 0x5) if self.platform.has_super_chip() => {
+                self.rpl_flags[..=x as usize].copy_from_slice(&self.registers[..=x as usize]);
+                Ok(MachineState::SuccessfulExecution)
             }
+
+            // Fx85 - LD Vx, R (SUPER-CHIP)
+            // Read V0 through Vx from the RPL user flags.
+            (0xF, x, 0x8, 0x5) if self.platform.has_super_chip() => {
+                self.registers[..=x as usize].copy_from_slice(&self.rpl_flags[..=x as usize]);
+                Ok(MachineState::SuccessfulExecution)
+            }
+
+            // Invalid opcodes
+            _ => Err(Chip8Error::InvalidOpcode {
+                pc: self.instruction_pc,
+                opcode,
+            }),
         }
     }
 
     pub fn wait_key_press(&mut self, key: u8) {
         self.registers[self.register_pressed_key as usize] = key;
+        self.waiting_key = false;
     }
 
     pub fn set_keys_pressed(&mut self, keys: u16) {
@@ -498,12 +813,119 @@ impl State {
         }
     }
 
-    fn get_opcode(&mut self) -> Result<u16, String> {
+    fn get_opcode(&mut self) -> Result<u16, Chip8Error> {
+        if self.pc as usize + 1 >= self.memory.len() {
+            return Err(Chip8Error::PcOutOfBounds { pc: self.pc });
+        }
         let opcode: u16 = (self.memory[self.pc as usize] as u16) << 0x8
-            | (self.memory[(self.pc + 1) as usize] as u16);
+            | (self.memory[self.pc as usize + 1] as u16);
         Ok(opcode)
     }
 
+    // Data accesses made by instructions, checked against the watchpoints
+    fn read_memory(&mut self, address: usize) -> Result<u8, Chip8Error> {
+        match self.memory.get(address) {
+            Some(&value) => {
+                self.watch(address, Access::Read, value, value);
+                Ok(value)
+            }
+            None => Err(self.memory_out_of_bounds(address)),
+        }
+    }
+
+    fn write_memory(&mut self, address: usize, value: u8) -> Result<(), Chip8Error> {
+        if address >= self.memory.len() {
+            return Err(self.memory_out_of_bounds(address));
+        }
+        let old = self.memory[address];
+        self.memory[address] = value;
+        self.watch(address, Access::Write, old, value);
+        Ok(())
+    }
+
+    fn read_word(&mut self, address: usize) -> Result<u16, Chip8Error> {
+        Ok((self.read_memory(address)? as u16) << 0x8 | (self.read_memory(address + 1)? as u16))
+    }
+
+    // Reads a word of the program itself, which is not watched
+    fn fetch_word(&self, address: usize) -> Result<u16, Chip8Error> {
+        match (self.memory.get(address), self.memory.get(address + 1)) {
+            (Some(&high), Some(&low)) => Ok((high as u16) << 0x8 | low as u16),
+            _ => Err(self.memory_out_of_bounds(address + 1)),
+        }
+    }
+
+    fn watch(&mut self, address: usize, access: Access, old: u8, new: u8) {
+        for watchpoint in self.watchpoints.iter() {
+            if watchpoint.matches(address, access) {
+                self.watch_hits.push(WatchHit {
+                    id: watchpoint.id,
+                    pc: self.instruction_pc,
+                    opcode: self.opcode,
+                    address,
+                    access,
+                    old,
+                    new,
+                });
+            }
+        }
+    }
+
+    // Records the instruction about to be executed, with the state before it
+    fn record_trace(&mut self) {
+        match self.trace_filter {
+            Some(ref filter) if filter.matches(self.instruction_pc, self.opcode) => {}
+            _ => return,
+        }
+        let pc = self.instruction_pc as usize;
+        let end = (pc + 4).min(self.memory.len());
+        self.trace.push(TraceEntry {
+            cycle: self.executed,
+            pc: self.instruction_pc,
+            opcode: self.opcode,
+            instruction: Instruction::decode(&self.memory[pc..end], self.platform),
+            registers: self.registers,
+            index: self.index,
+            sp: self.sp,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+        });
+    }
+
+    // Fails before instructions accessing several bytes change anything when some are out of memory
+    fn check_memory_range(&self, address: usize, len: usize) -> Result<(), Chip8Error> {
+        if address + len > self.memory.len() {
+            return Err(self.memory_out_of_bounds(address.max(self.memory.len())));
+        }
+        Ok(())
+    }
+
+    fn memory_out_of_bounds(&self, address: usize) -> Chip8Error {
+        Chip8Error::MemoryOutOfBounds {
+            pc: self.instruction_pc,
+            opcode: self.opcode,
+            address,
+        }
+    }
+
+    // Skips the instruction at PC. On XO-CHIP the 4 byte F000 nnnn instruction is skipped as a whole.
+    fn skip_next_instruction(&mut self) {
+        if self.platform.has_xo_chip() && self.fetch_word(self.pc as usize).ok() == Some(0xF000) {
+            self.pc = self.pc.wrapping_add(4);
+        } else {
+            self.pc = self.pc.wrapping_add(2);
+        }
+    }
+
+    // Registers from x to y inclusive, in reverse order if x > y
+    fn register_range(x: u8, y: u8) -> Box<dyn Iterator<Item = usize>> {
+        if x <= y {
+            Box::new(x as usize..=y as usize)
+        } else {
+            Box::new((y as usize..=x as usize).rev())
+        }
+    }
+
     fn break_opcode(&mut self, opcode: u16) -> (u8, u8, u8, u8) {
         (
             (opcode >> 12 & 0xF) as u8,
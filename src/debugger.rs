@@ -0,0 +1,607 @@
+use chip8::MachineState;
+use instruction::Instruction;
+use machine::Machine;
+use std::io::{self, Write};
+use watchpoint::WatchKind;
+
+static HELP: &str = "\
+break ADDR [if vX OP VALUE]  stop before executing ADDR (OP: == != < <= > >=)
+delete [N]                   delete breakpoint N, or all of them
+watch ADDR[-END] [r|w|rw]    stop after an instruction reads or writes (w by default) the range
+unwatch N                    delete watchpoint N
+breakpoints                  list the breakpoints and watchpoints
+continue                     resume execution until the next breakpoint
+step [N]                     execute N instructions (1 by default)
+next                         like step, running subroutine calls to completion
+registers                    dump the registers and timers
+stack, bt                    dump the call stack
+examine ADDR [COUNT]         dump COUNT bytes of memory (16 by default)
+set TARGET VALUE             set v0-vf, i, pc, dt or st
+poke ADDR BYTE...            write bytes to memory
+quit                         exit the emulator
+
+Commands can be abbreviated to their first letter (b, d, c, s, n, r, x), an empty line repeats
+the last command and numbers are decimal or hexadecimal with a 0x prefix.";
+
+/// What the front end should do after a debugger command.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DebuggerAction {
+    /// Keep reading commands.
+    Prompt,
+
+    /// Run the machine until `should_break` says otherwise.
+    Resume,
+
+    /// Exit the emulator.
+    Quit,
+}
+
+/// Comparison of a conditional breakpoint.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    fn from_symbol(symbol: &str) -> Option<Comparison> {
+        match symbol {
+            "==" => Some(Comparison::Equal),
+            "!=" => Some(Comparison::NotEqual),
+            "<" => Some(Comparison::Less),
+            "<=" => Some(Comparison::LessOrEqual),
+            ">" => Some(Comparison::Greater),
+            ">=" => Some(Comparison::GreaterOrEqual),
+            _ => None,
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            Comparison::Equal => "==",
+            Comparison::NotEqual => "!=",
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+            Comparison::Greater => ">",
+            Comparison::GreaterOrEqual => ">=",
+        }
+    }
+
+    fn holds(self, left: u8, right: u8) -> bool {
+        match self {
+            Comparison::Equal => left == right,
+            Comparison::NotEqual => left != right,
+            Comparison::Less => left < right,
+            Comparison::LessOrEqual => left <= right,
+            Comparison::Greater => left > right,
+            Comparison::GreaterOrEqual => left >= right,
+        }
+    }
+}
+
+/// Condition on a register value that a breakpoint requires to stop.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Condition {
+    pub register: usize,
+    pub comparison: Comparison,
+    pub value: u8,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub id: usize,
+    pub address: u16,
+    pub condition: Option<Condition>,
+}
+
+impl Breakpoint {
+    fn matches(&self, machine: &Machine) -> bool {
+        machine.pc() == self.address
+            && self.condition.is_none_or(|condition| {
+                condition
+                    .comparison
+                    .holds(machine.registers()[condition.register], condition.value)
+            })
+    }
+}
+
+/// A gdb-like command line debugger.
+///
+/// The debugger does not read its input nor drive the machine on its own. The front end passes
+/// every command line to `execute` and, while the machine runs, stops it through
+/// `Machine::run_frame_until` whenever `should_break` returns true.
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    next_id: usize,
+
+    // Repeated on an empty line
+    last_command: String,
+
+    // Breakpoint that stopped the machine
+    hit: Option<usize>,
+
+    // The instruction execution resumes at does not stop it again
+    resumed_at: Option<u16>,
+
+    // Return address and stack depth where `next` stops after a subroutine call
+    step_over: Option<(u16, usize)>,
+
+    // A movie records the session, its input can not replay changes made to the machine
+    recording: bool,
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        Debugger {
+            breakpoints: Vec::new(),
+            next_id: 1,
+            last_command: String::new(),
+            hit: None,
+            resumed_at: None,
+            step_over: None,
+            recording: false,
+        }
+    }
+
+    /// Refuses `set` and `poke` while a movie is recorded. The movie only holds the input, so
+    /// replaying it would not repeat the changes and the replay would desync.
+    pub fn set_recording(&mut self, recording: bool) {
+        self.recording = recording;
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    /// Adds a breakpoint and returns its number.
+    pub fn add_breakpoint(&mut self, address: u16, condition: Option<Condition>) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.breakpoints.push(Breakpoint {
+            id,
+            address,
+            condition,
+        });
+        id
+    }
+
+    /// Removes the breakpoint with number `id`. Returns false if there is none.
+    pub fn remove_breakpoint(&mut self, id: usize) -> bool {
+        let count = self.breakpoints.len();
+        self.breakpoints.retain(|breakpoint| breakpoint.id != id);
+        self.breakpoints.len() != count
+    }
+
+    /// Whether the machine must stop before executing the instruction at PC: a breakpoint matches
+    /// or the previous instruction hit a watchpoint.
+    pub fn should_break(&mut self, machine: &Machine) -> bool {
+        if !machine.watch_hits().is_empty() {
+            return true;
+        }
+        if self.resumed_at.take() == Some(machine.pc()) {
+            return false;
+        }
+
+        if let Some((address, sp)) = self.step_over {
+            if machine.pc() == address && machine.sp() == sp {
+                self.step_over = None;
+                return true;
+            }
+        }
+
+        match self
+            .breakpoints
+            .iter()
+            .find(|breakpoint| breakpoint.matches(machine))
+        {
+            Some(breakpoint) => {
+                self.hit = Some(breakpoint.id);
+                self.step_over = None;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Prints where the machine stopped: the breakpoint or watchpoints hit, if any, and the next
+    /// instruction.
+    pub fn print_stop<W: Write>(&mut self, machine: &mut Machine, out: &mut W) -> io::Result<()> {
+        print_watch_hits(machine, out)?;
+        if let Some(id) = self.hit.take() {
+            write!(out, "Breakpoint {}, ", id)?;
+        }
+        print_location(machine, out)
+    }
+
+    /// Executes a command line, writing its output to `out`.
+    pub fn execute<W: Write>(
+        &mut self,
+        machine: &mut Machine,
+        line: &str,
+        out: &mut W,
+    ) -> io::Result<DebuggerAction> {
+        let line = if line.trim().is_empty() {
+            self.last_command.clone()
+        } else {
+            self.last_command = line.trim().to_string();
+            line.trim().to_string()
+        };
+        let mut words = line.split_whitespace();
+        let command = match words.next() {
+            Some(command) => command,
+            None => return Ok(DebuggerAction::Prompt),
+        };
+        let arguments: Vec<&str> = words.collect();
+
+        match self.command(machine, command, &arguments, out) {
+            Ok(action) => Ok(action),
+            Err(CommandError::Usage(message)) => {
+                writeln!(out, "{}", message)?;
+                Ok(DebuggerAction::Prompt)
+            }
+            Err(CommandError::Io(error)) => Err(error),
+        }
+    }
+
+    fn command<W: Write>(
+        &mut self,
+        machine: &mut Machine,
+        command: &str,
+        arguments: &[&str],
+        out: &mut W,
+    ) -> Result<DebuggerAction, CommandError> {
+        match command {
+            "b" | "break" => {
+                let address = match arguments.first() {
+                    Some(address) => parse_address(address, machine)?,
+                    None => machine.pc(),
+                };
+                let condition = match arguments.get(1) {
+                    Some(&"if") => Some(parse_condition(&arguments[2..])?),
+                    Some(_) => return Err(usage("usage: break ADDR [if vX OP VALUE]")),
+                    None => None,
+                };
+                let id = self.add_breakpoint(address, condition);
+                writeln!(out, "Breakpoint {} at {:#05x}", id, address)?;
+            }
+            "d" | "delete" => match arguments.first() {
+                Some(id) => {
+                    let id = parse_number(id)? as usize;
+                    if !self.remove_breakpoint(id) {
+                        return Err(usage(&format!("no breakpoint number {}", id)));
+                    }
+                }
+                None => self.breakpoints.clear(),
+            },
+            "watch" => {
+                let (start, end) = match arguments.first() {
+                    Some(range) => parse_range(range, machine)?,
+                    None => return Err(usage("usage: watch ADDR[-END] [r|w|rw]")),
+                };
+                let kind = match arguments.get(1) {
+                    Some(kind) => WatchKind::from_name(kind)
+                        .ok_or_else(|| usage("usage: watch ADDR[-END] [r|w|rw]"))?,
+                    None => WatchKind::Write,
+                };
+                let id = machine.add_watchpoint(start, end, kind);
+                writeln!(out, "Watchpoint {} at {:#05x}-{:#05x}", id, start, end)?;
+            }
+            "unwatch" => {
+                let id = match arguments.first() {
+                    Some(id) => parse_number(id)? as usize,
+                    None => return Err(usage("usage: unwatch N")),
+                };
+                if !machine.remove_watchpoint(id) {
+                    return Err(usage(&format!("no watchpoint number {}", id)));
+                }
+            }
+            "breakpoints" | "info" => {
+                if self.breakpoints.is_empty() && machine.watchpoints().is_empty() {
+                    writeln!(out, "No breakpoints")?;
+                }
+                for breakpoint in self.breakpoints.iter() {
+                    write!(out, "{:<4} {:#05x}", breakpoint.id, breakpoint.address)?;
+                    if let Some(condition) = breakpoint.condition {
+                        write!(
+                            out,
+                            " if v{:x} {} {:#04x}",
+                            condition.register,
+                            condition.comparison.symbol(),
+                            condition.value
+                        )?;
+                    }
+                    writeln!(out)?;
+                }
+                for watchpoint in machine.watchpoints() {
+                    writeln!(
+                        out,
+                        "w{:<3} {:#05x}-{:#05x} {}",
+                        watchpoint.id,
+                        watchpoint.start,
+                        watchpoint.end,
+                        watchpoint.kind.name()
+                    )?;
+                }
+            }
+            "c" | "continue" => {
+                self.resumed_at = Some(machine.pc());
+                return Ok(DebuggerAction::Resume);
+            }
+            "s" | "step" => {
+                let count = match arguments.first() {
+                    Some(count) => parse_number(count)?,
+                    None => 1,
+                };
+                for _ in 0..count {
+                    if !step(machine, out)? {
+                        break;
+                    }
+                }
+                print_location(machine, out)?;
+            }
+            "n" | "next" => {
+                // Calls run until they return to the next instruction
+                if let Some(opcode) = opcode_at(machine, machine.pc()) {
+                    if opcode >> 12 == 0x2 {
+                        self.step_over = Some((machine.pc().wrapping_add(2), machine.sp()));
+                        self.resumed_at = Some(machine.pc());
+                        return Ok(DebuggerAction::Resume);
+                    }
+                }
+                step(machine, out)?;
+                print_location(machine, out)?;
+            }
+            "r" | "registers" => {
+                for (x, value) in machine.registers().iter().enumerate() {
+                    let separator = if x % 8 == 7 { "\n" } else { "  " };
+                    write!(out, "V{:X} {:02x}{}", x, value, separator)?;
+                }
+                writeln!(
+                    out,
+                    "I  {:#06x}  PC {:#06x}  SP {}  DT {:02x}  ST {:02x}",
+                    machine.index(),
+                    machine.pc(),
+                    machine.sp(),
+                    machine.delay_timer(),
+                    machine.sound_timer()
+                )?;
+            }
+            "bt" | "stack" => {
+                if machine.sp() == 0 {
+                    writeln!(out, "Empty stack")?;
+                }
+                for depth in (0..machine.sp()).rev() {
+                    writeln!(out, "#{:<3} {:#05x}", depth, machine.stack()[depth])?;
+                }
+            }
+            "x" | "examine" => {
+                let address = match arguments.first() {
+                    Some(address) => parse_address(address, machine)? as usize,
+                    // I is not checked when it is set
+                    None if (machine.index() as usize) < machine.memory().len() => {
+                        machine.index() as usize
+                    }
+                    None => {
+                        return Err(usage(&format!(
+                            "address out of memory bounds: I={:#06x}",
+                            machine.index()
+                        )))
+                    }
+                };
+                let count = match arguments.get(1) {
+                    Some(count) => parse_number(count)? as usize,
+                    None => 16,
+                };
+                let end = (address + count).min(machine.memory().len());
+                for (line, bytes) in machine.memory()[address..end].chunks(16).enumerate() {
+                    write!(out, "{:#06x}:", address + line * 16)?;
+                    for byte in bytes {
+                        write!(out, " {:02x}", byte)?;
+                    }
+                    writeln!(out)?;
+                }
+            }
+            "set" | "poke" if self.recording => {
+                return Err(usage(&format!(
+                    "{} is disabled while recording a movie, the replay could not repeat it",
+                    command
+                )))
+            }
+            "set" => {
+                let (target, value) = match arguments {
+                    [target, value] => (target.to_lowercase(), *value),
+                    _ => return Err(usage("usage: set TARGET VALUE")),
+                };
+                match target.as_str() {
+                    "i" => machine.set_index(parse_word(value)?),
+                    "pc" => machine.set_pc(parse_word(value)?),
+                    "dt" => machine.set_delay_timer(parse_byte(value)?),
+                    "st" => machine.set_sound_timer(parse_byte(value)?),
+                    register => match parse_register(register) {
+                        Some(x) => machine.set_register(x, parse_byte(value)?),
+                        None => return Err(usage(&format!("unknown target {}", target))),
+                    },
+                }
+            }
+            "poke" => {
+                if arguments.len() < 2 {
+                    return Err(usage("usage: poke ADDR BYTE..."));
+                }
+                let address = parse_address(arguments[0], machine)? as usize;
+                if address + arguments.len() - 1 > machine.memory().len() {
+                    return Err(usage("address out of memory bounds"));
+                }
+                // Nothing is written unless every byte is valid
+                let bytes = arguments[1..]
+                    .iter()
+                    .map(|byte| parse_byte(byte))
+                    .collect::<Result<Vec<u8>, CommandError>>()?;
+                machine.memory_mut()[address..address + bytes.len()].copy_from_slice(&bytes);
+            }
+            "h" | "help" => writeln!(out, "{}", HELP)?,
+            "q" | "quit" => return Ok(DebuggerAction::Quit),
+            _ => {
+                return Err(usage(&format!(
+                    "unknown command {}, type help for the list of commands",
+                    command
+                )))
+            }
+        }
+
+        Ok(DebuggerAction::Prompt)
+    }
+}
+
+impl Default for Debugger {
+    fn default() -> Debugger {
+        Debugger::new()
+    }
+}
+
+enum CommandError {
+    Usage(String),
+    Io(io::Error),
+}
+
+impl From<io::Error> for CommandError {
+    fn from(error: io::Error) -> CommandError {
+        CommandError::Io(error)
+    }
+}
+
+fn usage(message: &str) -> CommandError {
+    CommandError::Usage(message.to_string())
+}
+
+// Executes one instruction, returns false if the machine can not go on or hit a watchpoint
+fn step<W: Write>(machine: &mut Machine, out: &mut W) -> Result<bool, CommandError> {
+    let result = machine.step();
+    if print_watch_hits(machine, out)? {
+        return Ok(false);
+    }
+    match result {
+        Ok(MachineState::WaitForKeyboard) => {
+            writeln!(out, "Waiting for a key, continue and press it")?;
+            Ok(false)
+        }
+        Ok(MachineState::Exit) => {
+            writeln!(out, "The program exited")?;
+            Ok(false)
+        }
+        Ok(_) => Ok(true),
+        Err(error) => {
+            writeln!(out, "{}", error)?;
+            Ok(false)
+        }
+    }
+}
+
+// Returns whether any watchpoint was hit
+fn print_watch_hits<W: Write>(machine: &mut Machine, out: &mut W) -> io::Result<bool> {
+    let hits = machine.take_watch_hits();
+    for hit in hits.iter() {
+        writeln!(out, "{}", hit)?;
+    }
+    Ok(!hits.is_empty())
+}
+
+fn print_location<W: Write>(machine: &Machine, out: &mut W) -> io::Result<()> {
+    let pc = machine.pc();
+    match opcode_at(machine, pc) {
+        Some(opcode) => {
+            let memory = &machine.memory()[pc as usize..];
+            match Instruction::decode(memory, machine.platform()) {
+                Some(instruction) => writeln!(out, "{:#05x}: {:04x}  {}", pc, opcode, instruction),
+                None => writeln!(out, "{:#05x}: {:04x}  (invalid opcode)", pc, opcode),
+            }
+        }
+        None => writeln!(out, "{:#05x}: out of memory bounds", pc),
+    }
+}
+
+fn opcode_at(machine: &Machine, address: u16) -> Option<u16> {
+    let memory = machine.memory();
+    let address = address as usize;
+    if address + 1 < memory.len() {
+        Some((memory[address] as u16) << 8 | memory[address + 1] as u16)
+    } else {
+        None
+    }
+}
+
+// Numbers are hexadecimal with a 0x prefix or decimal
+fn parse_number(value: &str) -> Result<u32, CommandError> {
+    let parsed = if value.starts_with("0x") || value.starts_with("0X") {
+        u32::from_str_radix(&value[2..], 16)
+    } else {
+        value.parse::<u32>()
+    };
+    parsed.map_err(|_| usage(&format!("invalid number: {}", value)))
+}
+
+// A number that fits in a byte, for V0-VF, the timers and memory
+fn parse_byte(value: &str) -> Result<u8, CommandError> {
+    let number = parse_number(value)?;
+    if number > u8::MAX as u32 {
+        return Err(usage(&format!("value does not fit in a byte: {}", value)));
+    }
+    Ok(number as u8)
+}
+
+// A number that fits in 16 bits, for I and PC
+fn parse_word(value: &str) -> Result<u16, CommandError> {
+    let number = parse_number(value)?;
+    if number > u16::MAX as u32 {
+        return Err(usage(&format!("value does not fit in 16 bits: {}", value)));
+    }
+    Ok(number as u16)
+}
+
+fn parse_address(value: &str, machine: &Machine) -> Result<u16, CommandError> {
+    let address = parse_number(value)?;
+    if address as usize >= machine.memory().len() {
+        return Err(usage(&format!("address out of memory bounds: {}", value)));
+    }
+    Ok(address as u16)
+}
+
+// ADDR or ADDR-END
+fn parse_range(value: &str, machine: &Machine) -> Result<(u16, u16), CommandError> {
+    let mut bounds = value.splitn(2, '-');
+    let start = parse_address(bounds.next().unwrap_or(""), machine)?;
+    let end = match bounds.next() {
+        Some(end) => parse_address(end, machine)?,
+        None => start,
+    };
+    if end < start {
+        return Err(usage(&format!("invalid range: {}", value)));
+    }
+    Ok((start, end))
+}
+
+// v0 to vf
+fn parse_register(name: &str) -> Option<usize> {
+    let name = name.to_lowercase();
+    if name.len() == 2 && name.starts_with('v') {
+        usize::from_str_radix(&name[1..], 16).ok()
+    } else {
+        None
+    }
+}
+
+// vX OP VALUE
+fn parse_condition(words: &[&str]) -> Result<Condition, CommandError> {
+    let invalid = || usage("usage: break ADDR if vX OP VALUE");
+    match words {
+        [register, comparison, value] => Ok(Condition {
+            register: parse_register(register).ok_or_else(invalid)?,
+            comparison: Comparison::from_symbol(comparison).ok_or_else(invalid)?,
+            value: parse_byte(value)?,
+        }),
+        _ => Err(invalid()),
+    }
+}
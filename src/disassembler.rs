@@ -0,0 +1,176 @@
+use cfg::ControlFlowGraph;
+use instruction::{Instruction, Syntax};
+use platform::Platform;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Write};
+
+/// An instruction or, when the bytes do not decode to one, data.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Line {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    pub instruction: Option<Instruction>,
+}
+
+/// Listing of a ROM.
+///
+/// Jump and call targets that fall on a listed instruction are given a label: `sub_XXX` for
+/// subroutines and `loc_XXX` for everything else. Data loaded into I gets a `data_XXX` label.
+pub struct Disassembly {
+    pub lines: Vec<Line>,
+    pub labels: BTreeMap<u16, String>,
+}
+
+impl Disassembly {
+    /// Lists the instructions reachable from the load address (see `ControlFlowGraph`) and
+    /// shows everything else, sprites included, as data.
+    pub fn new(rom: &[u8], load_address: u16, platform: Platform) -> Disassembly {
+        Disassembly::from_graph(rom, &ControlFlowGraph::new(rom, load_address, platform))
+    }
+
+    /// Like `new`, with the graph already built for `rom`.
+    pub fn from_graph(rom: &[u8], graph: &ControlFlowGraph) -> Disassembly {
+        let load_address = graph.entry;
+        // Data is split where the program points I at it
+        let data: BTreeSet<u16> = graph
+            .instructions
+            .values()
+            .filter_map(|instruction| match *instruction {
+                Instruction::LoadIndex(address) | Instruction::LoadIndexLong(address) => {
+                    Some(address)
+                }
+                _ => None,
+            })
+            .collect();
+
+        let mut lines = Vec::new();
+        let mut offset = 0;
+        while offset < rom.len() {
+            let address = load_address.wrapping_add(offset as u16);
+            let instruction = graph.instructions.get(&address).cloned();
+            let size = match instruction {
+                Some(instruction) => instruction.size(),
+                None => {
+                    let mut size = 1;
+                    while size < 2 {
+                        let next = address.wrapping_add(size as u16);
+                        if graph.is_code(next) || data.contains(&next) {
+                            break;
+                        }
+                        size += 1;
+                    }
+                    size
+                }
+            }
+            .min(rom.len() - offset);
+            lines.push(Line {
+                address,
+                bytes: rom[offset..(offset + size)].to_vec(),
+                instruction,
+            });
+            offset += size;
+        }
+
+        let labels = labels(&lines, &data);
+        Disassembly { lines, labels }
+    }
+
+    /// Decodes the whole ROM two bytes at a time from the load address, data included, like
+    /// the programs did not mix code and data.
+    pub fn linear(rom: &[u8], load_address: u16, platform: Platform) -> Disassembly {
+        let mut lines = Vec::new();
+        let mut offset = 0;
+        while offset < rom.len() {
+            let address = load_address.wrapping_add(offset as u16);
+            let instruction = Instruction::decode(&rom[offset..], platform);
+            let size = instruction
+                .map_or(2, Instruction::size)
+                .min(rom.len() - offset);
+            lines.push(Line {
+                address,
+                bytes: rom[offset..(offset + size)].to_vec(),
+                instruction,
+            });
+            offset += size;
+        }
+
+        let labels = labels(&lines, &BTreeSet::new());
+        Disassembly { lines, labels }
+    }
+
+    /// Writes the listing in `syntax`: one line per instruction with its address, its bytes and
+    /// its mnemonic, preceded by its label if it has one.
+    pub fn write<W: Write>(&self, syntax: Syntax, out: &mut W) -> io::Result<()> {
+        for line in &self.lines {
+            if let Some(label) = self.labels.get(&line.address) {
+                match syntax {
+                    Syntax::Cowgod => writeln!(out, "{}:", label)?,
+                    Syntax::Octo => writeln!(out, ": {}", label)?,
+                }
+            }
+
+            let bytes: Vec<String> = line
+                .bytes
+                .chunks(2)
+                .map(|word| word.iter().map(|byte| format!("{:02x}", byte)).collect())
+                .collect();
+            let text = match line.instruction {
+                Some(instruction) => instruction.to_text(syntax, &self.labels),
+                None => data(&line.bytes, syntax),
+            };
+            writeln!(
+                out,
+                "{:#05x}  {:<11}{}",
+                line.address,
+                bytes.join(" "),
+                text
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// Labels for the jump and call targets and the data that start a line
+fn labels(lines: &[Line], data: &BTreeSet<u16>) -> BTreeMap<u16, String> {
+    let line_at = |address: u16| {
+        lines
+            .binary_search_by_key(&address, |line| line.address)
+            .ok()
+            .map(|index| &lines[index])
+    };
+
+    let mut labels = BTreeMap::new();
+    for &address in data {
+        if line_at(address).is_some_and(|line| line.instruction.is_none()) {
+            labels.insert(address, format!("data_{:03x}", address));
+        }
+    }
+    for line in lines {
+        let (target, prefix) = match line.instruction {
+            Some(Instruction::Call(address)) => (address, "sub"),
+            Some(Instruction::Jump(address)) | Some(Instruction::JumpOffset(address)) => {
+                (address, "loc")
+            }
+            _ => continue,
+        };
+        if line_at(target).is_none() {
+            continue;
+        }
+        // A subroutine that is also jumped to keeps its subroutine name
+        let label = format!("{}_{:03x}", prefix, target);
+        if prefix == "sub" {
+            labels.insert(target, label);
+        } else {
+            labels.entry(target).or_insert(label);
+        }
+    }
+    labels
+}
+
+fn data(bytes: &[u8], syntax: Syntax) -> String {
+    let bytes: Vec<String> = bytes.iter().map(|byte| format!("{:#04x}", byte)).collect();
+    match syntax {
+        Syntax::Cowgod => format!("DB {}", bytes.join(", ")),
+        Syntax::Octo => bytes.join(" "),
+    }
+}
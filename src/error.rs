@@ -0,0 +1,77 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// Faults the machine can run into. Execution faults carry the address of the instruction and
+/// its opcode.
+#[derive(Debug)]
+pub enum Chip8Error {
+    /// The opcode is not part of the emulated instruction set.
+    InvalidOpcode { pc: u16, opcode: u16 },
+
+    /// `2nnn` called with the 16 stack entries already in use.
+    StackOverflow { pc: u16, opcode: u16 },
+
+    /// `00EE` returned with an empty stack.
+    StackUnderflow { pc: u16, opcode: u16 },
+
+    /// The program counter left the addressable memory.
+    PcOutOfBounds { pc: u16 },
+
+    /// The instruction read or wrote past the end of the addressable memory.
+    MemoryOutOfBounds {
+        pc: u16,
+        opcode: u16,
+        address: usize,
+    },
+
+    /// The ROM does not fit in memory at its load address.
+    RomTooLarge { size: usize, max: usize },
+
+    /// The ROM could not be read.
+    Io(io::Error),
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Chip8Error::InvalidOpcode { pc, opcode } => write!(
+                f,
+                "attempted to execute {:04x} at {:#05x} (invalid opcode)",
+                opcode, pc
+            ),
+            Chip8Error::StackOverflow { pc, opcode } => {
+                write!(f, "stack overflow executing {:04x} at {:#05x}", opcode, pc)
+            }
+            Chip8Error::StackUnderflow { pc, opcode } => {
+                write!(f, "stack underflow executing {:04x} at {:#05x}", opcode, pc)
+            }
+            Chip8Error::PcOutOfBounds { pc } => {
+                write!(f, "program counter out of memory bounds ({:#05x})", pc)
+            }
+            Chip8Error::MemoryOutOfBounds {
+                pc,
+                opcode,
+                address,
+            } => write!(
+                f,
+                "{:04x} at {:#05x} accessed memory out of bounds ({:#x})",
+                opcode, pc, address
+            ),
+            Chip8Error::RomTooLarge { size, max } => write!(
+                f,
+                "ROM too large: {} bytes, at most {} bytes fit in memory",
+                size, max
+            ),
+            Chip8Error::Io(ref error) => write!(f, "could not read ROM: {}", error),
+        }
+    }
+}
+
+impl Error for Chip8Error {}
+
+impl From<io::Error> for Chip8Error {
+    fn from(error: io::Error) -> Chip8Error {
+        Chip8Error::Io(error)
+    }
+}
@@ -0,0 +1,430 @@
+use platform::Platform;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Assembly syntax instructions are written in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Syntax {
+    /// The mnemonics of Cowgod's technical reference, e.g. `LD V1, 0x20`.
+    Cowgod,
+
+    /// The Octo language, e.g. `v1 := 0x20`.
+    Octo,
+}
+
+impl Syntax {
+    /// Names accepted by `from_name`.
+    pub const NAMES: [&'static str; 2] = ["cowgod", "octo"];
+
+    /// Returns the syntax with the given name (see `NAMES`).
+    pub fn from_name(name: &str) -> Option<Syntax> {
+        match name {
+            "cowgod" => Some(Syntax::Cowgod),
+            "octo" => Some(Syntax::Octo),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Syntax::Cowgod => "cowgod",
+            Syntax::Octo => "octo",
+        }
+    }
+}
+
+/// A decoded instruction. Registers are numbered 0 to 15 and the variants are named after what
+/// the instruction does; the comments give the opcode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    /// 00E0
+    ClearScreen,
+    /// 00EE
+    Return,
+    /// 00Cn (SUPER-CHIP)
+    ScrollDown(u8),
+    /// 00Dn (XO-CHIP)
+    ScrollUp(u8),
+    /// 00FB (SUPER-CHIP)
+    ScrollRight,
+    /// 00FC (SUPER-CHIP)
+    ScrollLeft,
+    /// 00FD (SUPER-CHIP)
+    Exit,
+    /// 00FE (SUPER-CHIP)
+    LowRes,
+    /// 00FF (SUPER-CHIP)
+    HighRes,
+    /// 1nnn
+    Jump(u16),
+    /// 2nnn
+    Call(u16),
+    /// 3xkk
+    SkipEqualByte(u8, u8),
+    /// 4xkk
+    SkipNotEqualByte(u8, u8),
+    /// 5xy0
+    SkipEqual(u8, u8),
+    /// 5xy2 (XO-CHIP)
+    SaveRange(u8, u8),
+    /// 5xy3 (XO-CHIP)
+    LoadRange(u8, u8),
+    /// 6xkk
+    LoadByte(u8, u8),
+    /// 7xkk
+    AddByte(u8, u8),
+    /// 8xy0
+    Move(u8, u8),
+    /// 8xy1
+    Or(u8, u8),
+    /// 8xy2
+    And(u8, u8),
+    /// 8xy3
+    Xor(u8, u8),
+    /// 8xy4
+    Add(u8, u8),
+    /// 8xy5
+    Sub(u8, u8),
+    /// 8xy6
+    ShiftRight(u8, u8),
+    /// 8xy7
+    SubReverse(u8, u8),
+    /// 8xyE
+    ShiftLeft(u8, u8),
+    /// 9xy0
+    SkipNotEqual(u8, u8),
+    /// Annn
+    LoadIndex(u16),
+    /// Bnnn
+    JumpOffset(u16),
+    /// Cxkk
+    Random(u8, u8),
+    /// Dxyn
+    Draw(u8, u8, u8),
+    /// Ex9E
+    SkipKey(u8),
+    /// ExA1
+    SkipNotKey(u8),
+    /// F000 nnnn (XO-CHIP)
+    LoadIndexLong(u16),
+    /// Fn01 (XO-CHIP)
+    Plane(u8),
+    /// F002 (XO-CHIP)
+    Audio,
+    /// Fx07
+    GetDelay(u8),
+    /// Fx0A
+    WaitKey(u8),
+    /// Fx15
+    SetDelay(u8),
+    /// Fx18
+    SetSound(u8),
+    /// Fx1E
+    AddIndex(u8),
+    /// Fx29
+    Font(u8),
+    /// Fx30 (SUPER-CHIP)
+    BigFont(u8),
+    /// Fx33
+    Bcd(u8),
+    /// Fx3A (XO-CHIP)
+    Pitch(u8),
+    /// Fx55
+    Store(u8),
+    /// Fx65
+    Load(u8),
+    /// Fx75 (SUPER-CHIP)
+    SaveFlags(u8),
+    /// Fx85 (SUPER-CHIP)
+    LoadFlags(u8),
+}
+
+impl Instruction {
+    /// Decodes the instruction at the start of `bytes`, None if they do not hold an opcode the
+    /// platform executes.
+    pub fn decode(bytes: &[u8], platform: Platform) -> Option<Instruction> {
+        if bytes.len() < 2 {
+            return None;
+        }
+        let opcode = (bytes[0] as u16) << 8 | bytes[1] as u16;
+        let x = bytes[0] & 0xF;
+        let y = bytes[1] >> 4;
+        let n = bytes[1] & 0xF;
+        let kk = bytes[1];
+        let nnn = opcode & 0x0FFF;
+        let super_chip = platform.has_super_chip();
+        let xo_chip = platform.has_xo_chip();
+
+        let instruction = match (bytes[0] >> 4, x, y, n) {
+            (0x0, 0x0, 0xE, 0x0) => Instruction::ClearScreen,
+            (0x0, 0x0, 0xE, 0xE) => Instruction::Return,
+            (0x0, 0x0, 0xC, n) if super_chip => Instruction::ScrollDown(n),
+            (0x0, 0x0, 0xD, n) if xo_chip => Instruction::ScrollUp(n),
+            (0x0, 0x0, 0xF, 0xB) if super_chip => Instruction::ScrollRight,
+            (0x0, 0x0, 0xF, 0xC) if super_chip => Instruction::ScrollLeft,
+            (0x0, 0x0, 0xF, 0xD) if super_chip => Instruction::Exit,
+            (0x0, 0x0, 0xF, 0xE) if super_chip => Instruction::LowRes,
+            (0x0, 0x0, 0xF, 0xF) if super_chip => Instruction::HighRes,
+            (0x1, _, _, _) => Instruction::Jump(nnn),
+            (0x2, _, _, _) => Instruction::Call(nnn),
+            (0x3, x, _, _) => Instruction::SkipEqualByte(x, kk),
+            (0x4, x, _, _) => Instruction::SkipNotEqualByte(x, kk),
+            (0x5, x, y, 0x0) => Instruction::SkipEqual(x, y),
+            (0x5, x, y, 0x2) if xo_chip => Instruction::SaveRange(x, y),
+            (0x5, x, y, 0x3) if xo_chip => Instruction::LoadRange(x, y),
+            (0x6, x, _, _) => Instruction::LoadByte(x, kk),
+            (0x7, x, _, _) => Instruction::AddByte(x, kk),
+            (0x8, x, y, 0x0) => Instruction::Move(x, y),
+            (0x8, x, y, 0x1) => Instruction::Or(x, y),
+            (0x8, x, y, 0x2) => Instruction::And(x, y),
+            (0x8, x, y, 0x3) => Instruction::Xor(x, y),
+            (0x8, x, y, 0x4) => Instruction::Add(x, y),
+            (0x8, x, y, 0x5) => Instruction::Sub(x, y),
+            (0x8, x, y, 0x6) => Instruction::ShiftRight(x, y),
+            (0x8, x, y, 0x7) => Instruction::SubReverse(x, y),
+            (0x8, x, y, 0xE) => Instruction::ShiftLeft(x, y),
+            (0x9, x, y, 0x0) => Instruction::SkipNotEqual(x, y),
+            (0xA, _, _, _) => Instruction::LoadIndex(nnn),
+            (0xB, _, _, _) => Instruction::JumpOffset(nnn),
+            (0xC, x, _, _) => Instruction::Random(x, kk),
+            (0xD, x, y, n) => Instruction::Draw(x, y, n),
+            (0xE, x, 0x9, 0xE) => Instruction::SkipKey(x),
+            (0xE, x, 0xA, 0x1) => Instruction::SkipNotKey(x),
+            (0xF, 0x0, 0x0, 0x0) if xo_chip && bytes.len() >= 4 => {
+                Instruction::LoadIndexLong((bytes[2] as u16) << 8 | bytes[3] as u16)
+            }
+            (0xF, n, 0x0, 0x1) if xo_chip => Instruction::Plane(n),
+            (0xF, 0x0, 0x0, 0x2) if xo_chip => Instruction::Audio,
+            (0xF, x, 0x0, 0x7) => Instruction::GetDelay(x),
+            (0xF, x, 0x0, 0xA) => Instruction::WaitKey(x),
+            (0xF, x, 0x1, 0x5) => Instruction::SetDelay(x),
+            (0xF, x, 0x1, 0x8) => Instruction::SetSound(x),
+            (0xF, x, 0x1, 0xE) => Instruction::AddIndex(x),
+            (0xF, x, 0x2, 0x9) => Instruction::Font(x),
+            (0xF, x, 0x3, 0x0) if super_chip => Instruction::BigFont(x),
+            (0xF, x, 0x3, 0x3) => Instruction::Bcd(x),
+            (0xF, x, 0x3, 0xA) if xo_chip => Instruction::Pitch(x),
+            (0xF, x, 0x5, 0x5) => Instruction::Store(x),
+            (0xF, x, 0x6, 0x5) => Instruction::Load(x),
+            (0xF, x, 0x7, 0x5) if super_chip => Instruction::SaveFlags(x),
+            (0xF, x, 0x8, 0x5) if super_chip => Instruction::LoadFlags(x),
+            _ => return None,
+        };
+        Some(instruction)
+    }
+
+    /// Returns the bytes of the instruction, the inverse of `decode`.
+    pub fn encode(self) -> Vec<u8> {
+        use self::Instruction::*;
+        let xy = |high: u16, x: u8, y: u8, low: u16| {
+            high << 12 | (x as u16) << 8 | (y as u16) << 4 | low
+        };
+        let opcode = match self {
+            ClearScreen => 0x00E0,
+            Return => 0x00EE,
+            ScrollDown(n) => 0x00C0 | n as u16,
+            ScrollUp(n) => 0x00D0 | n as u16,
+            ScrollRight => 0x00FB,
+            ScrollLeft => 0x00FC,
+            Exit => 0x00FD,
+            LowRes => 0x00FE,
+            HighRes => 0x00FF,
+            Jump(nnn) => 0x1000 | nnn,
+            Call(nnn) => 0x2000 | nnn,
+            SkipEqualByte(x, kk) => 0x3000 | (x as u16) << 8 | kk as u16,
+            SkipNotEqualByte(x, kk) => 0x4000 | (x as u16) << 8 | kk as u16,
+            SkipEqual(x, y) => xy(0x5, x, y, 0x0),
+            SaveRange(x, y) => xy(0x5, x, y, 0x2),
+            LoadRange(x, y) => xy(0x5, x, y, 0x3),
+            LoadByte(x, kk) => 0x6000 | (x as u16) << 8 | kk as u16,
+            AddByte(x, kk) => 0x7000 | (x as u16) << 8 | kk as u16,
+            Move(x, y) => xy(0x8, x, y, 0x0),
+            Or(x, y) => xy(0x8, x, y, 0x1),
+            And(x, y) => xy(0x8, x, y, 0x2),
+            Xor(x, y) => xy(0x8, x, y, 0x3),
+            Add(x, y) => xy(0x8, x, y, 0x4),
+            Sub(x, y) => xy(0x8, x, y, 0x5),
+            ShiftRight(x, y) => xy(0x8, x, y, 0x6),
+            SubReverse(x, y) => xy(0x8, x, y, 0x7),
+            ShiftLeft(x, y) => xy(0x8, x, y, 0xE),
+            SkipNotEqual(x, y) => xy(0x9, x, y, 0x0),
+            LoadIndex(nnn) => 0xA000 | nnn,
+            JumpOffset(nnn) => 0xB000 | nnn,
+            Random(x, kk) => 0xC000 | (x as u16) << 8 | kk as u16,
+            Draw(x, y, n) => xy(0xD, x, y, n as u16),
+            SkipKey(x) => xy(0xE, x, 0x9, 0xE),
+            SkipNotKey(x) => xy(0xE, x, 0xA, 0x1),
+            LoadIndexLong(nnnn) => return vec![0xF0, 0x00, (nnnn >> 8) as u8, nnnn as u8],
+            Plane(n) => xy(0xF, n, 0x0, 0x1),
+            Audio => 0xF002,
+            GetDelay(x) => xy(0xF, x, 0x0, 0x7),
+            WaitKey(x) => xy(0xF, x, 0x0, 0xA),
+            SetDelay(x) => xy(0xF, x, 0x1, 0x5),
+            SetSound(x) => xy(0xF, x, 0x1, 0x8),
+            AddIndex(x) => xy(0xF, x, 0x1, 0xE),
+            Font(x) => xy(0xF, x, 0x2, 0x9),
+            BigFont(x) => xy(0xF, x, 0x3, 0x0),
+            Bcd(x) => xy(0xF, x, 0x3, 0x3),
+            Pitch(x) => xy(0xF, x, 0x3, 0xA),
+            Store(x) => xy(0xF, x, 0x5, 0x5),
+            Load(x) => xy(0xF, x, 0x6, 0x5),
+            SaveFlags(x) => xy(0xF, x, 0x7, 0x5),
+            LoadFlags(x) => xy(0xF, x, 0x8, 0x5),
+        };
+        vec![(opcode >> 8) as u8, opcode as u8]
+    }
+
+    /// Size in bytes: 4 for `F000 nnnn`, 2 for everything else.
+    pub fn size(self) -> usize {
+        match self {
+            Instruction::LoadIndexLong(_) => 4,
+            _ => 2,
+        }
+    }
+
+    /// Address the instruction transfers control to, for jumps and calls with a fixed target.
+    pub fn target(self) -> Option<u16> {
+        match self {
+            Instruction::Jump(address) | Instruction::Call(address) => Some(address),
+            _ => None,
+        }
+    }
+
+    /// Writes the instruction in `syntax`. Addresses with an entry in `labels` are written as
+    /// the label.
+    pub fn to_text(self, syntax: Syntax, labels: &BTreeMap<u16, String>) -> String {
+        let address = |address: u16| match labels.get(&address) {
+            Some(label) => label.clone(),
+            None => format!("{:#05x}", address),
+        };
+        match syntax {
+            Syntax::Cowgod => self.cowgod(address),
+            Syntax::Octo => self.octo(address, labels),
+        }
+    }
+
+    fn cowgod<F: Fn(u16) -> String>(self, address: F) -> String {
+        use self::Instruction::*;
+        match self {
+            ClearScreen => "CLS".to_string(),
+            Return => "RET".to_string(),
+            ScrollDown(n) => format!("SCD {}", n),
+            ScrollUp(n) => format!("SCU {}", n),
+            ScrollRight => "SCR".to_string(),
+            ScrollLeft => "SCL".to_string(),
+            Exit => "EXIT".to_string(),
+            LowRes => "LOW".to_string(),
+            HighRes => "HIGH".to_string(),
+            Jump(nnn) => format!("JP {}", address(nnn)),
+            Call(nnn) => format!("CALL {}", address(nnn)),
+            SkipEqualByte(x, kk) => format!("SE V{:X}, {:#04x}", x, kk),
+            SkipNotEqualByte(x, kk) => format!("SNE V{:X}, {:#04x}", x, kk),
+            SkipEqual(x, y) => format!("SE V{:X}, V{:X}", x, y),
+            SaveRange(x, y) => format!("SAVE V{:X} - V{:X}", x, y),
+            LoadRange(x, y) => format!("LOAD V{:X} - V{:X}", x, y),
+            LoadByte(x, kk) => format!("LD V{:X}, {:#04x}", x, kk),
+            AddByte(x, kk) => format!("ADD V{:X}, {:#04x}", x, kk),
+            Move(x, y) => format!("LD V{:X}, V{:X}", x, y),
+            Or(x, y) => format!("OR V{:X}, V{:X}", x, y),
+            And(x, y) => format!("AND V{:X}, V{:X}", x, y),
+            Xor(x, y) => format!("XOR V{:X}, V{:X}", x, y),
+            Add(x, y) => format!("ADD V{:X}, V{:X}", x, y),
+            Sub(x, y) => format!("SUB V{:X}, V{:X}", x, y),
+            ShiftRight(x, y) => format!("SHR V{:X}, V{:X}", x, y),
+            SubReverse(x, y) => format!("SUBN V{:X}, V{:X}", x, y),
+            ShiftLeft(x, y) => format!("SHL V{:X}, V{:X}", x, y),
+            SkipNotEqual(x, y) => format!("SNE V{:X}, V{:X}", x, y),
+            LoadIndex(nnn) => format!("LD I, {}", address(nnn)),
+            JumpOffset(nnn) => format!("JP V0, {}", address(nnn)),
+            Random(x, kk) => format!("RND V{:X}, {:#04x}", x, kk),
+            Draw(x, y, n) => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+            SkipKey(x) => format!("SKP V{:X}", x),
+            SkipNotKey(x) => format!("SKNP V{:X}", x),
+            LoadIndexLong(nnnn) => format!("LD I, long {}", address(nnnn)),
+            Plane(n) => format!("PLANE {}", n),
+            Audio => "AUDIO".to_string(),
+            GetDelay(x) => format!("LD V{:X}, DT", x),
+            WaitKey(x) => format!("LD V{:X}, K", x),
+            SetDelay(x) => format!("LD DT, V{:X}", x),
+            SetSound(x) => format!("LD ST, V{:X}", x),
+            AddIndex(x) => format!("ADD I, V{:X}", x),
+            Font(x) => format!("LD F, V{:X}", x),
+            BigFont(x) => format!("LD HF, V{:X}", x),
+            Bcd(x) => format!("LD B, V{:X}", x),
+            Pitch(x) => format!("PITCH V{:X}", x),
+            Store(x) => format!("LD [I], V{:X}", x),
+            Load(x) => format!("LD V{:X}, [I]", x),
+            SaveFlags(x) => format!("LD R, V{:X}", x),
+            LoadFlags(x) => format!("LD V{:X}, R", x),
+        }
+    }
+
+    // Octo writes skips as the condition under which the next instruction runs, so the
+    // comparisons are the opposite of the opcodes'
+    fn octo<F: Fn(u16) -> String>(self, address: F, labels: &BTreeMap<u16, String>) -> String {
+        use self::Instruction::*;
+        match self {
+            ClearScreen => "clear".to_string(),
+            Return => "return".to_string(),
+            ScrollDown(n) => format!("scroll-down {}", n),
+            ScrollUp(n) => format!("scroll-up {}", n),
+            ScrollRight => "scroll-right".to_string(),
+            ScrollLeft => "scroll-left".to_string(),
+            Exit => "exit".to_string(),
+            LowRes => "lores".to_string(),
+            HighRes => "hires".to_string(),
+            Jump(nnn) => format!("jump {}", address(nnn)),
+            // Subroutines are called by naming them
+            Call(nnn) => match labels.get(&nnn) {
+                Some(label) => label.clone(),
+                None => format!(":call {}", address(nnn)),
+            },
+            SkipEqualByte(x, kk) => format!("if v{:x} != {:#04x} then", x, kk),
+            SkipNotEqualByte(x, kk) => format!("if v{:x} == {:#04x} then", x, kk),
+            SkipEqual(x, y) => format!("if v{:x} != v{:x} then", x, y),
+            SaveRange(x, y) => format!("save v{:x} - v{:x}", x, y),
+            LoadRange(x, y) => format!("load v{:x} - v{:x}", x, y),
+            LoadByte(x, kk) => format!("v{:x} := {:#04x}", x, kk),
+            AddByte(x, kk) => format!("v{:x} += {:#04x}", x, kk),
+            Move(x, y) => format!("v{:x} := v{:x}", x, y),
+            Or(x, y) => format!("v{:x} |= v{:x}", x, y),
+            And(x, y) => format!("v{:x} &= v{:x}", x, y),
+            Xor(x, y) => format!("v{:x} ^= v{:x}", x, y),
+            Add(x, y) => format!("v{:x} += v{:x}", x, y),
+            Sub(x, y) => format!("v{:x} -= v{:x}", x, y),
+            ShiftRight(x, y) => format!("v{:x} >>= v{:x}", x, y),
+            SubReverse(x, y) => format!("v{:x} =- v{:x}", x, y),
+            ShiftLeft(x, y) => format!("v{:x} <<= v{:x}", x, y),
+            SkipNotEqual(x, y) => format!("if v{:x} == v{:x} then", x, y),
+            LoadIndex(nnn) => format!("i := {}", address(nnn)),
+            JumpOffset(nnn) => format!("jump0 {}", address(nnn)),
+            Random(x, kk) => format!("v{:x} := random {:#04x}", x, kk),
+            Draw(x, y, n) => format!("sprite v{:x} v{:x} {}", x, y, n),
+            SkipKey(x) => format!("if v{:x} -key then", x),
+            SkipNotKey(x) => format!("if v{:x} key then", x),
+            LoadIndexLong(nnnn) => format!("i := long {}", address(nnnn)),
+            Plane(n) => format!("plane {}", n),
+            Audio => "audio".to_string(),
+            GetDelay(x) => format!("v{:x} := delay", x),
+            WaitKey(x) => format!("v{:x} := key", x),
+            SetDelay(x) => format!("delay := v{:x}", x),
+            SetSound(x) => format!("buzzer := v{:x}", x),
+            AddIndex(x) => format!("i += v{:x}", x),
+            Font(x) => format!("i := hex v{:x}", x),
+            BigFont(x) => format!("i := bighex v{:x}", x),
+            Bcd(x) => format!("bcd v{:x}", x),
+            Pitch(x) => format!("pitch := v{:x}", x),
+            Store(x) => format!("save v{:x}", x),
+            Load(x) => format!("load v{:x}", x),
+            SaveFlags(x) => format!("saveflags v{:x}", x),
+            LoadFlags(x) => format!("loadflags v{:x}", x),
+        }
+    }
+}
+
+/// Writes the instruction in the Cowgod syntax.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.to_text(Syntax::Cowgod, &BTreeMap::new()))
+    }
+}
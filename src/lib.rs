@@ -0,0 +1,52 @@
+//! CHIP-8 interpreter core.
+//!
+//! This crate contains the emulated machine without any renderer: front ends drive a
+//! [`Machine`](struct.Machine.html) and decide themselves how to present the screen and where the
+//! keypad state comes from.
+
+extern crate rand;
+
+pub mod assembler;
+pub mod audio;
+pub mod cfg;
+pub mod chip8;
+pub mod debugger;
+pub mod disassembler;
+pub mod error;
+pub mod hash;
+pub mod instruction;
+pub mod lint;
+mod machine;
+pub mod movie;
+pub mod octo;
+pub mod platform;
+pub mod profiler;
+pub mod quirks;
+pub mod rewind;
+pub mod rng;
+pub mod savestate;
+pub mod scheduler;
+pub mod screen;
+pub mod timing;
+pub mod trace;
+pub mod watchpoint;
+
+pub use assembler::{Assembler, AssemblerError};
+pub use audio::{Tone, Waveform};
+pub use cfg::ControlFlowGraph;
+pub use chip8::{MachineState, State, DEFAULT_LOAD_ADDRESS, ETI_660_LOAD_ADDRESS};
+pub use debugger::Debugger;
+pub use disassembler::Disassembly;
+pub use error::Chip8Error;
+pub use instruction::{Instruction, Syntax};
+pub use machine::{Machine, DEFAULT_INSTRUCTIONS_PER_FRAME};
+pub use platform::Platform;
+pub use profiler::Profile;
+pub use quirks::{IndexIncrement, Quirks};
+pub use rewind::RewindBuffer;
+pub use rng::{CosmacVipRandom, RandomSource, SeededRandom, ThreadRandom};
+pub use savestate::SaveStateError;
+pub use screen::Screen;
+pub use timing::TimingMode;
+pub use trace::{TraceEntry, TraceFilter};
+pub use watchpoint::{WatchHit, WatchKind, Watchpoint};
@@ -0,0 +1,430 @@
+use cfg::{ControlFlowGraph, EdgeKind};
+use chip8::FONT_AREA_END;
+use instruction::Instruction;
+use platform::Platform;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+// Return addresses the CHIP-8 stack holds
+const STACK_SIZE: usize = 16;
+
+// From the smallest instruction set to the largest
+const PLATFORMS: [Platform; 3] = [Platform::Chip8, Platform::SuperChip, Platform::XoChip];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Something that may be intended, like a jump to an interpreter routine.
+    Warning,
+
+    /// Something that fails or misbehaves when it is executed.
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+/// A problem found at the address of a reachable instruction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Issue {
+    pub address: u16,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:#05x}: {}: {}",
+            self.address, self.severity, self.message
+        )
+    }
+}
+
+/// What `check` found in a ROM.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Report {
+    /// Smallest platform with every reachable instruction.
+    pub platform: Platform,
+
+    /// The first reachable instruction that needs `platform`, `None` for plain CHIP-8 programs.
+    pub platform_needed_by: Option<(u16, Instruction)>,
+
+    /// Deepest nesting of calls from the entry point, `None` when subroutines can call themselves.
+    pub call_depth: Option<usize>,
+
+    /// Issues by address.
+    pub issues: Vec<Issue>,
+}
+
+impl Report {
+    pub fn errors(&self) -> usize {
+        self.count(Severity::Error)
+    }
+
+    pub fn warnings(&self) -> usize {
+        self.count(Severity::Warning)
+    }
+
+    fn count(&self, severity: Severity) -> usize {
+        self.issues
+            .iter()
+            .filter(|issue| issue.severity == severity)
+            .count()
+    }
+}
+
+/// Statically checks the code reachable from the load address of a ROM (see `ControlFlowGraph`)
+/// without running it. Reports invalid opcodes, jumps and calls into the font area or outside
+/// the ROM, nested calls overflowing the 16 entry stack and sprite, BCD and register accesses
+/// through an `Annn` address running past the end of memory.
+///
+/// With a `platform`, instructions it does not have are errors. Without one, the memory size is
+/// the one of the smallest platform running the ROM.
+pub fn check(rom: &[u8], load_address: u16, platform: Option<Platform>) -> Report {
+    // The largest instruction set follows every path
+    let graph = ControlFlowGraph::new(rom, load_address, Platform::XoChip);
+    let mut issues = Vec::new();
+    let mut error = |address, message| {
+        issues.push(Issue {
+            address,
+            severity: Severity::Error,
+            message,
+        })
+    };
+
+    for &address in &graph.invalid {
+        let offset = (address - load_address) as usize;
+        let opcode = match rom.get(offset + 1) {
+            Some(&low) => format!("{:04x}", (rom[offset] as u16) << 8 | low as u16),
+            None => format!("{:02x}", rom[offset]),
+        };
+        error(address, format!("invalid opcode {}", opcode));
+    }
+
+    let mut required = Platform::Chip8;
+    let mut platform_needed_by = None;
+    for (&address, &instruction) in &graph.instructions {
+        let bytes = &rom[(address - load_address) as usize..];
+        let available = |platform| Instruction::decode(bytes, platform) == Some(instruction);
+        let minimum = PLATFORMS
+            .iter()
+            .cloned()
+            .find(|&platform| available(platform))
+            .unwrap_or(Platform::XoChip);
+        if rank(minimum) > rank(required) {
+            required = minimum;
+            platform_needed_by = Some((address, instruction));
+        }
+        if let Some(platform) = platform {
+            if !available(platform) {
+                error(
+                    address,
+                    format!(
+                        "{} needs the {} platform, not {}",
+                        instruction,
+                        minimum.name(),
+                        platform.name()
+                    ),
+                );
+            }
+        }
+    }
+
+    let memory_size = platform.unwrap_or(required).memory_size();
+    check_targets(&graph, rom.len(), &mut issues);
+    check_memory_accesses(&graph, memory_size, &mut issues);
+    let call_depth = check_call_depth(&graph, &mut issues);
+
+    issues.sort_by_key(|issue| issue.address);
+    Report {
+        platform: required,
+        platform_needed_by,
+        call_depth,
+        issues,
+    }
+}
+
+fn rank(platform: Platform) -> usize {
+    PLATFORMS.iter().position(|&p| p == platform).unwrap()
+}
+
+// Jumps and calls into the fonts, before the ROM or past its end
+fn check_targets(graph: &ControlFlowGraph, rom_size: usize, issues: &mut Vec<Issue>) {
+    let rom_end = graph.entry as usize + rom_size;
+    for block in graph.blocks.values() {
+        let &(address, instruction) = block.instructions.last().unwrap();
+        for edge in &block.successors {
+            let target = edge.target;
+            let kind = match edge.kind {
+                EdgeKind::Jump => "jump",
+                EdgeKind::Call => "call",
+                EdgeKind::Next | EdgeKind::Skip => "execution",
+            };
+            let (severity, message) = if target < FONT_AREA_END {
+                (
+                    Severity::Error,
+                    format!(
+                        "{} into the font area (0x000-{:#05x}) at {:#05x}",
+                        kind,
+                        FONT_AREA_END - 1,
+                        target
+                    ),
+                )
+            } else if target < graph.entry {
+                (
+                    Severity::Warning,
+                    format!(
+                        "{} to {:#05x}, before the ROM, where the interpreter was",
+                        kind, target
+                    ),
+                )
+            } else if target as usize >= rom_end {
+                let message = match edge.kind {
+                    EdgeKind::Next | EdgeKind::Skip => {
+                        format!(
+                            "execution runs past the end of the ROM after {}",
+                            instruction
+                        )
+                    }
+                    _ => format!("{} past the end of the ROM to {:#05x}", kind, target),
+                };
+                (Severity::Warning, message)
+            } else {
+                continue;
+            };
+            issues.push(Issue {
+                address,
+                severity,
+                message,
+            });
+        }
+    }
+}
+
+// Memory read or written from I by the instructions whose I is known, set by an Annn earlier in
+// the same block
+fn check_memory_accesses(graph: &ControlFlowGraph, memory_size: usize, issues: &mut Vec<Issue>) {
+    use self::Instruction::*;
+    for block in graph.blocks.values() {
+        let mut index: Option<u16> = None;
+        for &(address, instruction) in &block.instructions {
+            let access = match instruction {
+                LoadIndex(address) | LoadIndexLong(address) => {
+                    index = Some(address);
+                    continue;
+                }
+                AddIndex(_) | Font(_) | BigFont(_) => {
+                    index = None;
+                    continue;
+                }
+                Draw(_, _, 0) => Some(("a 16x16 sprite reads", 32)),
+                Draw(_, _, n) => Some(("a sprite reads", n as usize)),
+                Bcd(_) => Some(("BCD writes", 3)),
+                Store(x) => Some(("saving registers writes", x as usize + 1)),
+                Load(x) => Some(("loading registers reads", x as usize + 1)),
+                SaveRange(x, y) => Some(("saving registers writes", x.abs_diff(y) as usize + 1)),
+                LoadRange(x, y) => Some(("loading registers reads", x.abs_diff(y) as usize + 1)),
+                _ => None,
+            };
+            if let (Some((what, size)), Some(start)) = (access, index) {
+                if start as usize + size > memory_size {
+                    issues.push(Issue {
+                        address,
+                        severity: Severity::Error,
+                        message: format!(
+                            "{} {} bytes from I={:#05x}, past the end of memory at {:#x}",
+                            what,
+                            size,
+                            start,
+                            memory_size - 1
+                        ),
+                    });
+                }
+            }
+            // Depending on the quirks, saving and loading increment I
+            if let Store(_) | Load(_) = instruction {
+                index = None;
+            }
+        }
+    }
+}
+
+// Longest chain of nested calls from the entry point, reporting chains the stack can not hold
+// and recursive subroutines
+fn check_call_depth(graph: &ControlFlowGraph, issues: &mut Vec<Issue>) -> Option<usize> {
+    // Subroutines called by each function, following its code up to the returns
+    let mut callees: BTreeMap<u16, BTreeSet<u16>> = BTreeMap::new();
+    let mut functions = vec![graph.entry];
+    while let Some(function) = functions.pop() {
+        if callees.contains_key(&function) {
+            continue;
+        }
+        let mut called = BTreeSet::new();
+        let mut visited = BTreeSet::new();
+        let mut pending = vec![function];
+        while let Some(start) = pending.pop() {
+            let block = match graph.blocks.get(&start) {
+                Some(block) if visited.insert(start) => block,
+                _ => continue,
+            };
+            for edge in &block.successors {
+                if edge.kind == EdgeKind::Call {
+                    if graph.blocks.contains_key(&edge.target) {
+                        called.insert(edge.target);
+                    }
+                } else {
+                    pending.push(edge.target);
+                }
+            }
+        }
+        functions.extend(called.iter().cloned());
+        callees.insert(function, called);
+    }
+
+    let mut chains = BTreeMap::new();
+    let mut recursive = BTreeSet::new();
+    let chain = call_chain(
+        graph.entry,
+        &callees,
+        &mut chains,
+        &mut Vec::new(),
+        &mut recursive,
+    );
+    for &function in &recursive {
+        issues.push(Issue {
+            address: function,
+            severity: Severity::Warning,
+            message: "recursive subroutine, the depth of the stack can not be checked".to_string(),
+        });
+    }
+
+    let chain = chain?;
+    if chain.len() > STACK_SIZE {
+        let path: Vec<String> = chain
+            .iter()
+            .map(|address| format!("{:#05x}", address))
+            .collect();
+        issues.push(Issue {
+            address: graph.entry,
+            severity: Severity::Error,
+            message: format!(
+                "calls nest {} deep but the stack holds {} return addresses: {}",
+                chain.len(),
+                STACK_SIZE,
+                path.join(" > ")
+            ),
+        });
+    }
+    Some(chain.len())
+}
+
+// The subroutines of the longest chain of calls from the function, None if one of them can call
+// itself
+fn call_chain(
+    function: u16,
+    callees: &BTreeMap<u16, BTreeSet<u16>>,
+    chains: &mut BTreeMap<u16, Option<Vec<u16>>>,
+    active: &mut Vec<u16>,
+    recursive: &mut BTreeSet<u16>,
+) -> Option<Vec<u16>> {
+    if let Some(chain) = chains.get(&function) {
+        return chain.clone();
+    }
+    if active.contains(&function) {
+        recursive.insert(function);
+        return None;
+    }
+
+    active.push(function);
+    let mut longest = Some(Vec::new());
+    for &callee in &callees[&function] {
+        match call_chain(callee, callees, chains, active, recursive) {
+            Some(chain) => {
+                if let Some(ref mut longest) = longest {
+                    if chain.len() + 1 > longest.len() {
+                        *longest = vec![callee];
+                        longest.extend(chain);
+                    }
+                }
+            }
+            None => longest = None,
+        }
+    }
+    active.pop();
+    chains.insert(function, longest.clone());
+    longest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8::DEFAULT_LOAD_ADDRESS;
+
+    // The entry point calls the first of `depth` subroutines, each calling the next one
+    fn nested_calls(depth: usize) -> Vec<u8> {
+        let mut rom = vec![0x22, 0x04, 0x12, 0x02];
+        for level in 0..depth {
+            let next = DEFAULT_LOAD_ADDRESS as usize + 4 + (level + 1) * 4;
+            if level + 1 < depth {
+                rom.extend_from_slice(&[0x20 | (next >> 8) as u8, next as u8]);
+            } else {
+                rom.extend_from_slice(&[0x00, 0xE0]);
+            }
+            rom.extend_from_slice(&[0x00, 0xEE]);
+        }
+        rom
+    }
+
+    #[test]
+    fn calls_the_stack_can_hold_are_fine() {
+        let report = check(&nested_calls(16), DEFAULT_LOAD_ADDRESS, None);
+        assert_eq!(report.call_depth, Some(16));
+        assert_eq!(report.issues, []);
+        assert_eq!(report.platform, Platform::Chip8);
+    }
+
+    #[test]
+    fn calls_nesting_deeper_than_the_stack_are_errors() {
+        let report = check(&nested_calls(17), DEFAULT_LOAD_ADDRESS, None);
+        assert_eq!(report.call_depth, Some(17));
+        assert_eq!(report.errors(), 1);
+        assert_eq!(report.issues[0].address, DEFAULT_LOAD_ADDRESS);
+        assert!(report.issues[0]
+            .message
+            .starts_with("calls nest 17 deep but the stack holds 16 return addresses: 0x204 > "));
+    }
+
+    #[test]
+    fn recursive_subroutines_are_warnings() {
+        // 0x204 calls 0x208, which calls 0x204 back
+        let rom = [
+            0x22, 0x04, 0x12, 0x02, 0x22, 0x08, 0x00, 0xEE, 0x22, 0x04, 0x00, 0xEE,
+        ];
+        let report = check(&rom, DEFAULT_LOAD_ADDRESS, None);
+        assert_eq!(report.call_depth, None);
+        assert_eq!(report.errors(), 0);
+        assert_eq!(report.warnings(), 1);
+        assert_eq!(report.issues[0].address, 0x204);
+        assert!(report.issues[0].message.starts_with("recursive subroutine"));
+    }
+
+    #[test]
+    fn instructions_of_larger_platforms_are_reported() {
+        // 00FF (high resolution) needs SUPER-CHIP
+        let rom = [0x00, 0xFF, 0x12, 0x02];
+        let report = check(&rom, DEFAULT_LOAD_ADDRESS, None);
+        assert_eq!(report.platform, Platform::SuperChip);
+        assert_eq!(report.errors(), 0);
+
+        let report = check(&rom, DEFAULT_LOAD_ADDRESS, Some(Platform::Chip8));
+        assert_eq!(report.errors(), 1);
+        assert_eq!(report.issues[0].address, 0x200);
+    }
+}
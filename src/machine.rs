@@ -0,0 +1,476 @@
+use audio::{PatternPlayer, Tone, ToneGenerator, PATTERN_SIZE};
+use chip8::{MachineState, State};
+use error::Chip8Error;
+use hash::fnv1a;
+use platform::Platform;
+use profiler::Profile;
+use quirks::Quirks;
+use rng::RandomSource;
+use savestate::{self, SaveStateError};
+use screen::Screen;
+use std::io::Read;
+use std::mem;
+use timing::{TimingMode, VIP_CYCLES_PER_FRAME};
+use trace::{TraceEntry, TraceFilter};
+use watchpoint::{WatchHit, WatchKind, Watchpoint};
+
+/// Number of instructions `run_frame` executes per 60 Hz frame unless configured otherwise.
+pub const DEFAULT_INSTRUCTIONS_PER_FRAME: usize = 8;
+
+/// A headless CHIP-8 machine.
+///
+/// `Machine` wraps the interpreter `State` and exposes everything a front end, a tool or a test
+/// needs: loading a ROM, executing single instructions or whole frames, feeding the keypad and
+/// inspecting registers, memory and the framebuffer. It does not know anything about how the
+/// screen is drawn or where the keyboard input comes from.
+pub struct Machine {
+    state: State,
+    instructions_per_frame: usize,
+    pattern_player: PatternPlayer,
+    tone: Tone,
+    tone_generator: ToneGenerator,
+    // Where the sound timer started or stopped running during the last frame: position in the
+    // frame from 0.0 to 1.0 and whether it runs from there on
+    sound_edges: Vec<(f64, bool)>,
+    next_watchpoint_id: usize,
+}
+
+impl Machine {
+    /// Creates a machine emulating `platform` with the ROM at `filename` loaded at 0x200.
+    pub fn new(filename: String, platform: Platform) -> Result<Machine, Chip8Error> {
+        Ok(Machine::from_state(State::new(filename, platform)?))
+    }
+
+    /// Creates a machine emulating `platform` with `rom` loaded at `load_address`, where execution
+    /// starts (usually `DEFAULT_LOAD_ADDRESS`, or `ETI_660_LOAD_ADDRESS` for ETI-660 programs).
+    ///
+    /// Fails with `Chip8Error::RomTooLarge` if the ROM does not fit in memory.
+    pub fn from_bytes(
+        rom: &[u8],
+        platform: Platform,
+        load_address: u16,
+    ) -> Result<Machine, Chip8Error> {
+        Ok(Machine::from_state(State::from_bytes(
+            rom,
+            platform,
+            load_address,
+        )?))
+    }
+
+    /// Like `from_bytes`, reading the whole ROM from `reader`.
+    pub fn from_reader<R: Read>(
+        reader: R,
+        platform: Platform,
+        load_address: u16,
+    ) -> Result<Machine, Chip8Error> {
+        Ok(Machine::from_state(State::from_reader(
+            reader,
+            platform,
+            load_address,
+        )?))
+    }
+
+    /// Wraps an already initialized interpreter state.
+    pub fn from_state(state: State) -> Machine {
+        Machine {
+            state,
+            instructions_per_frame: DEFAULT_INSTRUCTIONS_PER_FRAME,
+            pattern_player: PatternPlayer::new(),
+            tone: Tone::default(),
+            tone_generator: ToneGenerator::new(),
+            sound_edges: Vec::new(),
+            next_watchpoint_id: 1,
+        }
+    }
+
+    /// Executes a single instruction.
+    ///
+    /// While the machine is waiting for a key (`Fx0A`) nothing is executed and
+    /// `MachineState::WaitForKeyboard` is returned until `wait_key_press` is called.
+    pub fn step(&mut self) -> Result<MachineState, Chip8Error> {
+        self.state.execute_instruction()
+    }
+
+    /// Runs one 60 Hz frame followed by one timer tick.
+    ///
+    /// With free-running timing a frame is `instructions_per_frame` instructions. With COSMAC VIP
+    /// timing it lasts until the instructions have used up the machine cycles of a frame, a draw
+    /// stalls the machine until the next frame and the cycles overspent by the last instruction are
+    /// taken from the next one.
+    ///
+    /// Returns `MachineState::Exit` if the program executed `00FD`,
+    /// `MachineState::WaitForKeyboard` if the frame stopped on `Fx0A`, otherwise
+    /// `MachineState::Draw` with the final screen if any instruction drew during the frame. With the
+    /// display wait quirk the frame ends at the first draw.
+    pub fn run_frame(&mut self) -> Result<MachineState, Chip8Error> {
+        self.run_frame_until(|_| false)
+    }
+
+    /// Like `run_frame`, checking `stop` before every instruction. When it returns true the frame
+    /// is interrupted before executing the instruction at PC, the timers are not decremented and
+    /// `MachineState::Break` is returned. The next call starts a new frame.
+    ///
+    /// Watchpoint hits are only recorded (see `watch_hits`): the frame goes on and the timers tick
+    /// as usual unless `stop` looks at them, like `Debugger::should_break` does.
+    pub fn run_frame_until<F>(&mut self, mut stop: F) -> Result<MachineState, Chip8Error>
+    where
+        F: FnMut(&Machine) -> bool,
+    {
+        let mut result = MachineState::SuccessfulExecution;
+        let mut executed: usize = 0;
+        let start_cycle = self.state.cycles;
+        let vblank_cycle = (self.state.cycles / VIP_CYCLES_PER_FRAME + 1) * VIP_CYCLES_PER_FRAME;
+        self.sound_edges.clear();
+
+        loop {
+            let frame_finished = match self.state.timing {
+                TimingMode::FreeRunning => executed >= self.instructions_per_frame,
+                TimingMode::CosmacVip => self.state.cycles >= vblank_cycle,
+            };
+            if frame_finished {
+                break;
+            }
+            if stop(self) {
+                return Ok(MachineState::Break);
+            }
+
+            executed += 1;
+            let sound_on = self.state.sound_timer > 0;
+            let step = self.step()?;
+            let position = match self.state.timing {
+                TimingMode::FreeRunning => executed as f64 / self.instructions_per_frame as f64,
+                TimingMode::CosmacVip => {
+                    (self.state.cycles - start_cycle) as f64 / (vblank_cycle - start_cycle) as f64
+                }
+            };
+            self.record_sound_edge(sound_on, position);
+
+            match step {
+                MachineState::SuccessfulExecution | MachineState::Break => {}
+                MachineState::Draw(screen) => {
+                    result = MachineState::Draw(screen);
+                    if self.state.quirks.display_wait || self.state.timing == TimingMode::CosmacVip
+                    {
+                        break;
+                    }
+                }
+                MachineState::WaitForKeyboard => {
+                    result = MachineState::WaitForKeyboard;
+                    break;
+                }
+                MachineState::Exit => return Ok(MachineState::Exit),
+            }
+        }
+
+        // Stopping early idles the rest of the frame
+        if self.state.timing == TimingMode::CosmacVip && self.state.cycles < vblank_cycle {
+            self.state.cycles = vblank_cycle;
+        }
+
+        let sound_on = self.state.sound_timer > 0;
+        self.state.decrement_timers();
+        self.record_sound_edge(sound_on, 1.0);
+        Ok(result)
+    }
+
+    fn record_sound_edge(&mut self, was_on: bool, position: f64) {
+        let on = self.state.sound_timer > 0;
+        if on != was_on {
+            self.sound_edges.push((position.min(1.0), on));
+        }
+    }
+
+    /// Sets the state of the whole keypad, one bit per key.
+    pub fn set_keys_pressed(&mut self, keys: u16) {
+        self.state.set_keys_pressed(keys);
+    }
+
+    /// Delivers the key an `Fx0A` instruction is waiting for.
+    pub fn wait_key_press(&mut self, key: u8) {
+        self.state.wait_key_press(key);
+    }
+
+    /// Decrements the delay and sound timers by one tick.
+    pub fn decrement_timers(&mut self) {
+        self.state.decrement_timers();
+    }
+
+    /// Sets the tone `render_audio` plays for the sound timer of CHIP-8 and SUPER-CHIP programs.
+    pub fn set_tone(&mut self, tone: Tone) {
+        self.tone = tone;
+    }
+
+    /// Renders the sound of the last frame as mono PCM samples in [-1.0, 1.0].
+    ///
+    /// On XO-CHIP the audio pattern is played at the pitch register rate while the sound timer is
+    /// non-zero. On the other platforms the tone set with `set_tone` is played while the sound
+    /// timer is non-zero, with edges faded over a few samples (see `ToneGenerator`). Otherwise the
+    /// buffer is filled with silence.
+    ///
+    /// The sound starts and stops at the sample matching the point of the frame where the timer
+    /// started or ran out: the instruction that set it (in instructions or in machine cycles,
+    /// depending on the timing) or the timer tick at the end of the frame. Calling this after
+    /// every `run_frame` with `sample_rate / 60` samples produces a continuous stream, which makes
+    /// it usable both to feed an audio device and to inspect the output of headless runs.
+    pub fn render_audio(&mut self, sample_rate: u32, out: &mut [f32]) {
+        let edges = mem::take(&mut self.sound_edges);
+        // Before the first change the sound was in the opposite state
+        let mut on = match edges.first() {
+            Some(&(_, on)) => !on,
+            None => self.state.sound_timer > 0,
+        };
+        let mut start = 0;
+        for (position, next) in edges {
+            let end = ((position * out.len() as f64).round() as usize).clamp(start, out.len());
+            self.render_sound(on, sample_rate, &mut out[start..end]);
+            start = end;
+            on = next;
+        }
+        self.render_sound(on, sample_rate, &mut out[start..]);
+    }
+
+    fn render_sound(&mut self, on: bool, sample_rate: u32, out: &mut [f32]) {
+        if !self.state.platform.has_xo_chip() {
+            self.tone_generator.render(self.tone, on, sample_rate, out);
+        } else if on {
+            self.pattern_player.render(
+                &self.state.audio_pattern,
+                self.state.pitch,
+                sample_rate,
+                out,
+            );
+        } else {
+            // Sound restarts from the beginning of the pattern
+            self.pattern_player.reset();
+            for sample in out.iter_mut() {
+                *sample = 0.0;
+            }
+        }
+    }
+
+    /// Watches the memory from `start` to `end` inclusive and returns the number of the new
+    /// watchpoint.
+    pub fn add_watchpoint(&mut self, start: u16, end: u16, kind: WatchKind) -> usize {
+        let id = self.next_watchpoint_id;
+        self.next_watchpoint_id += 1;
+        self.state.watchpoints.push(Watchpoint {
+            id,
+            start,
+            end,
+            kind,
+        });
+        id
+    }
+
+    /// Removes the watchpoint with number `id`. Returns false if there is none.
+    pub fn remove_watchpoint(&mut self, id: usize) -> bool {
+        let count = self.state.watchpoints.len();
+        self.state
+            .watchpoints
+            .retain(|watchpoint| watchpoint.id != id);
+        self.state.watchpoints.len() != count
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.state.watchpoints
+    }
+
+    /// The accesses that triggered a watchpoint since the last `take_watch_hits`, in the order they
+    /// happened.
+    pub fn watch_hits(&self) -> &[WatchHit] {
+        &self.state.watch_hits
+    }
+
+    /// Returns and forgets the accesses that triggered a watchpoint, in the order they happened.
+    pub fn take_watch_hits(&mut self) -> Vec<WatchHit> {
+        mem::take(&mut self.state.watch_hits)
+    }
+
+    /// Starts recording the executed instructions selected by `filter`, or stops with `None`.
+    pub fn set_trace(&mut self, filter: Option<TraceFilter>) {
+        self.state.trace_filter = filter;
+    }
+
+    /// Returns and forgets the instructions recorded since tracing started or since the last
+    /// call, in the order they were executed.
+    pub fn take_trace(&mut self) -> Vec<TraceEntry> {
+        mem::take(&mut self.state.trace)
+    }
+
+    /// Starts counting the executed instructions in a new profile, with the code at PC as the
+    /// entry point, or stops and drops the profile.
+    pub fn set_profiling(&mut self, enabled: bool) {
+        self.state.profile = if enabled {
+            Some(Profile::new(self.state.pc))
+        } else {
+            None
+        };
+    }
+
+    /// The profile counted since profiling started, if it is enabled.
+    pub fn profile(&self) -> Option<&Profile> {
+        self.state.profile.as_ref()
+    }
+
+    /// Snapshot of the whole machine state, to be restored later with `load_state`.
+    ///
+    /// The snapshot is tied to the loaded ROM and platform. The quirks, the timing mode and the
+    /// instructions per frame are configuration and are not saved.
+    pub fn save_state(&self) -> Vec<u8> {
+        savestate::save(&self.state)
+    }
+
+    /// Restores a snapshot taken with `save_state`.
+    ///
+    /// Snapshots of a different ROM or platform, from an incompatible version or damaged are
+    /// rejected and leave the machine untouched.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), SaveStateError> {
+        savestate::load(&mut self.state, data)?;
+        self.pattern_player.reset();
+        Ok(())
+    }
+
+    /// Hash of the whole machine state, to check that two runs are in the same state.
+    pub fn checksum(&self) -> u64 {
+        fnv1a(&self.save_state())
+    }
+
+    /// FNV-1a hash of the loaded ROM.
+    pub fn rom_hash(&self) -> u64 {
+        self.state.rom_hash
+    }
+
+    /// XO-CHIP audio pattern loaded by `F002`.
+    pub fn audio_pattern(&self) -> &[u8; PATTERN_SIZE] {
+        &self.state.audio_pattern
+    }
+
+    /// XO-CHIP pitch register set by `Fx3A`.
+    pub fn pitch(&self) -> u8 {
+        self.state.pitch
+    }
+
+    pub fn platform(&self) -> Platform {
+        self.state.platform
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.state.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.state.quirks = quirks;
+    }
+
+    /// Replaces the source of the random numbers returned by `Cxkk`. Use a `SeededRandom` to make
+    /// runs reproducible.
+    pub fn set_rng(&mut self, rng: Box<dyn RandomSource>) {
+        self.state.rng = rng;
+    }
+
+    pub fn timing(&self) -> TimingMode {
+        self.state.timing
+    }
+
+    pub fn set_timing(&mut self, timing: TimingMode) {
+        self.state.timing = timing;
+    }
+
+    /// Machine cycles executed under COSMAC VIP timing.
+    pub fn cycles(&self) -> u64 {
+        self.state.cycles
+    }
+
+    pub fn instructions_per_frame(&self) -> usize {
+        self.instructions_per_frame
+    }
+
+    pub fn set_instructions_per_frame(&mut self, instructions: usize) {
+        self.instructions_per_frame = instructions;
+    }
+
+    /// General purpose registers V0 to VF.
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.state.registers
+    }
+
+    /// Sets register Vx, `x` goes from 0x0 to 0xF.
+    pub fn set_register(&mut self, x: usize, value: u8) {
+        self.state.registers[x] = value;
+    }
+
+    /// Index register I.
+    pub fn index(&self) -> u16 {
+        self.state.index
+    }
+
+    pub fn set_index(&mut self, index: u16) {
+        self.state.index = index;
+    }
+
+    /// Address the ROM was loaded at.
+    pub fn load_address(&self) -> u16 {
+        self.state.load_address
+    }
+
+    /// Program counter.
+    pub fn pc(&self) -> u16 {
+        self.state.pc
+    }
+
+    /// Moves the program counter, execution continues at `pc`.
+    pub fn set_pc(&mut self, pc: u16) {
+        self.state.pc = pc;
+    }
+
+    /// Stack pointer, the number of return addresses currently on the stack.
+    pub fn sp(&self) -> usize {
+        self.state.sp
+    }
+
+    /// Call stack. Only the first `sp()` entries are meaningful.
+    pub fn stack(&self) -> &[u16; 16] {
+        &self.state.stack
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.state.delay_timer
+    }
+
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.state.delay_timer = value;
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.state.sound_timer
+    }
+
+    pub fn set_sound_timer(&mut self, value: u8) {
+        self.state.sound_timer = value;
+    }
+
+    /// Keypad state, one bit per key.
+    pub fn keypad(&self) -> u16 {
+        self.state.keypad
+    }
+
+    /// Whether an `Fx0A` instruction is waiting for a key.
+    pub fn is_waiting_key(&self) -> bool {
+        self.state.waiting_key
+    }
+
+    /// Framebuffer, 64x32 or 128x64 in SUPER-CHIP high resolution mode.
+    pub fn screen(&self) -> &Screen {
+        &self.state.screen
+    }
+
+    /// Main memory, 4 KiB or 64 KiB on XO-CHIP.
+    pub fn memory(&self) -> &[u8] {
+        &self.state.memory
+    }
+
+    /// Main memory, writable. Changes to the program are picked up by the next instruction.
+    pub fn memory_mut(&mut self) -> &mut [u8] {
+        &mut self.state.memory
+    }
+}
@@ -1,25 +1,84 @@
+extern crate chip8emu;
 extern crate clap;
 extern crate linux_raw_input_rs;
-extern crate rand;
 extern crate sdl2;
 extern crate termion;
 
-use std::time::Instant;
-use std::{thread, time};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use clap::{App, Arg};
+use clap::{App, Arg, ArgMatches};
 
-mod chip8;
-use chip8::MachineState;
+use chip8emu::audio::DEFAULT_TONE_FREQUENCY;
+use chip8emu::debugger::DebuggerAction;
+use chip8emu::lint;
+use chip8emu::movie::{Movie, MovieError, MovieHeader, MovieInput, MovieRng};
+use chip8emu::octo;
+use chip8emu::scheduler::{instructions_per_frame, FrameScheduler, FRAME_RATE};
+use chip8emu::trace;
+use chip8emu::{
+    Assembler, ControlFlowGraph, CosmacVipRandom, Debugger, Disassembly, Machine, MachineState,
+    Platform, Quirks, RewindBuffer, SeededRandom, Syntax, TimingMode, Tone, TraceFilter, WatchKind,
+    Waveform, DEFAULT_INSTRUCTIONS_PER_FRAME, DEFAULT_LOAD_ADDRESS,
+};
 
 mod renderers;
+use renderers::audio::wav_audio::WavAudio;
+use renderers::audio::DEFAULT_VOLUME;
+use renderers::graphics::palette::{Palette, PRESET_NAMES};
 use renderers::input::KeyboardCommand;
 use renderers::{get_renders, Renderer};
 
-static TIMERS_DECREMENT_FRACTION: u128 = 1000 / 60;
-
 static ARG_GAME: &str = "arg_game";
 static ARG_RENDERER: &str = "arg_renderer";
+static ARG_QUIRKS: &str = "arg_quirks";
+static ARG_PLATFORM: &str = "arg_platform";
+static ARG_LOAD_ADDRESS: &str = "arg_load_address";
+static ARG_SEED: &str = "arg_seed";
+static ARG_VIP_RND: &str = "arg_vip_rnd";
+static ARG_IPF: &str = "arg_ipf";
+static ARG_CLOCK_HZ: &str = "arg_clock_hz";
+static ARG_TIMING: &str = "arg_timing";
+static ARG_REWIND_SECONDS: &str = "arg_rewind_seconds";
+static ARG_RECORD: &str = "arg_record";
+static ARG_PLAY: &str = "arg_play";
+static ARG_DEBUG: &str = "arg_debug";
+static ARG_WATCH: &str = "arg_watch";
+static ARG_FRAMES: &str = "arg_frames";
+static ARG_TRACE: &str = "arg_trace";
+static ARG_TRACE_PC: &str = "arg_trace_pc";
+static ARG_TRACE_CLASS: &str = "arg_trace_class";
+static ARG_PROFILE: &str = "arg_profile";
+static ARG_PROFILE_JSON: &str = "arg_profile_json";
+static ARG_TONE: &str = "arg_tone";
+static ARG_WAVEFORM: &str = "arg_waveform";
+static ARG_VOLUME: &str = "arg_volume";
+static ARG_MUTE: &str = "arg_mute";
+static ARG_WAV: &str = "arg_wav";
+static ARG_PALETTE: &str = "arg_palette";
+static ARG_PALETTE_FILE: &str = "arg_palette_file";
+static ARG_LINEAR: &str = "arg_linear";
+static ARG_LEFT: &str = "arg_left";
+static ARG_RIGHT: &str = "arg_right";
+static ARG_ROM: &str = "arg_rom";
+static ARG_SYNTAX: &str = "arg_syntax";
+static ARG_SOURCE: &str = "arg_source";
+static ARG_OUTPUT: &str = "arg_output";
+static ARG_DEFINE: &str = "arg_define";
+static CMD_DISASM: &str = "disasm";
+static CMD_ASM: &str = "asm";
+static CMD_TRACE_DIFF: &str = "trace-diff";
+static CMD_CFG: &str = "cfg";
+static CMD_CHECK: &str = "check";
+// Seconds of gameplay that can be rewound unless configured otherwise
+const DEFAULT_REWIND_SECONDS: u32 = 10;
+// Addresses listed in the profile report
+const PROFILE_HOT_SPOTS: usize = 20;
+// Save state slots selectable with F6
+const SAVE_STATE_SLOTS: u8 = 10;
 static KEYMAPPING: &str = "
 Key mappings:
 
@@ -33,6 +92,9 @@ Key mappings:
 +-+-+-+-+                +-+-+-+-+
 |A|0|B|F|                |Z|X|C|V|
 +-+-+-+-+                +-+-+-+-+
+
+F5 saves the state to the current slot, F7 loads it and F6 selects the next slot (0 to 9).
+Holding Backspace rewinds the game. With --debug, F8 opens the debugger prompt.
 ";
 
 fn main() {
@@ -45,57 +107,989 @@ fn main() {
             .short('g')
             .long("game")
             .value_name("FILE")
-            .help("Path to the game")
+            .help("Path to the game. Octo sources (.8o) are compiled before running")
             .required(true)
             .takes_value(true))
         .arg(Arg::with_name(ARG_RENDERER)
             .short('r')
             .long("renderer")
-            .value_name("terminal | sdl")
-            .help("Render method to use. For terminal root privileges are needed (for reading keyboard input asynchronously)")
+            .value_name("terminal | sdl | headless")
+            .help("Render method to use. For terminal root privileges are needed (for reading keyboard input asynchronously). headless runs as fast as possible without display nor input")
+            .takes_value(true))
+        .arg(Arg::with_name(ARG_QUIRKS)
+            .short('q')
+            .long("quirks")
+            .value_name("vip | chip48 | schip | modern")
+            .help("Quirks profile used to interpret ambiguous opcodes. Default is modern")
+            .possible_values(Quirks::NAMES)
+            .takes_value(true))
+        .arg(platform_arg())
+        .arg(load_address_arg())
+        .arg(Arg::with_name(ARG_SEED)
+            .short('s')
+            .long("seed")
+            .value_name("NUMBER")
+            .help("Seed for the random numbers generated by Cxkk. The same seed always produces the same game")
+            .validator(|seed| seed.parse::<u64>().map_err(|_| format!("invalid seed: {}", seed)))
+            .takes_value(true))
+        .arg(Arg::with_name(ARG_VIP_RND)
+            .long("vip-rnd")
+            .help("Generate random numbers with the COSMAC VIP interpreter algorithm (seeded with --seed, 0 by default)"))
+        .arg(Arg::with_name(ARG_IPF)
+            .long("ipf")
+            .value_name("NUMBER")
+            .help(&*format!("Instructions executed per 60 Hz frame. Default is {}", DEFAULT_INSTRUCTIONS_PER_FRAME))
+            .validator(|ipf| match ipf.parse::<usize>() {
+                Ok(ipf) if ipf >= 1 => Ok(()),
+                _ => Err(format!("invalid number: {}, at least 1 instruction per frame is needed", ipf)),
+            })
+            .conflicts_with(ARG_CLOCK_HZ)
+            .takes_value(true))
+        .arg(Arg::with_name(ARG_CLOCK_HZ)
+            .long("clock-hz")
+            .value_name("HZ")
+            .help("Instructions executed per second, rounded to a whole number of instructions per frame")
+            .validator(|hz| hz.parse::<u32>().map_err(|_| format!("invalid frequency: {}", hz)))
+            .takes_value(true))
+        .arg(Arg::with_name(ARG_TIMING)
+            .short('t')
+            .long("timing")
+            .value_name("free | vip")
+            .help("Timing model. free runs a fixed number of instructions per frame, vip charges every instruction the machine cycles it took on the COSMAC VIP and makes draws wait for the vertical blank. Default is free")
+            .possible_values(TimingMode::NAMES)
+            .takes_value(true))
+        .arg(Arg::with_name(ARG_REWIND_SECONDS)
+            .long("rewind-seconds")
+            .value_name("SECONDS")
+            .help(&*format!("Seconds of gameplay kept to rewind with Backspace, 0 disables rewinding. Default is {}", DEFAULT_REWIND_SECONDS))
+            .validator(|seconds| seconds.parse::<u32>().map_err(|_| format!("invalid number: {}", seconds)))
+            .takes_value(true))
+        .arg(Arg::with_name(ARG_RECORD)
+            .long("record")
+            .value_name("FILE")
+            .help("Record the keypad input of every frame into a movie file. A random seed is chosen and printed if --seed is not given")
+            .conflicts_with(ARG_PLAY)
+            .takes_value(true))
+        .arg(Arg::with_name(ARG_PLAY)
+            .long("play")
+            .value_name("FILE")
+            .help("Replay a movie recorded with --record. The platform, load address, quirks, timing, speed and seed stored in the movie are used instead of the options")
+            .takes_value(true))
+        .arg(Arg::with_name(ARG_DEBUG)
+            .long("debug")
+            .help("Start in the debugger, a gdb-like prompt in the terminal. F8 brings the prompt back while the game runs"))
+        .arg(Arg::with_name(ARG_WATCH)
+            .long("watch")
+            .value_name("ADDR[-END][:r|w|rw]")
+            .help("Report the instructions that read or write (w by default) a memory range. In the debugger execution stops at every access. Can be given several times")
+            .validator(|watch| parse_watch(watch).map(|_| ()))
+            .multiple_occurrences(true)
+            .number_of_values(1)
+            .takes_value(true))
+        .arg(Arg::with_name(ARG_FRAMES)
+            .long("frames")
+            .value_name("NUMBER")
+            .help("Exit after running this many frames")
+            .validator(|frames| frames.parse::<usize>().map_err(|_| format!("invalid number: {}", frames)))
+            .takes_value(true))
+        .arg(Arg::with_name(ARG_TRACE)
+            .long("trace")
+            .value_name("FILE")
+            .help("Log every executed instruction with the registers before it: cycle, PC, opcode, V0-VF, I, SP, DT, ST and mnemonic, one per line")
+            .takes_value(true))
+        .arg(Arg::with_name(ARG_TRACE_PC)
+            .long("trace-pc")
+            .value_name("ADDR[-END]")
+            .help("Only trace the instructions in the address range. Can be given several times")
+            .requires(ARG_TRACE)
+            .validator(|range| parse_range(range).map(|_| ()))
+            .multiple_occurrences(true)
+            .number_of_values(1)
+            .takes_value(true))
+        .arg(Arg::with_name(ARG_TRACE_CLASS)
+            .long("trace-class")
+            .value_name("CLASSES")
+            .help("Only trace the opcodes whose first hexadecimal digit is in the comma separated list, e.g. D,F for draws and Fxkk instructions")
+            .requires(ARG_TRACE)
+            .validator(|classes| parse_classes(classes).map(|_| ()))
+            .takes_value(true))
+        .arg(Arg::with_name(ARG_PROFILE)
+            .long("profile")
+            .help("Count the instructions executed per address and opcode class and print the hot spots and the call graph at exit"))
+        .arg(Arg::with_name(ARG_PROFILE_JSON)
+            .long("profile-json")
+            .value_name("FILE")
+            .help("Count the instructions executed like --profile and export the counts as JSON at exit")
+            .takes_value(true))
+        .arg(Arg::with_name(ARG_TONE)
+            .long("tone")
+            .value_name("HZ")
+            .help(&*format!("Frequency of the tone played while the sound timer runs. Default is {} (XO-CHIP programs play their audio pattern instead)", DEFAULT_TONE_FREQUENCY))
+            .validator(|hz| match hz.parse::<f64>() {
+                Ok(hz) if hz > 0.0 && hz < 20000.0 => Ok(()),
+                _ => Err(format!("invalid frequency: {}", hz)),
+            })
+            .takes_value(true))
+        .arg(Arg::with_name(ARG_WAVEFORM)
+            .long("waveform")
+            .value_name("square | sine | triangle")
+            .help("Shape of the tone played while the sound timer runs. Default is square")
+            .possible_values(Waveform::NAMES)
             .takes_value(true))
+        .arg(Arg::with_name(ARG_VOLUME)
+            .long("volume")
+            .value_name("PERCENT")
+            .help(&*format!("Sound volume from 0 to 100. Default is {}", (DEFAULT_VOLUME * 100.0) as u32))
+            .validator(|volume| match volume.parse::<u32>() {
+                Ok(volume) if volume <= 100 => Ok(()),
+                _ => Err(format!("invalid volume: {}", volume)),
+            })
+            .takes_value(true))
+        .arg(Arg::with_name(ARG_MUTE)
+            .long("mute")
+            .help("Start with the sound muted. M mutes and unmutes it while the game runs"))
+        .arg(Arg::with_name(ARG_WAV)
+            .long("wav")
+            .value_name("FILE")
+            .help("Write the sound to a WAV file instead of playing it, e.g. with the headless renderer to check it offline")
+            .takes_value(true))
+        .arg(Arg::with_name(ARG_PALETTE)
+            .long("palette")
+            .value_name("PRESET | COLORS")
+            .help(&*format!("Screen colours: a preset ({}) or the background and foreground colours in hexadecimal, optionally followed by the XO-CHIP colours of plane 2 and both planes, e.g. 202020,ffb000. Default is white on black", PRESET_NAMES.join(", ")))
+            .validator(|palette| Palette::parse(palette).map(|_| ()))
+            .conflicts_with(ARG_PALETTE_FILE)
+            .takes_value(true))
+        .arg(Arg::with_name(ARG_PALETTE_FILE)
+            .long("palette-file")
+            .value_name("FILE")
+            .help("Read the screen colours from a file of name = value lines: preset, background, foreground, color2 and color3")
+            .takes_value(true))
+        .subcommand_negates_reqs(true)
+        .subcommand(App::new(CMD_DISASM)
+            .about("Print the disassembly of a ROM: address, bytes and mnemonic of every instruction, with labels for jump and call targets")
+            .arg(Arg::with_name(ARG_ROM)
+                .value_name("ROM")
+                .help("Path to the ROM")
+                .required(true)
+                .index(1))
+            .arg(platform_arg())
+            .arg(load_address_arg())
+            .arg(Arg::with_name(ARG_SYNTAX)
+                .long("syntax")
+                .value_name("cowgod | octo")
+                .help("Assembly syntax, Cowgod's mnemonics (LD V1, 0x20) or Octo (v1 := 0x20). Default is cowgod")
+                .possible_values(Syntax::NAMES)
+                .takes_value(true))
+            .arg(Arg::with_name(ARG_LINEAR)
+                .long("linear")
+                .help("Decode every two bytes as an instruction instead of only the code reachable from the load address, which shows data as instructions")))
+        .subcommand(App::new(CMD_CFG)
+            .about("Write the control flow graph of a ROM in the Graphviz DOT language")
+            .arg(Arg::with_name(ARG_ROM)
+                .value_name("ROM")
+                .help("Path to the ROM")
+                .required(true)
+                .index(1))
+            .arg(platform_arg())
+            .arg(load_address_arg())
+            .arg(Arg::with_name(ARG_OUTPUT)
+                .short('o')
+                .long("output")
+                .value_name("FILE")
+                .help("Where to write the graph. Default is the standard output")
+                .takes_value(true)))
+        .subcommand(App::new(CMD_CHECK)
+            .about("Check the code reachable in a ROM for invalid opcodes, jumps into the fonts, calls overflowing the stack and reads past the end of memory, and print the platform it needs")
+            .arg(Arg::with_name(ARG_ROM)
+                .value_name("ROM")
+                .help("Path to the ROM")
+                .required(true)
+                .index(1))
+            .arg(platform_arg()
+                .help("Platform the ROM must run on, its missing instructions are errors. Default is the smallest platform running the ROM"))
+            .arg(load_address_arg()))
+        .subcommand(App::new(CMD_ASM)
+            .about("Assemble a CHIPPER source, or compile an Octo source (.8o), into a ROM")
+            .arg(Arg::with_name(ARG_SOURCE)
+                .value_name("SOURCE")
+                .help("Path to the source")
+                .required(true)
+                .index(1))
+            .arg(Arg::with_name(ARG_OUTPUT)
+                .short('o')
+                .long("output")
+                .value_name("FILE")
+                .help("Where to write the ROM. Default is the source path with the .ch8 extension")
+                .takes_value(true))
+            .arg(Arg::with_name(ARG_DEFINE)
+                .short('D')
+                .long("define")
+                .value_name("SYMBOL")
+                .help("Define a symbol for IFDEF, e.g. -D SUPER to build the SUPER-CHIP version of BLINKY. Can be given several times")
+                .multiple_occurrences(true)
+                .number_of_values(1)
+                .takes_value(true)))
+        .subcommand(App::new(CMD_TRACE_DIFF)
+            .about("Compare two traces written with --trace and report the first instruction where the machine states differ")
+            .arg(Arg::with_name(ARG_LEFT)
+                .value_name("TRACE")
+                .help("Path to the first trace")
+                .required(true)
+                .index(1))
+            .arg(Arg::with_name(ARG_RIGHT)
+                .value_name("OTHER")
+                .help("Path to the trace to compare it with")
+                .required(true)
+                .index(2)))
         .get_matches();
 
+    if let Some(disasm) = matches.subcommand_matches(CMD_DISASM) {
+        run_disasm(disasm);
+        return;
+    }
+    if let Some(asm) = matches.subcommand_matches(CMD_ASM) {
+        run_asm(asm);
+        return;
+    }
+    if let Some(cfg) = matches.subcommand_matches(CMD_CFG) {
+        run_cfg(cfg);
+        return;
+    }
+    if let Some(check) = matches.subcommand_matches(CMD_CHECK) {
+        run_check(check);
+        return;
+    }
+    if let Some(trace_diff) = matches.subcommand_matches(CMD_TRACE_DIFF) {
+        run_trace_diff(trace_diff);
+        return;
+    }
+
     let game_file = matches.value_of(ARG_GAME).unwrap();
     let renderer_arg = matches.value_of(ARG_RENDERER).unwrap_or("sdl");
+    let quirks_arg = matches.value_of(ARG_QUIRKS).unwrap_or("modern");
+    let platform = platform_of(&matches);
+    let load_address = load_address_of(&matches);
+
+    // Initialize chip8 machine
+    let rom = read_rom(game_file).and_then(|rom| {
+        Machine::from_bytes(&rom, platform, load_address)
+            .map(|vm| (rom, vm))
+            .map_err(|error| error.to_string())
+    });
+    let (rom, mut vm) = match rom {
+        Ok(loaded) => loaded,
+        Err(error) => {
+            eprintln!("Critical error: {}", error);
+            process::exit(1);
+        }
+    };
+    vm.set_quirks(Quirks::from_name(quirks_arg).unwrap());
+
+    let mut seed: Option<u64> = matches.value_of(ARG_SEED).map(|seed| seed.parse().unwrap());
+    if matches.is_present(ARG_RECORD) && seed.is_none() && !matches.is_present(ARG_VIP_RND) {
+        // Movies need reproducible random numbers
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        seed = Some(now.as_secs() ^ now.subsec_nanos() as u64);
+        eprintln!("Recording with seed {}", seed.unwrap());
+    }
+    let rng = if matches.is_present(ARG_VIP_RND) {
+        let seed = seed.unwrap_or(0) as u16;
+        vm.set_rng(Box::new(CosmacVipRandom::new(seed)));
+        Some(MovieRng::CosmacVip(seed))
+    } else if let Some(seed) = seed {
+        vm.set_rng(Box::new(SeededRandom::new(seed)));
+        Some(MovieRng::Seeded(seed))
+    } else {
+        None
+    };
+
+    if let Some(ipf) = matches.value_of(ARG_IPF) {
+        vm.set_instructions_per_frame(ipf.parse().unwrap());
+    } else if let Some(clock_hz) = matches.value_of(ARG_CLOCK_HZ) {
+        vm.set_instructions_per_frame(instructions_per_frame(clock_hz.parse().unwrap()));
+    }
+
+    let timing_arg = matches.value_of(ARG_TIMING).unwrap_or("free");
+    vm.set_timing(TimingMode::from_name(timing_arg).unwrap());
+
+    let rewind_seconds = matches
+        .value_of(ARG_REWIND_SECONDS)
+        .map_or(DEFAULT_REWIND_SECONDS, |seconds| seconds.parse().unwrap());
+    let rewind = RewindBuffer::new(rewind_seconds as usize * FRAME_RATE as usize);
+
+    let movie = if let Some(movie_file) = matches.value_of(ARG_RECORD) {
+        let header = MovieHeader::from_machine(&vm, rng.unwrap());
+        MovieMode::Record(Movie::new(header), movie_file.to_string())
+    } else if let Some(movie_file) = matches.value_of(ARG_PLAY) {
+        // The machine is recreated with the configuration the movie was recorded with
+        let movie = File::open(movie_file)
+            .map_err(MovieError::from)
+            .and_then(|file| Movie::read(BufReader::new(file)));
+        let replay = movie.and_then(|movie| {
+            vm = movie.header.create_machine(&rom)?;
+            Ok(movie)
+        });
+        match replay {
+            Ok(movie) => MovieMode::Play(movie),
+            Err(error) => {
+                eprintln!("Critical error: {}", error);
+                process::exit(1);
+            }
+        }
+    } else {
+        MovieMode::Off
+    };
+
+    vm.set_tone(Tone {
+        frequency: matches
+            .value_of(ARG_TONE)
+            .map_or(DEFAULT_TONE_FREQUENCY, |hz| hz.parse().unwrap()),
+        waveform: Waveform::from_name(matches.value_of(ARG_WAVEFORM).unwrap_or("square")).unwrap(),
+    });
+
+    for watch in matches.values_of(ARG_WATCH).into_iter().flatten() {
+        let (start, end, kind) = parse_watch(watch).unwrap();
+        vm.add_watchpoint(start, end, kind);
+    }
+
+    let trace = matches.value_of(ARG_TRACE).map(|trace_file| {
+        let filter = TraceFilter {
+            ranges: matches
+                .values_of(ARG_TRACE_PC)
+                .into_iter()
+                .flatten()
+                .map(|range| parse_range(range).unwrap())
+                .collect(),
+            classes: matches
+                .value_of(ARG_TRACE_CLASS)
+                .map_or_else(Vec::new, |classes| parse_classes(classes).unwrap()),
+        };
+        vm.set_trace(Some(filter));
+        match File::create(trace_file) {
+            Ok(file) => (BufWriter::new(file), trace_file.to_string()),
+            Err(error) => {
+                eprintln!("Critical error: could not create {}: {}", trace_file, error);
+                process::exit(1);
+            }
+        }
+    });
+
+    if matches.is_present(ARG_PROFILE) || matches.is_present(ARG_PROFILE_JSON) {
+        vm.set_profiling(true);
+    }
 
-    // Initialize chip8 state
-    let vm = chip8::State::new(game_file.to_string()).unwrap();
+    let debugger = if matches.is_present(ARG_DEBUG) {
+        println!("Type help for the list of debugger commands");
+        let mut debugger = Debugger::new();
+        debugger.set_recording(matches!(movie, MovieMode::Record(..)));
+        Some(debugger)
+    } else {
+        None
+    };
 
     // Initialize graphics and input;
-    let renderer: Renderer = get_renders(renderer_arg.to_string());
+    let palette = match matches.value_of(ARG_PALETTE_FILE) {
+        Some(palette_file) => Palette::read(palette_file).unwrap_or_else(|error| {
+            eprintln!("Critical error: {}", error);
+            process::exit(1);
+        }),
+        None => matches
+            .value_of(ARG_PALETTE)
+            .map_or_else(Palette::default, |palette| Palette::parse(palette).unwrap()),
+    };
+    let mut renderer: Renderer = get_renders(renderer_arg.to_string(), palette);
+    if let Some(wav_file) = matches.value_of(ARG_WAV) {
+        match WavAudio::new(wav_file) {
+            Ok(audio) => renderer.audio = Box::new(audio),
+            Err(error) => {
+                eprintln!("Critical error: could not create {}: {}", wav_file, error);
+                process::exit(1);
+            }
+        }
+    }
+    if let Some(volume) = matches.value_of(ARG_VOLUME) {
+        renderer
+            .audio
+            .set_volume(volume.parse::<u32>().unwrap() as f32 / 100.0);
+    }
+    if matches.is_present(ARG_MUTE) {
+        renderer.audio.toggle_mute();
+    }
 
     // Run game loop
-    run_loop(vm, renderer);
+    let session = Session {
+        game_file,
+        rewind,
+        movie,
+        debugger,
+        max_frames: matches
+            .value_of(ARG_FRAMES)
+            .map(|frames| frames.parse().unwrap()),
+        trace,
+        profile_report: matches.is_present(ARG_PROFILE),
+        profile_json: matches.value_of(ARG_PROFILE_JSON),
+        wav_file: matches.value_of(ARG_WAV),
+    };
+    run_loop(vm, renderer, session);
+}
+
+// Options shared by the emulator and the subcommands
+fn platform_arg<'a>() -> Arg<'a> {
+    Arg::with_name(ARG_PLATFORM)
+        .short('p')
+        .long("platform")
+        .value_name("chip8 | schip | xochip")
+        .help("Platform to emulate. Default is chip8, SUPER-CHIP (schip) and XO-CHIP (xochip) programs need their platform")
+        .possible_values(Platform::NAMES)
+        .takes_value(true)
+}
+
+fn load_address_arg<'a>() -> Arg<'a> {
+    Arg::with_name(ARG_LOAD_ADDRESS)
+        .short('l')
+        .long("load-address")
+        .value_name("ADDRESS")
+        .help("Address where the game is loaded and execution starts, e.g. 0x600 for ETI-660 programs. Default is 0x200")
+        .validator(parse_address)
+        .takes_value(true)
+}
+
+fn platform_of(matches: &ArgMatches) -> Platform {
+    let platform_arg = matches.value_of(ARG_PLATFORM).unwrap_or("chip8");
+    Platform::from_name(platform_arg).unwrap()
+}
+
+fn load_address_of(matches: &ArgMatches) -> u16 {
+    matches
+        .value_of(ARG_LOAD_ADDRESS)
+        .map_or(DEFAULT_LOAD_ADDRESS, |address| {
+            parse_address(address).unwrap()
+        })
+}
+
+// Prints the listing of a ROM instead of running it
+fn run_disasm(matches: &ArgMatches) {
+    let rom = read_rom_arg(matches);
+    let syntax = Syntax::from_name(matches.value_of(ARG_SYNTAX).unwrap_or("cowgod")).unwrap();
+    let load_address = load_address_of(matches);
+    let platform = platform_of(matches);
+
+    let disassembly = if matches.is_present(ARG_LINEAR) {
+        Disassembly::linear(&rom, load_address, platform)
+    } else {
+        let graph = ControlFlowGraph::new(&rom, load_address, platform);
+        for address in &graph.unresolved {
+            eprintln!(
+                "Warning: computed jump at {:#05x}, the code it reaches is shown as data",
+                address
+            );
+        }
+        Disassembly::from_graph(&rom, &graph)
+    };
+    let stdout = io::stdout();
+    if disassembly.write(syntax, &mut stdout.lock()).is_err() {
+        // The output was closed, e.g. piped to head
+        process::exit(1);
+    }
+}
+
+// Writes the control flow graph of a ROM instead of running it
+fn run_cfg(matches: &ArgMatches) {
+    let rom = read_rom_arg(matches);
+    let graph = ControlFlowGraph::new(&rom, load_address_of(matches), platform_of(matches));
+    let result = match matches.value_of(ARG_OUTPUT) {
+        Some(output_file) => File::create(output_file).and_then(|file| {
+            let mut writer = BufWriter::new(file);
+            graph.write_dot(&mut writer)?;
+            writer.flush()
+        }),
+        None => graph.write_dot(&mut io::stdout().lock()),
+    };
+    if let Err(error) = result {
+        eprintln!("Critical error: {}", error);
+        process::exit(1);
+    }
+}
+
+// Prints the issues found in a ROM, exits with 1 if there are errors
+fn run_check(matches: &ArgMatches) {
+    let rom = read_rom_arg(matches);
+    let platform = matches
+        .value_of(ARG_PLATFORM)
+        .map(|name| Platform::from_name(name).unwrap());
+    let report = lint::check(&rom, load_address_of(matches), platform);
+
+    for issue in &report.issues {
+        println!("{}", issue);
+    }
+    match report.platform_needed_by {
+        Some((address, instruction)) => println!(
+            "Platform: {} ({} at {:#05x})",
+            report.platform.name(),
+            instruction,
+            address
+        ),
+        None => println!("Platform: {}", report.platform.name()),
+    }
+    match report.call_depth {
+        Some(depth) => println!("Call depth: {}", depth),
+        None => println!("Call depth: unbounded (recursive subroutines)"),
+    }
+    println!("{} errors, {} warnings", report.errors(), report.warnings());
+    if report.errors() > 0 {
+        process::exit(1);
+    }
+}
+
+// Reads the ROM given to a subcommand, exits if it can not
+fn read_rom_arg(matches: &ArgMatches) -> Vec<u8> {
+    match fs::read(matches.value_of(ARG_ROM).unwrap()) {
+        Ok(rom) => rom,
+        Err(error) => {
+            eprintln!("Critical error: {}", error);
+            process::exit(1);
+        }
+    }
 }
 
-fn run_loop(mut vm: chip8::State, mut renderer: Renderer) {
+// Assembles a source into a ROM instead of running a game
+fn run_asm(matches: &ArgMatches) {
+    let source_file = matches.value_of(ARG_SOURCE).unwrap();
+    let output_file = matches.value_of(ARG_OUTPUT).map_or_else(
+        || Path::new(source_file).with_extension("ch8"),
+        PathBuf::from,
+    );
+
+    let mut assembler = Assembler::new();
+    for symbol in matches.values_of(ARG_DEFINE).into_iter().flatten() {
+        assembler.define(symbol);
+    }
+    // The sources predate UTF-8, comments may hold other characters
+    let result = fs::read(source_file)
+        .map_err(|error| error.to_string())
+        .and_then(|source| {
+            let source = String::from_utf8_lossy(&source);
+            let rom = if is_octo_source(source_file) {
+                octo::compile(&source)
+            } else {
+                assembler.assemble(&source)
+            };
+            rom.map_err(|error| error.to_string())
+        })
+        .and_then(|rom| fs::write(&output_file, rom).map_err(|error| error.to_string()));
+    if let Err(error) = result {
+        eprintln!("{}: {}", source_file, error);
+        process::exit(1);
+    }
+}
+
+// Reads the ROM of a game, compiling it first if it is an Octo source
+fn read_rom(game_file: &str) -> Result<Vec<u8>, String> {
+    if is_octo_source(game_file) {
+        let source = fs::read_to_string(game_file).map_err(|error| error.to_string())?;
+        octo::compile(&source).map_err(|error| format!("{}: {}", game_file, error))
+    } else {
+        fs::read(game_file).map_err(|error| error.to_string())
+    }
+}
+
+fn is_octo_source(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("8o"))
+}
+
+// Compares two traces instead of running a game, exits with 1 if they differ
+fn run_trace_diff(matches: &ArgMatches) {
+    let left_file = matches.value_of(ARG_LEFT).unwrap();
+    let right_file = matches.value_of(ARG_RIGHT).unwrap();
+    let open = |path: &str| {
+        File::open(path)
+            .map(BufReader::new)
+            .map_err(|error| format!("{}: {}", path, error))
+    };
+    let result = open(left_file).and_then(|left| {
+        let right = open(right_file)?;
+        trace::first_divergence(left, right).map_err(|error| error.to_string())
+    });
+    let divergence = match result {
+        Ok(Some(divergence)) => divergence,
+        Ok(None) => {
+            println!("The traces are identical");
+            return;
+        }
+        Err(error) => {
+            eprintln!("Critical error: {}", error);
+            process::exit(1);
+        }
+    };
+
+    println!("The traces diverge at line {}", divergence.line);
+    if !divergence.fields.is_empty() {
+        println!("Different fields: {}", divergence.fields.join(", "));
+    }
+    for (path, line) in [(left_file, divergence.left), (right_file, divergence.right)] {
+        match line {
+            Some(line) => println!("{}: {}", path, line),
+            None => println!("{}: (end of the trace)", path),
+        }
+    }
+    process::exit(1);
+}
+
+// Everything the main loop keeps track of besides the machine and the renderer
+struct Session<'a> {
+    game_file: &'a str,
+    rewind: RewindBuffer,
+    movie: MovieMode,
+    debugger: Option<Debugger>,
+    // The emulator exits after running this many frames
+    max_frames: Option<usize>,
+    // Where the executed instructions are logged
+    trace: Option<(BufWriter<File>, String)>,
+    // What to do with the profile at exit
+    profile_report: bool,
+    profile_json: Option<&'a str>,
+    // The WAV file the sound is written to, if any
+    wav_file: Option<&'a str>,
+}
+
+// Where the input of the session comes from and goes to
+enum MovieMode {
+    Off,
+    Record(Movie, String),
+    Play(Movie),
+}
+
+fn run_loop(mut vm: Machine, mut renderer: Renderer, session: Session) {
+    let Session {
+        game_file,
+        mut rewind,
+        mut movie,
+        mut debugger,
+        max_frames,
+        mut trace,
+        profile_report,
+        profile_json,
+        wav_file,
+    } = session;
+
     renderer.input.initialize();
     renderer.graphics.initialize();
 
-    let mut time_counter = Instant::now();
+    let mut scheduler = FrameScheduler::new();
+    let mut slot: u8 = 0;
+    let mut frame: usize = 0;
+    let mut paused = debugger.is_some();
 
     loop {
-        thread::sleep(time::Duration::from_millis(2));
-        match renderer.input.get_keyboard_state() {
-            KeyboardCommand::KeypadState(state) => vm.set_keys_pressed(state),
-            KeyboardCommand::SingleKey(key) => vm.wait_key_press(key),
-            KeyboardCommand::Quit => break,
+        if paused {
+            if let Some(ref mut debugger) = debugger {
+                renderer.graphics.suspend();
+                let action = debug_prompt(debugger, &mut vm);
+                renderer.graphics.resume();
+                renderer.input.reset();
+                renderer.graphics.draw(*vm.screen());
+                if action == DebuggerAction::Quit {
+                    break;
+                }
+                if vm.is_waiting_key() {
+                    renderer.input.set_waiting_key();
+                }
+                // Do not try to catch up with the time spent in the prompt
+                scheduler = FrameScheduler::new();
+            }
+            paused = false;
+        }
+
+        if max_frames == Some(frame) {
+            break;
+        }
+        if renderer.realtime {
+            scheduler.wait_next_frame();
+        }
+
+        if let MovieMode::Play(ref replay) = movie {
+            if frame == replay.frames.len() {
+                eprintln!("End of the movie, the keyboard is back in control");
+                movie = MovieMode::Off;
+                if vm.is_waiting_key() {
+                    renderer.input.set_waiting_key();
+                }
+            }
+        }
+
+        let command = renderer.input.get_keyboard_state();
+        if let KeyboardCommand::Mute = command {
+            let muted = renderer.audio.toggle_mute();
+            eprintln!("Sound {}", if muted { "muted" } else { "unmuted" });
+        }
+        let mut input = MovieInput::None;
+
+        // While replaying the keyboard can only quit
+        if let MovieMode::Play(ref replay) = movie {
+            if let KeyboardCommand::Quit = command {
+                break;
+            }
+            input = replay.frames[frame].input;
+            input.apply(&mut vm);
+        } else {
+            match command {
+                KeyboardCommand::KeypadState(state) => {
+                    vm.set_keys_pressed(state);
+                    input = MovieInput::Keypad(state);
+                }
+                KeyboardCommand::SingleKey(key) => {
+                    vm.wait_key_press(key);
+                    input = MovieInput::Key(key);
+                }
+                KeyboardCommand::Quit => break,
+                KeyboardCommand::Debug => {
+                    paused = debugger.is_some();
+                    continue;
+                }
+                KeyboardCommand::SaveState => {
+                    match fs::write(save_state_path(game_file, slot), vm.save_state()) {
+                        Ok(()) => eprintln!("State saved to slot {}", slot),
+                        Err(error) => eprintln!("Could not save state to slot {}: {}", slot, error),
+                    }
+                }
+                // Going back in time would break the recording
+                KeyboardCommand::LoadState if matches!(movie, MovieMode::Record(..)) => {
+                    eprintln!("Loading states is disabled while recording");
+                }
+                KeyboardCommand::Rewind if matches!(movie, MovieMode::Record(..)) => {}
+                KeyboardCommand::LoadState => {
+                    let result = fs::read(save_state_path(game_file, slot))
+                        .map_err(|error| error.to_string())
+                        .and_then(|data| vm.load_state(&data).map_err(|error| error.to_string()));
+                    match result {
+                        Ok(()) => {
+                            eprintln!("State loaded from slot {}", slot);
+                            renderer.graphics.draw(*vm.screen());
+                            if vm.is_waiting_key() {
+                                renderer.input.set_waiting_key();
+                            }
+                        }
+                        Err(error) => {
+                            eprintln!("Could not load state from slot {}: {}", slot, error)
+                        }
+                    }
+                }
+                KeyboardCommand::Mute => {}
+                KeyboardCommand::NextSlot => {
+                    slot = (slot + 1) % SAVE_STATE_SLOTS;
+                    eprintln!("Save state slot {}", slot);
+                }
+                KeyboardCommand::Rewind => {
+                    // Steps one frame back, the machine stays paused once the history runs out
+                    if let Some(state) = rewind.pop() {
+                        match vm.load_state(&state) {
+                            Ok(()) => {
+                                renderer.graphics.draw(*vm.screen());
+                                if vm.is_waiting_key() {
+                                    renderer.input.set_waiting_key();
+                                }
+                            }
+                            Err(error) => eprintln!("Could not rewind: {}", error),
+                        }
+                    }
+                    continue;
+                }
+            }
         }
 
-        if time_counter.elapsed().as_millis() > TIMERS_DECREMENT_FRACTION {
-            vm.decrement_timers();
-            time_counter = Instant::now();
+        rewind.push(vm.save_state());
+
+        let result = match debugger {
+            Some(ref mut debugger) => vm.run_frame_until(|vm| debugger.should_break(vm)),
+            None => vm.run_frame(),
+        };
+
+        // Play the sound of the frame, with the beeps where the program started and stopped them
+        let sample_rate = renderer.audio.sample_rate();
+        let mut samples = vec![0.0; (sample_rate / FRAME_RATE) as usize];
+        vm.render_audio(sample_rate, &mut samples);
+        renderer.audio.play(&samples);
+
+        if let Some((ref mut writer, ref trace_file)) = trace {
+            if let Err(error) = write_trace(&mut vm, writer) {
+                eprintln!("Could not write the trace to {}: {}", trace_file, error);
+                vm.set_trace(None);
+            }
+        }
+
+        // Without the debugger watchpoints only report the accesses, the game keeps running
+        if debugger.is_none() {
+            for hit in vm.take_watch_hits() {
+                eprintln!("{}", hit);
+            }
         }
 
-        match vm.execute_instruction() {
+        match movie {
+            MovieMode::Record(ref mut recording, _) => recording.record(input, &vm),
+            MovieMode::Play(ref replay) => {
+                if let Err(error) = replay.verify(frame, &vm) {
+                    eprintln!("{}", error);
+                    break;
+                }
+            }
+            MovieMode::Off => {}
+        }
+        frame += 1;
+
+        match result {
             Ok(MachineState::SuccessfulExecution) => continue,
+            // The replay delivers the key, there is no need to wait for the keyboard
+            Ok(MachineState::WaitForKeyboard) if matches!(movie, MovieMode::Play(_)) => {}
             Ok(MachineState::WaitForKeyboard) => renderer.input.set_waiting_key(),
             Ok(MachineState::Draw(screen)) => renderer.graphics.draw(*screen),
+            Ok(MachineState::Exit) => break,
+            Ok(MachineState::Break) => {
+                renderer.graphics.draw(*vm.screen());
+                paused = debugger.is_some();
+            }
+            // The debugger gets a chance to inspect the machine
+            Err(error) if debugger.is_some() => {
+                println!("Critical error: {}", error);
+                paused = true;
+            }
             Err(error) => {
-                println!("{}", error);
+                println!("Critical error: {}", error);
                 break;
             }
         }
     }
+
+    if let Some((mut writer, trace_file)) = trace {
+        let result = write_trace(&mut vm, &mut writer).and_then(|_| writer.flush());
+        match result {
+            Ok(()) => eprintln!("Trace saved to {}", trace_file),
+            Err(error) => eprintln!("Could not write the trace to {}: {}", trace_file, error),
+        }
+    }
+
+    match renderer.audio.finish() {
+        Ok(()) => {
+            if let Some(wav_file) = wav_file {
+                eprintln!("Sound saved to {}", wav_file);
+            }
+        }
+        Err(error) => eprintln!("Could not write the sound: {}", error),
+    }
+
+    if let Some(profile) = vm.profile() {
+        // The report goes below the screen of the terminal renderer
+        renderer.graphics.suspend();
+        if profile_report {
+            // A closed output, e.g. piped to head, only loses the rest of the report, the files
+            // below are still written
+            let _ = profile.write_report(PROFILE_HOT_SPOTS, &mut io::stdout().lock());
+        }
+        if let Some(profile_file) = profile_json {
+            let result = File::create(profile_file).and_then(|file| {
+                let mut writer = BufWriter::new(file);
+                profile.write_json(&mut writer)?;
+                writer.flush()
+            });
+            match result {
+                Ok(()) => eprintln!("Profile saved to {}", profile_file),
+                Err(error) => eprintln!("Could not save profile to {}: {}", profile_file, error),
+            }
+        }
+    }
+
+    if let MovieMode::Record(recording, movie_file) = movie {
+        let result =
+            File::create(&movie_file).and_then(|file| recording.write(BufWriter::new(file)));
+        match result {
+            Ok(()) => eprintln!("Movie saved to {}", movie_file),
+            Err(error) => eprintln!("Could not save movie to {}: {}", movie_file, error),
+        }
+    }
+}
+
+// Writes the instructions traced since the last call
+fn write_trace<W: Write>(vm: &mut Machine, writer: &mut W) -> io::Result<()> {
+    for entry in vm.take_trace() {
+        writeln!(writer, "{}", entry)?;
+    }
+    Ok(())
+}
+
+// Reads debugger commands from the standard input until execution resumes or the emulator quits
+fn debug_prompt(debugger: &mut Debugger, vm: &mut Machine) -> DebuggerAction {
+    let stdin = io::stdin();
+    let mut stdout = io::stdout();
+    debugger.print_stop(vm, &mut stdout).unwrap();
+
+    loop {
+        print!("(chip8) ");
+        stdout.flush().unwrap();
+
+        let mut line = String::new();
+        match stdin.read_line(&mut line) {
+            Ok(0) | Err(_) => return DebuggerAction::Quit,
+            Ok(_) => {}
+        }
+        match debugger.execute(vm, &line, &mut stdout) {
+            Ok(DebuggerAction::Prompt) => {}
+            Ok(action) => return action,
+            Err(_) => return DebuggerAction::Quit,
+        }
+    }
+}
+
+// Save states are stored next to the game, one file per slot
+fn save_state_path(game_file: &str, slot: u8) -> String {
+    format!("{}.state{}", game_file, slot)
+}
+
+// Parses a watched range: ADDR[-END][:r|w|rw]
+fn parse_watch(value: &str) -> Result<(u16, u16, WatchKind), String> {
+    let mut parts = value.splitn(2, ':');
+    let (start, end) = parse_range(parts.next().unwrap_or(""))?;
+    let kind = match parts.next() {
+        Some(kind) => {
+            WatchKind::from_name(kind).ok_or_else(|| format!("invalid access kind: {}", kind))?
+        }
+        None => WatchKind::Write,
+    };
+    Ok((start, end, kind))
+}
+
+// Parses an address range: ADDR[-END], both ends inclusive
+fn parse_range(value: &str) -> Result<(u16, u16), String> {
+    let mut range = value.splitn(2, '-');
+    let start = parse_address(range.next().unwrap_or(""))?;
+    let end = match range.next() {
+        Some(end) => parse_address(end)?,
+        None => start,
+    };
+    if end < start {
+        return Err(format!("invalid range: {}", value));
+    }
+    Ok((start, end))
+}
+
+// Parses opcode classes: comma separated hexadecimal digits
+fn parse_classes(value: &str) -> Result<Vec<u8>, String> {
+    value
+        .split(',')
+        .map(|class| match u8::from_str_radix(class.trim(), 16) {
+            Ok(digit) if class.trim().len() == 1 => Ok(digit),
+            _ => Err(format!("invalid opcode class: {}", class)),
+        })
+        .collect()
+}
+
+// Parses an address written in hexadecimal (0x prefix) or decimal
+fn parse_address(value: &str) -> Result<u16, String> {
+    let parsed = if value.starts_with("0x") || value.starts_with("0X") {
+        u16::from_str_radix(&value[2..], 16)
+    } else {
+        value.parse::<u16>()
+    };
+    parsed.map_err(|_| format!("invalid address: {}", value))
 }
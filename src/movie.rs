@@ -0,0 +1,515 @@
+use error::Chip8Error;
+use machine::Machine;
+use platform::Platform;
+use quirks::{IndexIncrement, Quirks};
+use rng::{CosmacVipRandom, SeededRandom};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use timing::TimingMode;
+
+// First line of every movie, with the format version
+const MAGIC: &str = "chip8emu movie";
+
+/// Version of the movie format written by `Movie::write`.
+pub const VERSION: u32 = 1;
+
+/// A checksum of the machine state is stored every this many frames.
+pub const CHECKSUM_INTERVAL: usize = 60;
+
+// Quirk names used in the header, in the order of the Quirks fields. load_store is the increment
+// of I by x + 1 and load_store_x the one by x
+const QUIRK_NAMES: [&str; 7] = [
+    "shift",
+    "load_store",
+    "jump",
+    "vf_reset",
+    "clipping",
+    "display_wait",
+    "load_store_x",
+];
+
+/// Random number generator a movie was recorded with. Movies need a reproducible one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MovieRng {
+    /// `SeededRandom` with the given seed.
+    Seeded(u64),
+
+    /// `CosmacVipRandom` with the given seed.
+    CosmacVip(u16),
+}
+
+/// Everything needed to recreate the machine a movie was recorded on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MovieHeader {
+    pub rom_hash: u64,
+    pub platform: Platform,
+    pub load_address: u16,
+    pub quirks: Quirks,
+    pub timing: TimingMode,
+    pub instructions_per_frame: usize,
+    pub rng: MovieRng,
+}
+
+impl MovieHeader {
+    /// Header describing the current configuration of `machine`, which must have been set up with
+    /// the random number generator described by `rng`.
+    pub fn from_machine(machine: &Machine, rng: MovieRng) -> MovieHeader {
+        MovieHeader {
+            rom_hash: machine.rom_hash(),
+            platform: machine.platform(),
+            load_address: machine.load_address(),
+            quirks: machine.quirks(),
+            timing: machine.timing(),
+            instructions_per_frame: machine.instructions_per_frame(),
+            rng,
+        }
+    }
+
+    /// Creates a machine configured like the one the movie was recorded on, with `rom` loaded.
+    ///
+    /// Fails with `MovieError::RomMismatch` if `rom` is not the ROM the movie was recorded with.
+    pub fn create_machine(&self, rom: &[u8]) -> Result<Machine, MovieError> {
+        let mut machine = Machine::from_bytes(rom, self.platform, self.load_address)?;
+        if machine.rom_hash() != self.rom_hash {
+            return Err(MovieError::RomMismatch);
+        }
+
+        machine.set_quirks(self.quirks);
+        machine.set_timing(self.timing);
+        machine.set_instructions_per_frame(self.instructions_per_frame);
+        match self.rng {
+            MovieRng::Seeded(seed) => machine.set_rng(Box::new(SeededRandom::new(seed))),
+            MovieRng::CosmacVip(seed) => machine.set_rng(Box::new(CosmacVipRandom::new(seed))),
+        }
+        Ok(machine)
+    }
+}
+
+/// Input given to the machine before running a frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MovieInput {
+    /// The frame ran without new input.
+    None,
+
+    /// State of the whole keypad, passed to `Machine::set_keys_pressed`.
+    Keypad(u16),
+
+    /// Key delivered to `Fx0A` through `Machine::wait_key_press`.
+    Key(u8),
+}
+
+impl MovieInput {
+    /// Gives the input to `machine`.
+    pub fn apply(self, machine: &mut Machine) {
+        match self {
+            MovieInput::None => {}
+            MovieInput::Keypad(keys) => machine.set_keys_pressed(keys),
+            MovieInput::Key(key) => machine.wait_key_press(key),
+        }
+    }
+}
+
+/// One recorded frame: its input and, every `CHECKSUM_INTERVAL` frames, the checksum of the
+/// machine state after running it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MovieFrame {
+    pub input: MovieInput,
+    pub checksum: Option<u64>,
+}
+
+/// The input of a play session, frame by frame, which replays deterministically on a machine
+/// created from its header.
+///
+/// Movies are text files: a header with one `key value` line per setting, then the word
+/// `frames` and one line per frame. A frame line is `-` (no input), `k` and the keypad state in
+/// hexadecimal, or `w` and the key delivered to `Fx0A`, optionally followed by `=` and the
+/// checksum of the machine state after the frame.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Movie {
+    pub header: MovieHeader,
+    pub frames: Vec<MovieFrame>,
+}
+
+impl Movie {
+    pub fn new(header: MovieHeader) -> Movie {
+        Movie {
+            header,
+            frames: Vec::new(),
+        }
+    }
+
+    /// Appends a frame that ran with `input`. `machine` is the machine after running it.
+    pub fn record(&mut self, input: MovieInput, machine: &Machine) {
+        let checksum = if (self.frames.len() + 1).is_multiple_of(CHECKSUM_INTERVAL) {
+            Some(machine.checksum())
+        } else {
+            None
+        };
+        self.frames.push(MovieFrame { input, checksum });
+    }
+
+    /// Checks the machine state after replaying frame number `frame` against the recording.
+    /// Fails with `MovieError::Desync` if the machine is not where it was when recording.
+    pub fn verify(&self, frame: usize, machine: &Machine) -> Result<(), MovieError> {
+        match self.frames.get(frame).and_then(|frame| frame.checksum) {
+            Some(checksum) if checksum != machine.checksum() => Err(MovieError::Desync { frame }),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let header = &self.header;
+        writeln!(writer, "{} {}", MAGIC, VERSION)?;
+        writeln!(writer, "rom {:016x}", header.rom_hash)?;
+        writeln!(writer, "platform {}", header.platform.name())?;
+        writeln!(writer, "load-address {:#05x}", header.load_address)?;
+        let quirks = quirk_flags(header.quirks);
+        let enabled: Vec<&str> = QUIRK_NAMES
+            .iter()
+            .zip(quirks.iter())
+            .filter(|&(_, enabled)| *enabled)
+            .map(|(name, _)| *name)
+            .collect();
+        writeln!(writer, "quirks {}", enabled.join(" "))?;
+        writeln!(writer, "timing {}", header.timing.name())?;
+        writeln!(writer, "ipf {}", header.instructions_per_frame)?;
+        match header.rng {
+            MovieRng::Seeded(seed) => writeln!(writer, "rng seeded {}", seed)?,
+            MovieRng::CosmacVip(seed) => writeln!(writer, "rng vip {}", seed)?,
+        }
+
+        writeln!(writer, "frames")?;
+        for frame in self.frames.iter() {
+            match frame.input {
+                MovieInput::None => write!(writer, "-")?,
+                MovieInput::Keypad(keys) => write!(writer, "k {:04x}", keys)?,
+                MovieInput::Key(key) => write!(writer, "w {:x}", key)?,
+            }
+            if let Some(checksum) = frame.checksum {
+                write!(writer, " = {:016x}", checksum)?;
+            }
+            writeln!(writer)?;
+        }
+        writer.flush()
+    }
+
+    pub fn read<R: BufRead>(reader: R) -> Result<Movie, MovieError> {
+        let mut lines = reader.lines().enumerate();
+        let mut next_line = || -> Result<Option<(usize, String)>, MovieError> {
+            match lines.next() {
+                Some((number, line)) => Ok(Some((number + 1, line?))),
+                None => Ok(None),
+            }
+        };
+
+        match next_line()? {
+            Some((_, ref line)) if line.trim() == format!("{} {}", MAGIC, VERSION) => {}
+            Some((_, ref line)) if line.starts_with(MAGIC) => {
+                return Err(MovieError::UnsupportedVersion(
+                    line[MAGIC.len()..].trim().into(),
+                ))
+            }
+            _ => return Err(MovieError::NotAMovie),
+        }
+
+        let mut rom_hash = None;
+        let mut platform = None;
+        let mut load_address = None;
+        let mut quirks = None;
+        let mut timing = None;
+        let mut instructions_per_frame = None;
+        let mut rng = None;
+
+        let mut frames_line = 0;
+        while let Some((number, line)) = next_line()? {
+            let mut words = line.split_whitespace();
+            let key = match words.next() {
+                Some(key) => key,
+                None => continue,
+            };
+            let value = words.next();
+            let invalid = |message: String| MovieError::Syntax {
+                line: number,
+                message,
+            };
+            let invalid_value = || invalid(format!("invalid {}", key));
+
+            match key {
+                "rom" => {
+                    rom_hash = value.and_then(|hash| u64::from_str_radix(hash, 16).ok());
+                    rom_hash.ok_or_else(invalid_value)?;
+                }
+                "platform" => {
+                    platform = value.and_then(Platform::from_name);
+                    platform.ok_or_else(invalid_value)?;
+                }
+                "load-address" => {
+                    load_address = value.and_then(|address| {
+                        u16::from_str_radix(address.trim_start_matches("0x"), 16).ok()
+                    });
+                    load_address.ok_or_else(invalid_value)?;
+                }
+                "quirks" => {
+                    let mut flags = [false; 7];
+                    for name in value.into_iter().chain(words) {
+                        match QUIRK_NAMES.iter().position(|quirk| *quirk == name) {
+                            Some(position) => flags[position] = true,
+                            None => return Err(invalid(format!("unknown quirk {}", name))),
+                        }
+                    }
+                    quirks = Some(quirks_from_flags(flags));
+                }
+                "timing" => {
+                    timing = value.and_then(TimingMode::from_name);
+                    timing.ok_or_else(invalid_value)?;
+                }
+                "ipf" => {
+                    instructions_per_frame = value.and_then(|ipf| ipf.parse().ok());
+                    instructions_per_frame.ok_or_else(invalid_value)?;
+                }
+                "rng" => {
+                    let seed = words.next();
+                    rng = match value {
+                        Some("seeded") => seed
+                            .and_then(|seed| seed.parse().ok())
+                            .map(MovieRng::Seeded),
+                        Some("vip") => seed
+                            .and_then(|seed| seed.parse().ok())
+                            .map(MovieRng::CosmacVip),
+                        _ => None,
+                    };
+                    rng.ok_or_else(invalid_value)?;
+                }
+                "frames" => {
+                    frames_line = number;
+                    break;
+                }
+                _ => return Err(invalid(format!("unknown setting {}", key))),
+            }
+        }
+
+        let missing = |setting: &str| MovieError::Syntax {
+            line: frames_line,
+            message: format!("missing {}", setting),
+        };
+        let header = MovieHeader {
+            rom_hash: rom_hash.ok_or_else(|| missing("rom"))?,
+            platform: platform.ok_or_else(|| missing("platform"))?,
+            load_address: load_address.ok_or_else(|| missing("load-address"))?,
+            quirks: quirks.ok_or_else(|| missing("quirks"))?,
+            timing: timing.ok_or_else(|| missing("timing"))?,
+            instructions_per_frame: instructions_per_frame.ok_or_else(|| missing("ipf"))?,
+            rng: rng.ok_or_else(|| missing("rng"))?,
+        };
+
+        let mut frames = Vec::new();
+        while let Some((number, line)) = next_line()? {
+            if line.trim().is_empty() {
+                continue;
+            }
+            match parse_frame(&line) {
+                Some(frame) => frames.push(frame),
+                None => {
+                    return Err(MovieError::Syntax {
+                        line: number,
+                        message: format!("invalid frame: {}", line),
+                    })
+                }
+            }
+        }
+
+        Ok(Movie { header, frames })
+    }
+}
+
+// Parses "<input> [= <checksum>]"
+fn parse_frame(line: &str) -> Option<MovieFrame> {
+    let mut parts = line.splitn(2, '=');
+    let mut input = parts.next()?.split_whitespace();
+
+    let frame_input = match (input.next()?, input.next()) {
+        ("-", None) => MovieInput::None,
+        ("k", Some(keys)) => MovieInput::Keypad(u16::from_str_radix(keys, 16).ok()?),
+        ("w", Some(key)) => MovieInput::Key(u8::from_str_radix(key, 16).ok()?),
+        _ => return None,
+    };
+    if input.next().is_some() {
+        return None;
+    }
+
+    let checksum = match parts.next() {
+        Some(checksum) => Some(u64::from_str_radix(checksum.trim(), 16).ok()?),
+        None => None,
+    };
+
+    Some(MovieFrame {
+        input: frame_input,
+        checksum,
+    })
+}
+
+fn quirk_flags(quirks: Quirks) -> [bool; 7] {
+    [
+        quirks.shift,
+        quirks.load_store == IndexIncrement::XPlusOne,
+        quirks.jump,
+        quirks.vf_reset,
+        quirks.clipping,
+        quirks.display_wait,
+        quirks.load_store == IndexIncrement::X,
+    ]
+}
+
+fn quirks_from_flags(flags: [bool; 7]) -> Quirks {
+    let load_store = match (flags[1], flags[6]) {
+        (true, _) => IndexIncrement::XPlusOne,
+        (false, true) => IndexIncrement::X,
+        (false, false) => IndexIncrement::None,
+    };
+    Quirks {
+        shift: flags[0],
+        load_store,
+        jump: flags[2],
+        vf_reset: flags[3],
+        clipping: flags[4],
+        display_wait: flags[5],
+    }
+}
+
+/// Reasons a movie can not be read or replayed.
+#[derive(Debug)]
+pub enum MovieError {
+    /// The file is not a movie.
+    NotAMovie,
+
+    /// The movie was written by an incompatible version of the emulator.
+    UnsupportedVersion(String),
+
+    /// A line of the movie is malformed.
+    Syntax { line: usize, message: String },
+
+    /// The movie was recorded with a different ROM.
+    RomMismatch,
+
+    /// The replay diverged from the recording: the machine state after `frame` (counted from 0)
+    /// does not match the checksum stored in the movie.
+    Desync { frame: usize },
+
+    /// The machine the movie was recorded on could not be created.
+    Machine(Chip8Error),
+
+    /// The movie could not be read.
+    Io(io::Error),
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MovieError::NotAMovie => write!(f, "not a movie"),
+            MovieError::UnsupportedVersion(ref version) => write!(
+                f,
+                "unsupported movie version {} (expected {})",
+                version, VERSION
+            ),
+            MovieError::Syntax { line, ref message } => {
+                write!(f, "invalid movie, line {}: {}", line, message)
+            }
+            MovieError::RomMismatch => write!(f, "the movie was recorded with a different ROM"),
+            MovieError::Desync { frame } => write!(
+                f,
+                "replay desynchronized from the recording at frame {}",
+                frame
+            ),
+            MovieError::Machine(ref error) => write!(f, "{}", error),
+            MovieError::Io(ref error) => write!(f, "could not read movie: {}", error),
+        }
+    }
+}
+
+impl Error for MovieError {}
+
+impl From<io::Error> for MovieError {
+    fn from(error: io::Error) -> MovieError {
+        MovieError::Io(error)
+    }
+}
+
+impl From<Chip8Error> for MovieError {
+    fn from(error: Chip8Error) -> MovieError {
+        MovieError::Machine(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8::DEFAULT_LOAD_ADDRESS;
+
+    // Adds random numbers to V1 forever
+    const ROM: [u8; 6] = [0xC0, 0xFF, 0x81, 0x04, 0x12, 0x00];
+
+    fn recording(frames: usize) -> Movie {
+        let mut machine = Machine::from_bytes(&ROM, Platform::Chip8, DEFAULT_LOAD_ADDRESS).unwrap();
+        machine.set_rng(Box::new(SeededRandom::new(42)));
+        let mut movie = Movie::new(MovieHeader::from_machine(&machine, MovieRng::Seeded(42)));
+        for frame in 0..frames {
+            let input = if frame % 7 == 0 {
+                MovieInput::Keypad(1 << (frame % 16))
+            } else {
+                MovieInput::None
+            };
+            input.apply(&mut machine);
+            machine.run_frame().unwrap();
+            movie.record(input, &machine);
+        }
+        movie
+    }
+
+    #[test]
+    fn read_returns_the_written_movie() {
+        let movie = recording(2 * CHECKSUM_INTERVAL);
+        let mut text = Vec::new();
+        movie.write(&mut text).unwrap();
+
+        let read = Movie::read(&text[..]).unwrap();
+        assert_eq!(read, movie);
+        assert_eq!(
+            read.frames
+                .iter()
+                .filter(|frame| frame.checksum.is_some())
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn replay_matches_the_checksums() {
+        let movie = recording(2 * CHECKSUM_INTERVAL);
+        let mut machine = movie.header.create_machine(&ROM).unwrap();
+        for (number, frame) in movie.frames.iter().enumerate() {
+            frame.input.apply(&mut machine);
+            machine.run_frame().unwrap();
+            movie.verify(number, &machine).unwrap();
+        }
+    }
+
+    #[test]
+    fn verify_detects_a_desync_at_the_next_checksum() {
+        let movie = recording(CHECKSUM_INTERVAL);
+        let mut machine = movie.header.create_machine(&ROM).unwrap();
+        for (number, frame) in movie.frames.iter().enumerate() {
+            if number == 10 {
+                machine.set_register(0x2, 0x01);
+            }
+            frame.input.apply(&mut machine);
+            machine.run_frame().unwrap();
+            let result = movie.verify(number, &machine);
+            if number + 1 == CHECKSUM_INTERVAL {
+                assert!(matches!(result, Err(MovieError::Desync { frame }) if frame == number));
+            } else {
+                assert!(result.is_ok());
+            }
+        }
+    }
+}
@@ -0,0 +1,920 @@
+use assembler::AssemblerError;
+use chip8::DEFAULT_LOAD_ADDRESS;
+use instruction::Instruction;
+use std::collections::{HashMap, VecDeque};
+use std::f64::consts;
+use std::mem;
+
+/// Compiles an Octo program into a ROM loaded at `0x200`.
+///
+/// Execution starts at the `main` label: unless the program starts with it, the ROM starts with
+/// a jump to it. The compiler supports:
+///
+/// - `: name` labels, `:next name` (the second byte of the next instruction), `:const`, `:alias`,
+///   `:calc name { expression }`, `:macro name arguments { body }`, `:byte`, `:org`, `:call`
+///   and `:unpack`; `:breakpoint` and `:monitor` are accepted and ignored;
+/// - `if ... then`, `if ... begin ... else ... end` and `loop ... while ... again`, including
+///   the `<`, `>`, `<=` and `>=` comparisons, which use `vf` unless `compare-temp` is aliased;
+/// - the CHIP-8, SUPER-CHIP and XO-CHIP statements, such as `sprite`, `scroll-down`,
+///   `i := long`, `save vx - vy`, `plane` and `audio`;
+/// - numbers alone, which are emitted as bytes, and names alone, which call the subroutine.
+///
+/// `:calc` expressions are evaluated right to left without precedence, as in Octo.
+pub fn compile(source: &str) -> Result<Vec<u8>, AssemblerError> {
+    let tokens = tokenize(source);
+    let starts_with_main = tokens.len() >= 2 && tokens[0].text == ":" && tokens[1].text == "main";
+    let mut compiler = Compiler::new(tokens);
+    if !starts_with_main {
+        let at = compiler
+            .instruction(Instruction::Jump(0))
+            .map_err(|message| compiler.error(message))?;
+        compiler
+            .reference(at, Reference::Address, Target::Name("main".to_string()))
+            .map_err(|message| compiler.error(message))?;
+    }
+    compiler.run()
+}
+
+// Statement words, which can not name labels
+static KEYWORDS: [&str; 34] = [
+    "clear",
+    "return",
+    ";",
+    "hires",
+    "lores",
+    "exit",
+    "audio",
+    "scroll-down",
+    "scroll-up",
+    "scroll-left",
+    "scroll-right",
+    "plane",
+    "bcd",
+    "save",
+    "load",
+    "saveflags",
+    "loadflags",
+    "sprite",
+    "jump",
+    "jump0",
+    "native",
+    "delay",
+    "buzzer",
+    "pitch",
+    "i",
+    "if",
+    "then",
+    "begin",
+    "else",
+    "end",
+    "loop",
+    "while",
+    "again",
+    "key",
+];
+
+// A recursive macro would expand forever
+const MAX_MACRO_EXPANSIONS: usize = 0x10000;
+
+struct Token {
+    text: String,
+    line: usize,
+}
+
+struct Macro {
+    parameters: Vec<String>,
+    body: Vec<String>,
+}
+
+// Where the address of a label goes once it is known
+#[derive(Clone, Copy)]
+enum Reference {
+    // The low 12 bits of the instruction
+    Address,
+    // The word following `i := long`
+    Long,
+    // The two `:=` of `:unpack`, with the nibble given for the high byte
+    Unpack(u8),
+}
+
+enum Target {
+    Value(i64),
+    Name(String),
+}
+
+struct Fixup {
+    at: usize,
+    reference: Reference,
+    name: String,
+    line: usize,
+}
+
+// Open control flow statement
+enum Block {
+    // The jump of `begin` or `else`, to the next `else` or `end`
+    Branch(usize),
+    // The start of `loop` and the jumps of its `while`
+    Loop(usize, Vec<usize>),
+}
+
+struct Compiler {
+    tokens: VecDeque<Token>,
+    line: usize,
+    rom: Vec<u8>,
+    address: usize,
+    labels: HashMap<String, u16>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    expansions: usize,
+    fixups: Vec<Fixup>,
+    next_labels: Vec<String>,
+    blocks: Vec<Block>,
+}
+
+impl Compiler {
+    fn new(tokens: VecDeque<Token>) -> Compiler {
+        Compiler {
+            tokens,
+            line: 1,
+            rom: Vec::new(),
+            address: DEFAULT_LOAD_ADDRESS as usize,
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            expansions: 0,
+            fixups: Vec::new(),
+            next_labels: Vec::new(),
+            blocks: Vec::new(),
+        }
+    }
+
+    fn run(mut self) -> Result<Vec<u8>, AssemblerError> {
+        while !self.tokens.is_empty() {
+            if let Err(message) = self.statement() {
+                return Err(self.error(message));
+            }
+        }
+
+        match self.blocks.last() {
+            Some(Block::Branch(_)) => return Err(self.error("begin without end".to_string())),
+            Some(Block::Loop(..)) => return Err(self.error("loop without again".to_string())),
+            None => {}
+        }
+        if !self.next_labels.is_empty() {
+            return Err(self.error(":next without an instruction".to_string()));
+        }
+        for fixup in mem::take(&mut self.fixups) {
+            let value = self
+                .known_value(&fixup.name)
+                .map(|value| value.floor() as i64);
+            let result = match value {
+                Some(value) => self.patch(fixup.at, fixup.reference, value),
+                None => Err(format!("undefined name {}", fixup.name)),
+            };
+            if let Err(message) = result {
+                return Err(AssemblerError {
+                    line: fixup.line,
+                    message,
+                });
+            }
+        }
+        Ok(self.rom)
+    }
+
+    fn error(&self, message: String) -> AssemblerError {
+        AssemblerError {
+            line: self.line,
+            message,
+        }
+    }
+
+    fn statement(&mut self) -> Result<(), String> {
+        use self::Instruction::*;
+        let token = self.next()?;
+        let instruction = match token.as_str() {
+            ":" => {
+                let name = self.name()?;
+                let address = self.address;
+                return self.define_label(name, address);
+            }
+            ":next" => {
+                let name = self.name()?;
+                self.next_labels.push(name);
+                return Ok(());
+            }
+            ":const" => {
+                let name = self.name()?;
+                let value = self.value()?;
+                return self.define_constant(name, value as f64);
+            }
+            ":alias" => {
+                let name = self.name()?;
+                let register = self.register()?;
+                self.aliases.insert(name, register);
+                return Ok(());
+            }
+            ":calc" => {
+                let name = self.name()?;
+                self.expect("{")?;
+                let value = self.calc()?;
+                self.expect("}")?;
+                return self.define_constant(name, value);
+            }
+            ":macro" => return self.define_macro(),
+            ":byte" => {
+                let value = if self.peek() == Some("{") {
+                    self.next()?;
+                    let value = self.calc()?;
+                    self.expect("}")?;
+                    value.floor() as i64
+                } else {
+                    self.value()?
+                };
+                let byte = byte(value)?;
+                self.emit(&[byte])?;
+                return Ok(());
+            }
+            ":org" => {
+                let address = self.value()?;
+                if !(DEFAULT_LOAD_ADDRESS as i64..=0xFFFF).contains(&address) {
+                    return Err(format!("address out of range: {:#x}", address));
+                }
+                self.address = address as usize;
+                return Ok(());
+            }
+            ":call" => {
+                let target = self.target()?;
+                let at = self.instruction(Call(0))?;
+                return self.reference(at, Reference::Address, target);
+            }
+            ":unpack" => {
+                let nibble = self.nibble()?;
+                let target = self.target()?;
+                let high = self.alias("unpack-hi", 0x0);
+                let low = self.alias("unpack-lo", 0x1);
+                let at = self.instruction(LoadByte(high, 0))?;
+                self.instruction(LoadByte(low, 0))?;
+                return self.reference(at, Reference::Unpack(nibble), target);
+            }
+            ":breakpoint" => {
+                self.next()?;
+                return Ok(());
+            }
+            ":monitor" => {
+                self.next()?;
+                self.next()?;
+                return Ok(());
+            }
+            "clear" => ClearScreen,
+            "return" | ";" => Return,
+            "hires" => HighRes,
+            "lores" => LowRes,
+            "exit" => Exit,
+            "audio" => Audio,
+            "scroll-down" => ScrollDown(self.nibble()?),
+            "scroll-up" => ScrollUp(self.nibble()?),
+            "scroll-left" => ScrollLeft,
+            "scroll-right" => ScrollRight,
+            "plane" => match self.value()? {
+                plane @ 0..=3 => Plane(plane as u8),
+                plane => return Err(format!("invalid plane: {}", plane)),
+            },
+            "bcd" => Bcd(self.register()?),
+            "saveflags" => SaveFlags(self.register()?),
+            "loadflags" => LoadFlags(self.register()?),
+            "save" | "load" => {
+                let x = self.register()?;
+                match (self.peek() == Some("-"), token.as_str()) {
+                    (false, "save") => Store(x),
+                    (false, _) => Load(x),
+                    (true, save_or_load) => {
+                        self.next()?;
+                        let y = self.register()?;
+                        if save_or_load == "save" {
+                            SaveRange(x, y)
+                        } else {
+                            LoadRange(x, y)
+                        }
+                    }
+                }
+            }
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                Draw(x, y, self.nibble()?)
+            }
+            "jump" | "jump0" => {
+                let target = self.target()?;
+                let instruction = if token == "jump" {
+                    Jump(0)
+                } else {
+                    JumpOffset(0)
+                };
+                let at = self.instruction(instruction)?;
+                return self.reference(at, Reference::Address, target);
+            }
+            "native" => {
+                let target = self.target()?;
+                let at = self.emit(&[0, 0])?;
+                return self.reference(at, Reference::Address, target);
+            }
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                match token.as_str() {
+                    "delay" => SetDelay(x),
+                    "buzzer" => SetSound(x),
+                    _ => Pitch(x),
+                }
+            }
+            "i" => return self.index(),
+            "if" => return self.conditional(),
+            "loop" => {
+                self.blocks.push(Block::Loop(self.address, Vec::new()));
+                return Ok(());
+            }
+            "while" => {
+                self.condition(true)?;
+                let at = self.instruction(Jump(0))?;
+                return match self.blocks.iter_mut().rev().find_map(|block| match block {
+                    Block::Loop(_, breaks) => Some(breaks),
+                    _ => None,
+                }) {
+                    Some(breaks) => {
+                        breaks.push(at);
+                        Ok(())
+                    }
+                    None => Err("while without loop".to_string()),
+                };
+            }
+            "again" => {
+                let (start, breaks) = match self.blocks.pop() {
+                    Some(Block::Loop(start, breaks)) => (start, breaks),
+                    _ => return Err("again without loop".to_string()),
+                };
+                self.instruction(Jump(0))
+                    .and_then(|at| self.patch(at, Reference::Address, start as i64))?;
+                let end = self.address as i64;
+                for at in breaks {
+                    self.patch(at, Reference::Address, end)?;
+                }
+                return Ok(());
+            }
+            "else" => {
+                let branch = match self.blocks.pop() {
+                    Some(Block::Branch(at)) => at,
+                    _ => return Err("else without begin".to_string()),
+                };
+                let at = self.instruction(Jump(0))?;
+                self.blocks.push(Block::Branch(at));
+                let address = self.address as i64;
+                return self.patch(branch, Reference::Address, address);
+            }
+            "end" => {
+                let branch = match self.blocks.pop() {
+                    Some(Block::Branch(at)) => at,
+                    _ => return Err("end without begin".to_string()),
+                };
+                let address = self.address as i64;
+                return self.patch(branch, Reference::Address, address);
+            }
+            _ => {
+                if let Some(x) = self.register_named(&token) {
+                    return self.assignment(x);
+                }
+                if self.macros.contains_key(&token) {
+                    return self.expand(&token);
+                }
+                if let Some(value) = number(&token) {
+                    let byte = byte(value)?;
+                    self.emit(&[byte])?;
+                    return Ok(());
+                }
+                if !is_name(&token) {
+                    return Err(format!("unexpected {}", token));
+                }
+                // A name alone calls the subroutine
+                self.tokens.push_front(Token {
+                    text: token,
+                    line: self.line,
+                });
+                let target = self.target()?;
+                let at = self.instruction(Call(0))?;
+                return self.reference(at, Reference::Address, target);
+            }
+        };
+        self.instruction(instruction).map(|_| ())
+    }
+
+    fn assignment(&mut self, x: u8) -> Result<(), String> {
+        use self::Instruction::*;
+        let operator = self.next()?;
+        let y = self.peek().and_then(|token| self.register_named(token));
+        if y.is_some() {
+            self.next()?;
+        }
+        let instruction = match (operator.as_str(), y) {
+            (":=", Some(y)) => Move(x, y),
+            (":=", None) => match self.peek() {
+                Some("random") => {
+                    self.next()?;
+                    Random(x, self.byte_value()?)
+                }
+                Some("key") => {
+                    self.next()?;
+                    WaitKey(x)
+                }
+                Some("delay") => {
+                    self.next()?;
+                    GetDelay(x)
+                }
+                _ => LoadByte(x, self.byte_value()?),
+            },
+            ("+=", Some(y)) => Add(x, y),
+            ("+=", None) => AddByte(x, self.byte_value()?),
+            ("-=", Some(y)) => Sub(x, y),
+            ("-=", None) => AddByte(x, self.byte_value()?.wrapping_neg()),
+            ("=-", Some(y)) => SubReverse(x, y),
+            ("|=", Some(y)) => Or(x, y),
+            ("&=", Some(y)) => And(x, y),
+            ("^=", Some(y)) => Xor(x, y),
+            (">>=", Some(y)) => ShiftRight(x, y),
+            ("<<=", Some(y)) => ShiftLeft(x, y),
+            ("=-", None)
+            | ("|=", None)
+            | ("&=", None)
+            | ("^=", None)
+            | (">>=", None)
+            | ("<<=", None) => return Err(format!("{} expects a register", operator)),
+            _ => return Err(format!("unknown operator {}", operator)),
+        };
+        self.instruction(instruction).map(|_| ())
+    }
+
+    fn index(&mut self) -> Result<(), String> {
+        use self::Instruction::*;
+        let operator = self.next()?;
+        let instruction = match operator.as_str() {
+            "+=" => AddIndex(self.register()?),
+            ":=" => match self.peek() {
+                Some("hex") => {
+                    self.next()?;
+                    Font(self.register()?)
+                }
+                Some("bighex") => {
+                    self.next()?;
+                    BigFont(self.register()?)
+                }
+                Some("long") => {
+                    self.next()?;
+                    let target = self.target()?;
+                    let at = self.instruction(LoadIndexLong(0))?;
+                    return self.reference(at, Reference::Long, target);
+                }
+                _ => {
+                    let target = self.target()?;
+                    let at = self.instruction(LoadIndex(0))?;
+                    return self.reference(at, Reference::Address, target);
+                }
+            },
+            _ => return Err(format!("unknown operator {}", operator)),
+        };
+        self.instruction(instruction).map(|_| ())
+    }
+
+    // `if` followed by `then` skips the next statement unless the condition holds, followed by
+    // `begin` jumps to the matching `else` or `end`
+    fn conditional(&mut self) -> Result<(), String> {
+        let comparison = self.tokens.get(1).map(|token| token.text.as_str());
+        let end = match comparison {
+            Some("key") | Some("-key") => 2,
+            _ => 3,
+        };
+        let begin = match self.tokens.get(end).map(|token| token.text.as_str()) {
+            Some("then") => false,
+            Some("begin") => true,
+            _ => return Err("expected then or begin after the condition".to_string()),
+        };
+        self.condition(begin)?;
+        self.next()?;
+        if begin {
+            let at = self.instruction(Instruction::Jump(0))?;
+            self.blocks.push(Block::Branch(at));
+        }
+        Ok(())
+    }
+
+    // Emits the instructions that skip the next one when the condition does not hold, or when
+    // it holds if `negated`
+    fn condition(&mut self, negated: bool) -> Result<(), String> {
+        use self::Instruction::*;
+        let x = self.register()?;
+        let comparison = self.next()?;
+        let comparison = match (comparison.as_str(), negated) {
+            (comparison, false) => comparison,
+            ("==", true) => "!=",
+            ("!=", true) => "==",
+            ("key", true) => "-key",
+            ("-key", true) => "key",
+            ("<", true) => ">=",
+            (">", true) => "<=",
+            ("<=", true) => ">",
+            (">=", true) => "<",
+            (comparison, true) => comparison,
+        };
+        match comparison {
+            "key" => return self.instruction(SkipNotKey(x)).map(|_| ()),
+            "-key" => return self.instruction(SkipKey(x)).map(|_| ()),
+            "==" | "!=" | "<" | ">" | "<=" | ">=" => {}
+            _ => return Err(format!("unknown comparison {}", comparison)),
+        }
+
+        let y = self.peek().and_then(|token| self.register_named(token));
+        let operand = match y {
+            Some(y) => {
+                self.next()?;
+                Ok(y)
+            }
+            None => Err(self.byte_value()?),
+        };
+        let instructions = match (comparison, operand) {
+            ("==", Ok(y)) => vec![SkipNotEqual(x, y)],
+            ("==", Err(kk)) => vec![SkipNotEqualByte(x, kk)],
+            ("!=", Ok(y)) => vec![SkipEqual(x, y)],
+            ("!=", Err(kk)) => vec![SkipEqualByte(x, kk)],
+            // The others subtract in the temporary register and test the borrow flag
+            (comparison, operand) => {
+                let temp = self.alias("compare-temp", 0xF);
+                let load = match operand {
+                    Ok(y) => Move(temp, y),
+                    Err(kk) => LoadByte(temp, kk),
+                };
+                let subtract = match comparison {
+                    ">" | "<=" => Sub(temp, x),
+                    _ => SubReverse(temp, x),
+                };
+                let skip = match comparison {
+                    ">" | "<" => SkipEqualByte(0xF, 1),
+                    _ => SkipNotEqualByte(0xF, 1),
+                };
+                vec![load, subtract, skip]
+            }
+        };
+        for instruction in instructions {
+            self.instruction(instruction)?;
+        }
+        Ok(())
+    }
+
+    fn define_macro(&mut self) -> Result<(), String> {
+        let name = self.name()?;
+        let mut parameters = Vec::new();
+        loop {
+            match self.next()?.as_str() {
+                "{" => break,
+                parameter => parameters.push(parameter.to_string()),
+            }
+        }
+        let mut body = Vec::new();
+        let mut depth = 0;
+        loop {
+            let token = self.next()?;
+            match token.as_str() {
+                "{" => depth += 1,
+                "}" if depth == 0 => break,
+                "}" => depth -= 1,
+                _ => {}
+            }
+            body.push(token);
+        }
+        self.macros.insert(name, Macro { parameters, body });
+        Ok(())
+    }
+
+    // Replaces the macro and its arguments with its body
+    fn expand(&mut self, name: &str) -> Result<(), String> {
+        self.expansions += 1;
+        if self.expansions > MAX_MACRO_EXPANSIONS {
+            return Err(format!("too many expansions of {}, is it recursive?", name));
+        }
+        let mut arguments = HashMap::new();
+        for parameter in &self.macros[name].parameters {
+            let argument = match self.tokens.pop_front() {
+                Some(token) => token.text,
+                None => return Err(format!("missing arguments for {}", name)),
+            };
+            arguments.insert(parameter.as_str(), argument);
+        }
+        let line = self.line;
+        for text in self.macros[name].body.iter().rev() {
+            let text = arguments.get(text.as_str()).unwrap_or(text).clone();
+            self.tokens.push_front(Token { text, line });
+        }
+        Ok(())
+    }
+
+    // Expression of :calc, up to the closing } or )
+    fn calc(&mut self) -> Result<f64, String> {
+        let left = self.calc_term()?;
+        let operator = match self.peek() {
+            Some("}") | Some(")") | None => return Ok(left),
+            Some(operator) => operator.to_string(),
+        };
+        self.next()?;
+        // Everything on the right is evaluated first
+        let right = self.calc()?;
+        let truth = |condition: bool| if condition { 1.0 } else { 0.0 };
+        Ok(match operator.as_str() {
+            "+" => left + right,
+            "-" => left - right,
+            "*" => left * right,
+            "/" => left / right,
+            "%" => left % right,
+            "&" => (left as i64 & right as i64) as f64,
+            "|" => (left as i64 | right as i64) as f64,
+            "^" => (left as i64 ^ right as i64) as f64,
+            "<<" => (left as i64).checked_shl(right as u32).unwrap_or(0) as f64,
+            ">>" => (left as i64).checked_shr(right as u32).unwrap_or(0) as f64,
+            "pow" => left.powf(right),
+            "min" => left.min(right),
+            "max" => left.max(right),
+            "<" => truth(left < right),
+            ">" => truth(left > right),
+            "<=" => truth(left <= right),
+            ">=" => truth(left >= right),
+            "==" => truth(left == right),
+            "!=" => truth(left != right),
+            _ => return Err(format!("unknown operator {}", operator)),
+        })
+    }
+
+    fn calc_term(&mut self) -> Result<f64, String> {
+        let token = self.next()?;
+        let unary: Option<fn(f64) -> f64> = match token.as_str() {
+            "(" => {
+                let value = self.calc()?;
+                self.expect(")")?;
+                return Ok(value);
+            }
+            "HERE" => return Ok(self.address as f64),
+            "PI" => return Ok(consts::PI),
+            "E" => return Ok(consts::E),
+            "-" => Some(|value: f64| -value),
+            "~" => Some(|value: f64| !(value as i64) as f64),
+            "!" => Some(|value: f64| if value == 0.0 { 1.0 } else { 0.0 }),
+            "sin" => Some(f64::sin),
+            "cos" => Some(f64::cos),
+            "tan" => Some(f64::tan),
+            "exp" => Some(f64::exp),
+            "log" => Some(f64::ln),
+            "abs" => Some(f64::abs),
+            "sqrt" => Some(f64::sqrt),
+            "sign" => Some(f64::signum),
+            "ceil" => Some(f64::ceil),
+            "floor" => Some(f64::floor),
+            _ => None,
+        };
+        if let Some(unary) = unary {
+            return Ok(unary(self.calc_term()?));
+        }
+        // @ reads a byte already compiled
+        if token == "@" {
+            let address = self.calc_term()? as usize;
+            let offset = address.wrapping_sub(DEFAULT_LOAD_ADDRESS as usize);
+            return Ok(self.rom.get(offset).cloned().unwrap_or(0) as f64);
+        }
+        match self.known_value(&token) {
+            Some(value) => Ok(value),
+            None if is_name(&token) => Err(format!("undefined name {}", token)),
+            None => Err(format!("expected a value, found {}", token)),
+        }
+    }
+
+    fn define_label(&mut self, name: String, address: usize) -> Result<(), String> {
+        if self.labels.contains_key(&name) || self.constants.contains_key(&name) {
+            return Err(format!("{} is already defined", name));
+        }
+        self.labels.insert(name, address as u16);
+        Ok(())
+    }
+
+    // Constants can be redefined, which :calc relies on
+    fn define_constant(&mut self, name: String, value: f64) -> Result<(), String> {
+        if self.labels.contains_key(&name) {
+            return Err(format!("{} is already defined", name));
+        }
+        self.constants.insert(name, value);
+        Ok(())
+    }
+
+    // Returns the address of the instruction
+    fn instruction(&mut self, instruction: Instruction) -> Result<usize, String> {
+        let at = self.emit(&instruction.encode())?;
+        for name in mem::take(&mut self.next_labels) {
+            self.define_label(name, at + 1)?;
+        }
+        Ok(at)
+    }
+
+    fn emit(&mut self, bytes: &[u8]) -> Result<usize, String> {
+        let at = self.address;
+        if at + bytes.len() > 0x10000 {
+            return Err("the program does not fit in memory".to_string());
+        }
+        let offset = at - DEFAULT_LOAD_ADDRESS as usize;
+        if self.rom.len() < offset + bytes.len() {
+            self.rom.resize(offset + bytes.len(), 0);
+        }
+        self.rom[offset..(offset + bytes.len())].copy_from_slice(bytes);
+        self.address += bytes.len();
+        Ok(at)
+    }
+
+    // Fills the address of the instruction at `at` now, or once the name is defined
+    fn reference(&mut self, at: usize, reference: Reference, target: Target) -> Result<(), String> {
+        match target {
+            Target::Value(value) => self.patch(at, reference, value),
+            Target::Name(name) => {
+                self.fixups.push(Fixup {
+                    at,
+                    reference,
+                    name,
+                    line: self.line,
+                });
+                Ok(())
+            }
+        }
+    }
+
+    fn patch(&mut self, at: usize, reference: Reference, value: i64) -> Result<(), String> {
+        let maximum = match reference {
+            Reference::Long => 0xFFFF,
+            _ => 0xFFF,
+        };
+        if !(0..=maximum).contains(&value) {
+            return Err(format!("address out of range: {:#x}", value));
+        }
+        let offset = at - DEFAULT_LOAD_ADDRESS as usize;
+        let rom = &mut self.rom[offset..];
+        match reference {
+            Reference::Address => {
+                rom[0] = (rom[0] & 0xF0) | (value >> 8) as u8;
+                rom[1] = value as u8;
+            }
+            Reference::Long => {
+                rom[2] = (value >> 8) as u8;
+                rom[3] = value as u8;
+            }
+            Reference::Unpack(nibble) => {
+                rom[1] = (nibble << 4) | (value >> 8) as u8;
+                rom[3] = value as u8;
+            }
+        }
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<String, String> {
+        match self.tokens.pop_front() {
+            Some(token) => {
+                self.line = token.line;
+                Ok(token.text)
+            }
+            None => Err("unexpected end of the program".to_string()),
+        }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.front().map(|token| token.text.as_str())
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), String> {
+        let token = self.next()?;
+        if token != expected {
+            return Err(format!("expected {}, found {}", expected, token));
+        }
+        Ok(())
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        let token = self.next()?;
+        if !is_name(&token) {
+            return Err(format!("invalid name {}", token));
+        }
+        Ok(token)
+    }
+
+    fn register(&mut self) -> Result<u8, String> {
+        let token = self.next()?;
+        self.register_named(&token)
+            .ok_or_else(|| format!("expected a register, found {}", token))
+    }
+
+    fn register_named(&self, name: &str) -> Option<u8> {
+        register(name).or_else(|| self.aliases.get(name).cloned())
+    }
+
+    fn alias(&self, name: &str, default: u8) -> u8 {
+        self.aliases.get(name).cloned().unwrap_or(default)
+    }
+
+    // Numbers, constants and labels that are already defined
+    fn known_value(&self, name: &str) -> Option<f64> {
+        number(name)
+            .map(|value| value as f64)
+            .or_else(|| self.constants.get(name).cloned())
+            .or_else(|| self.labels.get(name).map(|&address| address as f64))
+    }
+
+    fn value(&mut self) -> Result<i64, String> {
+        let token = self.next()?;
+        match self.known_value(&token) {
+            Some(value) => Ok(value.floor() as i64),
+            None => Err(format!("undefined name {}", token)),
+        }
+    }
+
+    fn byte_value(&mut self) -> Result<u8, String> {
+        byte(self.value()?)
+    }
+
+    fn nibble(&mut self) -> Result<u8, String> {
+        match self.value()? {
+            value @ 0..=0xF => Ok(value as u8),
+            value => Err(format!("nibble out of range: {}", value)),
+        }
+    }
+
+    // An address, which may be a label defined later
+    fn target(&mut self) -> Result<Target, String> {
+        let token = self.next()?;
+        match self.known_value(&token) {
+            Some(value) => Ok(Target::Value(value.floor() as i64)),
+            None if is_name(&token) => Ok(Target::Name(token)),
+            None => Err(format!("expected an address, found {}", token)),
+        }
+    }
+}
+
+// Words separated by whitespace, with comments from # to the end of the line
+fn tokenize(source: &str) -> VecDeque<Token> {
+    let mut tokens = VecDeque::new();
+    for (number, line) in source.lines().enumerate() {
+        let code = line.split('#').next().unwrap_or("");
+        for word in code.split_whitespace() {
+            tokens.push_back(Token {
+                text: word.to_string(),
+                line: number + 1,
+            });
+        }
+    }
+    tokens
+}
+
+// Decimal, 0x hexadecimal or 0b binary, optionally negative
+fn number(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let (radix, digits) = if let Some(hex) = digits.strip_prefix("0x") {
+        (16, hex)
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        (2, binary)
+    } else {
+        (10, digits)
+    };
+    if digits.is_empty() || !digits.chars().all(|digit| digit.is_digit(radix)) {
+        return None;
+    }
+    let value = i64::from_str_radix(digits, radix).ok()?;
+    Some(if negative { -value } else { value })
+}
+
+fn register(name: &str) -> Option<u8> {
+    let mut characters = name.chars();
+    match (characters.next(), characters.next(), characters.next()) {
+        (Some('v'), Some(digit), None) | (Some('V'), Some(digit), None) => {
+            digit.to_digit(16).map(|digit| digit as u8)
+        }
+        _ => None,
+    }
+}
+
+fn is_name(token: &str) -> bool {
+    !token.starts_with(':')
+        && !token.starts_with('{')
+        && number(token).is_none()
+        && register(token).is_none()
+        && !KEYWORDS.contains(&token)
+}
+
+// Bytes can be written signed
+fn byte(value: i64) -> Result<u8, String> {
+    if (-0x80..=0xFF).contains(&value) {
+        Ok(value as u8)
+    } else {
+        Err(format!("byte out of range: {}", value))
+    }
+}
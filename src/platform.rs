@@ -0,0 +1,56 @@
+/// Instruction set and memory layout the machine emulates.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Platform {
+    /// The original CHIP-8: 4 KiB of memory and the 35 original opcodes. This is the default, the
+    /// extensions change what some classic opcodes do (`Dxy0`, `00Cn`).
+    #[default]
+    Chip8,
+
+    /// CHIP-8 plus the SUPER-CHIP 1.1 extensions.
+    SuperChip,
+
+    /// SUPER-CHIP plus the XO-CHIP extensions: 64 KiB of memory, two bitplanes, long index loads,
+    /// register range save/load and audio patterns.
+    XoChip,
+}
+
+impl Platform {
+    /// Names accepted by `from_name`.
+    pub const NAMES: [&'static str; 3] = ["chip8", "schip", "xochip"];
+
+    /// Returns the platform with the given name (see `NAMES`).
+    pub fn from_name(name: &str) -> Option<Platform> {
+        match name {
+            "chip8" => Some(Platform::Chip8),
+            "schip" => Some(Platform::SuperChip),
+            "xochip" => Some(Platform::XoChip),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Platform::Chip8 => "chip8",
+            Platform::SuperChip => "schip",
+            Platform::XoChip => "xochip",
+        }
+    }
+
+    /// Size in bytes of the addressable memory.
+    pub fn memory_size(self) -> usize {
+        match self {
+            Platform::Chip8 | Platform::SuperChip => 0x1000,
+            Platform::XoChip => 0x10000,
+        }
+    }
+
+    /// Whether the SUPER-CHIP instructions are available.
+    pub fn has_super_chip(self) -> bool {
+        self != Platform::Chip8
+    }
+
+    /// Whether the XO-CHIP instructions are available.
+    pub fn has_xo_chip(self) -> bool {
+        self == Platform::XoChip
+    }
+}
@@ -0,0 +1,361 @@
+use instruction::Instruction;
+use platform::Platform;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+// Depth of the CHIP-8 call stack, deeper calls fail before they are executed
+const MAX_CALL_DEPTH: usize = 16;
+
+/// Executions of the instruction at an address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HotSpot {
+    pub count: u64,
+
+    /// The last opcode executed at the address, programs can modify themselves.
+    pub opcode: u16,
+    pub instruction: Option<Instruction>,
+}
+
+/// Instructions executed by a subroutine, or by the code reached from the entry point.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Function {
+    /// Times it was called with `2nnn`.
+    pub calls: u64,
+
+    /// Instructions executed by the function itself.
+    pub self_instructions: u64,
+
+    /// Instructions executed between its calls and the matching returns, including the ones of
+    /// the subroutines it called.
+    pub total_instructions: u64,
+}
+
+/// Where the cycles of a program go: executions per address and per opcode class, and the call
+/// graph built from the `2nnn` and `00EE` pairs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Profile {
+    /// Instructions executed while profiling.
+    pub instructions: u64,
+
+    pub hot_spots: BTreeMap<u16, HotSpot>,
+
+    /// Executions per opcode class, the first nibble of the opcode.
+    pub classes: [u64; 16],
+
+    /// Functions by address, the entry point being the function that was running when profiling
+    /// started.
+    pub functions: BTreeMap<u16, Function>,
+
+    /// Calls from a function (the caller address) to a subroutine (the callee address).
+    pub calls: BTreeMap<(u16, u16), u64>,
+
+    pub entry: u16,
+
+    // Functions being executed, with the instruction count when they were called
+    stack: Vec<(u16, u64)>,
+}
+
+impl Profile {
+    /// Starts an empty profile of the code running at `entry`.
+    pub fn new(entry: u16) -> Profile {
+        Profile {
+            instructions: 0,
+            hot_spots: BTreeMap::new(),
+            classes: [0; 16],
+            functions: BTreeMap::new(),
+            calls: BTreeMap::new(),
+            entry,
+            stack: Vec::new(),
+        }
+    }
+
+    // Counts the instruction at pc, about to be executed
+    pub(crate) fn record(&mut self, pc: u16, opcode: u16, memory: &[u8], platform: Platform) {
+        self.instructions += 1;
+        self.classes[(opcode >> 12) as usize] += 1;
+
+        let start = pc as usize;
+        let end = (start + 4).min(memory.len());
+        let decode = || Instruction::decode(&memory[start..end], platform);
+        let hot_spot = self.hot_spots.entry(pc).or_insert_with(|| HotSpot {
+            count: 0,
+            opcode,
+            instruction: decode(),
+        });
+        hot_spot.count += 1;
+        if hot_spot.opcode != opcode {
+            hot_spot.opcode = opcode;
+            hot_spot.instruction = decode();
+        }
+
+        let function = self
+            .stack
+            .last()
+            .map_or(self.entry, |&(function, _)| function);
+        self.functions
+            .entry(function)
+            .or_default()
+            .self_instructions += 1;
+        match opcode {
+            0x2000..=0x2FFF => {
+                let subroutine = opcode & 0x0FFF;
+                *self.calls.entry((function, subroutine)).or_insert(0) += 1;
+                self.functions.entry(subroutine).or_default().calls += 1;
+                if self.stack.len() == MAX_CALL_DEPTH {
+                    self.stack.remove(0);
+                }
+                self.stack.push((subroutine, self.instructions));
+            }
+            0x00EE => {
+                if let Some((subroutine, called_at)) = self.stack.pop() {
+                    let function = self.functions.entry(subroutine).or_default();
+                    function.total_instructions += self.instructions - called_at;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Addresses by decreasing number of executions.
+    pub fn hottest(&self) -> Vec<(u16, HotSpot)> {
+        let mut hot_spots: Vec<(u16, HotSpot)> = self
+            .hot_spots
+            .iter()
+            .map(|(&address, &hot_spot)| (address, hot_spot))
+            .collect();
+        hot_spots.sort_by(|a, b| b.1.count.cmp(&a.1.count).then(a.0.cmp(&b.0)));
+        hot_spots
+    }
+
+    /// Writes a human readable report: the `top` hottest addresses, the opcode classes and the
+    /// call graph.
+    pub fn write_report<W: Write>(&self, top: usize, out: &mut W) -> io::Result<()> {
+        writeln!(out, "Profile of {} instructions", self.instructions)?;
+        writeln!(out)?;
+        writeln!(out, "Hot spots:")?;
+        writeln!(out, "  address       count       %  instruction")?;
+        for (address, hot_spot) in self.hottest().into_iter().take(top) {
+            let text = match hot_spot.instruction {
+                Some(instruction) => instruction.to_string(),
+                None => format!("{:04x} (invalid opcode)", hot_spot.opcode),
+            };
+            writeln!(
+                out,
+                "  {:<7} {:>11} {:>6.2}%  {}",
+                format!("{:#05x}", address),
+                hot_spot.count,
+                self.percent(hot_spot.count),
+                text
+            )?;
+        }
+
+        writeln!(out)?;
+        writeln!(out, "Opcode classes:")?;
+        writeln!(out, "  class        count       %")?;
+        for (class, &count) in self.classes.iter().enumerate() {
+            if count > 0 {
+                writeln!(
+                    out,
+                    "  {:X}xxx  {:>11} {:>6.2}%",
+                    class,
+                    count,
+                    self.percent(count)
+                )?;
+            }
+        }
+
+        writeln!(out)?;
+        writeln!(out, "Call graph:")?;
+        writeln!(out, "  function      calls         self        total")?;
+        let mut functions: Vec<(&u16, &Function)> = self.functions.iter().collect();
+        functions.sort_by_key(|&(_, function)| Reverse(function.self_instructions));
+        for (&address, function) in functions {
+            let total = if address == self.entry && function.calls == 0 {
+                "-".to_string()
+            } else {
+                function.total_instructions.to_string()
+            };
+            writeln!(
+                out,
+                "  {:<9} {:>8} {:>12} {:>12}",
+                format!(
+                    "{:#05x}{}",
+                    address,
+                    if address == self.entry { "*" } else { "" }
+                ),
+                function.calls,
+                function.self_instructions,
+                total
+            )?;
+            for (&(caller, _), count) in self
+                .calls
+                .iter()
+                .filter(|&(&(_, callee), _)| callee == address)
+            {
+                writeln!(out, "      called {} times from {:#05x}", count, caller)?;
+            }
+        }
+        writeln!(out, "  (* entry point)")
+    }
+
+    /// Writes the whole profile as a JSON object with the fields `instructions`, `entry`,
+    /// `hot_spots`, `classes`, `functions` and `calls`. Addresses and opcodes are numbers.
+    pub fn write_json<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(out, "{{")?;
+        writeln!(out, "  \"instructions\": {},", self.instructions)?;
+        writeln!(out, "  \"entry\": {},", self.entry)?;
+
+        writeln!(out, "  \"hot_spots\": [")?;
+        let hot_spots = self.hottest();
+        for (index, (address, hot_spot)) in hot_spots.iter().enumerate() {
+            let instruction = match hot_spot.instruction {
+                Some(instruction) => format!("\"{}\"", instruction),
+                None => "null".to_string(),
+            };
+            writeln!(
+                out,
+                "    {{\"address\": {}, \"count\": {}, \"opcode\": {}, \"instruction\": {}}}{}",
+                address,
+                hot_spot.count,
+                hot_spot.opcode,
+                instruction,
+                separator(index, hot_spots.len())
+            )?;
+        }
+        writeln!(out, "  ],")?;
+
+        let classes: Vec<String> = self.classes.iter().map(u64::to_string).collect();
+        writeln!(out, "  \"classes\": [{}],", classes.join(", "))?;
+
+        writeln!(out, "  \"functions\": [")?;
+        for (index, (address, function)) in self.functions.iter().enumerate() {
+            writeln!(
+                out,
+                "    {{\"address\": {}, \"calls\": {}, \"self\": {}, \"total\": {}}}{}",
+                address,
+                function.calls,
+                function.self_instructions,
+                function.total_instructions,
+                separator(index, self.functions.len())
+            )?;
+        }
+        writeln!(out, "  ],")?;
+
+        writeln!(out, "  \"calls\": [")?;
+        for (index, (&(caller, callee), count)) in self.calls.iter().enumerate() {
+            writeln!(
+                out,
+                "    {{\"caller\": {}, \"callee\": {}, \"count\": {}}}{}",
+                caller,
+                callee,
+                count,
+                separator(index, self.calls.len())
+            )?;
+        }
+        writeln!(out, "  ]")?;
+        writeln!(out, "}}")
+    }
+
+    fn percent(&self, count: u64) -> f64 {
+        if self.instructions == 0 {
+            0.0
+        } else {
+            count as f64 * 100.0 / self.instructions as f64
+        }
+    }
+}
+
+fn separator(index: usize, len: usize) -> &'static str {
+    if index + 1 < len {
+        ","
+    } else {
+        ""
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(profile: &mut Profile, trace: &[(u16, u16)]) {
+        let mut memory = vec![0x0; 0x1000];
+        for &(pc, opcode) in trace {
+            memory[pc as usize..pc as usize + 2].copy_from_slice(&opcode.to_be_bytes());
+        }
+        for &(pc, opcode) in trace {
+            profile.record(pc, opcode, &memory, Platform::Chip8);
+        }
+    }
+
+    fn function(calls: u64, self_instructions: u64, total_instructions: u64) -> Function {
+        Function {
+            calls,
+            self_instructions,
+            total_instructions,
+        }
+    }
+
+    #[test]
+    fn calls_and_returns_account_the_instructions_of_each_function() {
+        let mut profile = Profile::new(0x200);
+        record(
+            &mut profile,
+            &[
+                (0x200, 0x2300),
+                (0x300, 0x6001),
+                (0x302, 0x2400),
+                (0x400, 0x00EE),
+                (0x304, 0x00EE),
+                (0x202, 0x1202),
+                (0x202, 0x1202),
+            ],
+        );
+
+        assert_eq!(profile.instructions, 7);
+        assert_eq!(profile.functions[&0x200], function(0, 3, 0));
+        assert_eq!(profile.functions[&0x300], function(1, 3, 4));
+        assert_eq!(profile.functions[&0x400], function(1, 1, 1));
+        assert_eq!(profile.calls[&(0x200, 0x300)], 1);
+        assert_eq!(profile.calls[&(0x300, 0x400)], 1);
+        assert_eq!(profile.classes[0x0], 2);
+        assert_eq!(profile.classes[0x1], 2);
+        assert_eq!(profile.classes[0x2], 2);
+        assert_eq!(profile.classes[0x6], 1);
+        assert_eq!(profile.hottest()[0].0, 0x202);
+        assert_eq!(profile.hot_spots[&0x202].count, 2);
+    }
+
+    #[test]
+    fn calls_deeper_than_the_stack_forget_the_outermost_one() {
+        let mut profile = Profile::new(0x200);
+        // 17 nested calls: 0x200 calls 0x300, which calls 0x302, and so on up to 0x320
+        let mut trace = vec![(0x200, 0x2300)];
+        for depth in 0..16 {
+            trace.push((0x300 + depth * 2, 0x2302 + depth * 2));
+        }
+        // One return more than the stack holds
+        trace.extend((0..17).map(|_| (0x400, 0x00EE)));
+        record(&mut profile, &trace);
+
+        // The return of 0x300 was not seen, the one of 0x302 came after 31 instructions
+        assert_eq!(profile.functions[&0x300], function(1, 1, 0));
+        assert_eq!(profile.functions[&0x302].total_instructions, 31);
+        assert_eq!(profile.functions[&0x320].total_instructions, 1);
+        // The extra return runs in the entry point
+        assert_eq!(profile.functions[&0x200].self_instructions, 2);
+    }
+
+    #[test]
+    fn json_has_the_entry_point() {
+        let mut profile = Profile::new(0x200);
+        record(&mut profile, &[(0x200, 0x2300), (0x300, 0x00EE)]);
+        let mut json = Vec::new();
+        profile.write_json(&mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+
+        assert!(json.contains("\"entry\": 512,"));
+        assert!(json.contains("{\"caller\": 512, \"callee\": 768, \"count\": 1}"));
+        assert!(json.contains("\"classes\": [1, 0, 1, 0,"));
+    }
+}
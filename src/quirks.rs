@@ -0,0 +1,108 @@
+/// Interpretation of the opcodes whose behaviour differs between CHIP-8 implementations.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    /// `8xy6`/`8xyE` shift Vx in place. When false, Vy is shifted and the result stored in Vx.
+    pub shift: bool,
+
+    /// How far `Fx55`/`Fx65` move I after storing or loading the registers.
+    pub load_store: IndexIncrement,
+
+    /// `Bnnn` is read as `Bxnn` and jumps to nnn + Vx instead of nnn + V0.
+    pub jump: bool,
+
+    /// `8xy1`/`8xy2`/`8xy3` reset VF to 0.
+    pub vf_reset: bool,
+
+    /// `Dxyn` clips sprites at the screen edges instead of wrapping them around.
+    pub clipping: bool,
+
+    /// `Dxyn` waits for the vertical blank, so at most one sprite is drawn per frame.
+    pub display_wait: bool,
+}
+
+impl Quirks {
+    /// The original COSMAC VIP interpreter.
+    pub const COSMAC_VIP: Quirks = Quirks {
+        shift: false,
+        load_store: IndexIncrement::XPlusOne,
+        jump: false,
+        vf_reset: true,
+        clipping: true,
+        display_wait: true,
+    };
+
+    /// CHIP-48 for the HP-48 calculators, like SUPER-CHIP 1.1 except for the off by one
+    /// increment of I by `Fx55`/`Fx65`.
+    pub const CHIP_48: Quirks = Quirks {
+        shift: true,
+        load_store: IndexIncrement::X,
+        jump: true,
+        vf_reset: false,
+        clipping: true,
+        display_wait: false,
+    };
+
+    /// SUPER-CHIP 1.1.
+    pub const SUPER_CHIP: Quirks = Quirks {
+        shift: true,
+        load_store: IndexIncrement::None,
+        jump: true,
+        vf_reset: false,
+        clipping: true,
+        display_wait: false,
+    };
+
+    /// What most modern interpreters do. This is the default.
+    pub const MODERN: Quirks = Quirks {
+        shift: true,
+        load_store: IndexIncrement::None,
+        jump: false,
+        vf_reset: false,
+        clipping: false,
+        display_wait: false,
+    };
+
+    /// Names accepted by `from_name`.
+    pub const NAMES: [&'static str; 4] = ["vip", "chip48", "schip", "modern"];
+
+    /// Returns the preset with the given name (see `NAMES`).
+    pub fn from_name(name: &str) -> Option<Quirks> {
+        match name {
+            "vip" => Some(Quirks::COSMAC_VIP),
+            "chip48" => Some(Quirks::CHIP_48),
+            "schip" => Some(Quirks::SUPER_CHIP),
+            "modern" => Some(Quirks::MODERN),
+            _ => None,
+        }
+    }
+}
+
+/// Where `Fx55`/`Fx65` leave I after storing or loading V0 to Vx.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndexIncrement {
+    /// I is left unchanged, like SUPER-CHIP 1.1 and most modern interpreters do.
+    None,
+
+    /// I = I + x, pointing at the last register stored or loaded, like CHIP-48 does.
+    X,
+
+    /// I = I + x + 1, pointing past the last register stored or loaded, like the COSMAC VIP does.
+    XPlusOne,
+}
+
+impl IndexIncrement {
+    /// Amount added to I by `Fx55`/`Fx65` for register `x`.
+    pub fn amount(self, x: u8) -> u16 {
+        match self {
+            IndexIncrement::None => 0,
+            IndexIncrement::X => x as u16,
+            IndexIncrement::XPlusOne => x as u16 + 1,
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Quirks {
+        Quirks::MODERN
+    }
+}
@@ -0,0 +1,39 @@
+use renderers::audio::{Audio, SAMPLE_RATE};
+use std::io::{stdout, Write};
+
+// Rings the terminal bell (BEL) when a sound starts, terminals can not play the tone itself
+pub struct BellAudio {
+    // The previous frame was not silent
+    sounding: bool,
+    muted: bool,
+}
+
+impl BellAudio {
+    pub fn new() -> Self {
+        BellAudio {
+            sounding: false,
+            muted: false,
+        }
+    }
+}
+
+impl Audio for BellAudio {
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn play(&mut self, samples: &[f32]) {
+        let sounding = samples.iter().any(|&sample| sample != 0.0);
+        if sounding && !self.sounding && !self.muted {
+            // A closed output, e.g. piped to head, only loses the bell
+            let mut stdout = stdout();
+            let _ = stdout.write_all(b"\x07").and_then(|_| stdout.flush());
+        }
+        self.sounding = sounding;
+    }
+
+    fn toggle_mute(&mut self) -> bool {
+        self.muted = !self.muted;
+        self.muted
+    }
+}
@@ -0,0 +1,31 @@
+pub mod bell_audio;
+pub mod null_audio;
+pub mod sdl_audio;
+pub mod wav_audio;
+
+use std::io;
+
+// Sample rate of the backends that do not depend on a device
+pub const SAMPLE_RATE: u32 = 44100;
+
+// Gain unless configured otherwise, full scale square waves are loud
+pub const DEFAULT_VOLUME: f32 = 0.25;
+
+pub trait Audio {
+    // Samples per second expected by play
+    fn sample_rate(&self) -> u32;
+
+    // Outputs one frame of mono samples in [-1.0, 1.0]
+    fn play(&mut self, samples: &[f32]);
+
+    // Gain from 0.0 to 1.0
+    fn set_volume(&mut self, _volume: f32) {}
+
+    // Silences the output or restores it, returns whether it is now muted
+    fn toggle_mute(&mut self) -> bool;
+
+    // Writes what is still buffered before the emulator exits
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,25 @@
+use renderers::audio::{Audio, SAMPLE_RATE};
+
+// Discards the sound, for runs without a sound device
+pub struct NullAudio {
+    muted: bool,
+}
+
+impl NullAudio {
+    pub fn new() -> Self {
+        NullAudio { muted: false }
+    }
+}
+
+impl Audio for NullAudio {
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn play(&mut self, _samples: &[f32]) {}
+
+    fn toggle_mute(&mut self) -> bool {
+        self.muted = !self.muted;
+        self.muted
+    }
+}
@@ -0,0 +1,77 @@
+extern crate sdl2;
+use renderers::audio::{Audio, DEFAULT_VOLUME, SAMPLE_RATE};
+use sdl2::audio::{AudioQueue, AudioSpecDesired};
+use sdl2::Sdl;
+
+// Frames of samples the queue holds before samples are dropped so the sound does not lag behind
+// the game
+const MAX_QUEUED_FRAMES: usize = 4;
+
+pub struct SdlAudio {
+    queue: AudioQueue<f32>,
+    // Gain applied to the samples, from 0.0 to 1.0
+    volume: f32,
+    muted: bool,
+    // Last sample played, to find the zero crossings across frames
+    last_sample: f32,
+}
+
+impl SdlAudio {
+    pub fn new(sdl: &Sdl) -> Self {
+        let audio_subsystem = sdl.audio().unwrap();
+        let desired_spec = AudioSpecDesired {
+            freq: Some(SAMPLE_RATE as i32),
+            channels: Some(1),
+            samples: None,
+        };
+        let queue = audio_subsystem
+            .open_queue::<f32, _>(None, &desired_spec)
+            .unwrap();
+        queue.resume();
+
+        SdlAudio {
+            queue,
+            volume: DEFAULT_VOLUME,
+            muted: false,
+            last_sample: 0.0,
+        }
+    }
+}
+
+impl Audio for SdlAudio {
+    // The device may not support the requested rate
+    fn sample_rate(&self) -> u32 {
+        self.queue.spec().freq as u32
+    }
+
+    fn set_volume(&mut self, volume: f32) {
+        self.volume = volume;
+    }
+
+    fn toggle_mute(&mut self) -> bool {
+        self.muted = !self.muted;
+        self.muted
+    }
+
+    // Queues one frame of samples for playback. While the queue is too long, samples are dropped
+    // one at a time where the wave crosses zero (or is silent) so the sound catches up without the
+    // clicks of skipping whole frames
+    fn play(&mut self, samples: &[f32]) {
+        let queued = self.queue.size() as usize / std::mem::size_of::<f32>();
+        let mut excess = queued.saturating_sub(samples.len() * MAX_QUEUED_FRAMES);
+
+        // Muted frames are still queued as silence to keep the queue filled
+        let volume = if self.muted { 0.0 } else { self.volume };
+        let mut kept = Vec::with_capacity(samples.len());
+        for &sample in samples {
+            let crossing = sample == 0.0 || (sample > 0.0) != (self.last_sample > 0.0);
+            self.last_sample = sample;
+            if excess > 0 && crossing {
+                excess -= 1;
+                continue;
+            }
+            kept.push(sample * volume);
+        }
+        self.queue.queue(&kept);
+    }
+}
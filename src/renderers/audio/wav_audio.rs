@@ -0,0 +1,93 @@
+use renderers::audio::{Audio, DEFAULT_VOLUME, SAMPLE_RATE};
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+
+// Size of the RIFF header, the fmt chunk and the header of the data chunk
+const HEADER_SIZE: u32 = 44;
+const BYTES_PER_SAMPLE: u16 = 2;
+
+// Writes the sound to a 16-bit mono PCM WAV file instead of playing it, which gives the same
+// file for the same run when combined with the headless renderer
+pub struct WavAudio {
+    writer: BufWriter<File>,
+    // Bytes of samples written so far
+    data_size: u32,
+    volume: f32,
+    muted: bool,
+    // First write error, play can not report it
+    error: Option<io::Error>,
+}
+
+impl WavAudio {
+    pub fn new(path: &str) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        // The sizes are written by finish once they are known
+        write_header(&mut writer, 0)?;
+        Ok(WavAudio {
+            writer,
+            data_size: 0,
+            volume: DEFAULT_VOLUME,
+            muted: false,
+            error: None,
+        })
+    }
+}
+
+fn write_header<W: Write>(out: &mut W, data_size: u32) -> io::Result<()> {
+    out.write_all(b"RIFF")?;
+    out.write_all(&(HEADER_SIZE - 8 + data_size).to_le_bytes())?;
+    out.write_all(b"WAVE")?;
+
+    out.write_all(b"fmt ")?;
+    out.write_all(&16u32.to_le_bytes())?;
+    // PCM, one channel
+    out.write_all(&1u16.to_le_bytes())?;
+    out.write_all(&1u16.to_le_bytes())?;
+    out.write_all(&SAMPLE_RATE.to_le_bytes())?;
+    out.write_all(&(SAMPLE_RATE * BYTES_PER_SAMPLE as u32).to_le_bytes())?;
+    out.write_all(&BYTES_PER_SAMPLE.to_le_bytes())?;
+    out.write_all(&(BYTES_PER_SAMPLE * 8).to_le_bytes())?;
+
+    out.write_all(b"data")?;
+    out.write_all(&data_size.to_le_bytes())
+}
+
+impl Audio for WavAudio {
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn play(&mut self, samples: &[f32]) {
+        if self.error.is_some() {
+            return;
+        }
+        let volume = if self.muted { 0.0 } else { self.volume };
+        let mut bytes = Vec::with_capacity(samples.len() * BYTES_PER_SAMPLE as usize);
+        for sample in samples {
+            let value = ((sample * volume).clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        match self.writer.write_all(&bytes) {
+            Ok(()) => self.data_size += bytes.len() as u32,
+            Err(error) => self.error = Some(error),
+        }
+    }
+
+    fn set_volume(&mut self, volume: f32) {
+        self.volume = volume;
+    }
+
+    fn toggle_mute(&mut self) -> bool {
+        self.muted = !self.muted;
+        self.muted
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+        self.writer.seek(SeekFrom::Start(0))?;
+        write_header(&mut self.writer, self.data_size)?;
+        self.writer.flush()
+    }
+}
@@ -0,0 +1,11 @@
+use chip8emu::Screen;
+use renderers::graphics::Graphics;
+
+// Draws nothing, for runs that only report what the machine does
+pub struct HeadlessGraphics;
+
+impl Graphics for HeadlessGraphics {
+    fn initialize(&mut self) {}
+
+    fn draw(&mut self, _screen: Screen) {}
+}
@@ -1,7 +1,17 @@
+pub mod headless_graphics;
+pub mod palette;
 pub mod sdl_graphics;
 pub mod termion_graphics;
 
+use chip8emu::Screen;
+
 pub trait Graphics {
     fn initialize(&mut self);
-    fn draw(&mut self, screen: [[u8; 8]; 32]);
+    fn draw(&mut self, screen: Screen);
+
+    // Gives the terminal back while the debugger prompt is open
+    fn suspend(&mut self) {}
+
+    // Takes the terminal again, the next draw repaints the screen
+    fn resume(&mut self) {}
 }
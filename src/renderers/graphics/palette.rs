@@ -0,0 +1,220 @@
+use std::fs;
+
+pub type Rgb = (u8, u8, u8);
+
+// Colour of each pixel value: background, plane 1, plane 2 and both planes (XO-CHIP)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Palette {
+    pub colors: [Rgb; 4],
+}
+
+// Names accepted by from_name, the first one is the default
+pub const PRESET_NAMES: [&str; 5] = ["default", "amber", "green", "lcd", "high-contrast"];
+
+const PRESETS: [[Rgb; 4]; 5] = [
+    // White on black, with the orange planes of Octo
+    [(0, 0, 0), (255, 255, 255), (255, 102, 0), (102, 34, 0)],
+    // Amber monochrome monitor
+    [(20, 12, 0), (255, 176, 0), (179, 107, 0), (255, 224, 160)],
+    // Green phosphor monitor
+    [(0, 20, 0), (51, 255, 51), (0, 160, 0), (176, 255, 176)],
+    // Dark pixels on the greenish screen of a handheld LCD
+    [(155, 188, 15), (15, 56, 15), (139, 172, 15), (48, 98, 48)],
+    // Saturated colours for low vision
+    [(0, 0, 0), (255, 255, 255), (255, 255, 0), (0, 255, 255)],
+];
+
+impl Palette {
+    pub fn from_name(name: &str) -> Option<Palette> {
+        PRESET_NAMES
+            .iter()
+            .position(|&preset| preset == name)
+            .map(|index| Palette {
+                colors: PRESETS[index],
+            })
+    }
+
+    // Parses a preset name, or background and foreground colours followed by the colours of
+    // plane 2 and both planes, comma separated hexadecimal RRGGBB with an optional #. The planes
+    // keep the default colours when only two are given
+    pub fn parse(value: &str) -> Result<Palette, String> {
+        if let Some(palette) = Palette::from_name(value) {
+            return Ok(palette);
+        }
+        let colors = value
+            .split(',')
+            .map(|color| parse_color(color.trim()))
+            .collect::<Result<Vec<Rgb>, String>>()?;
+        let mut palette = Palette::default();
+        match colors.len() {
+            2 | 4 => palette.colors[..colors.len()].copy_from_slice(&colors),
+            _ => return Err(format!("expected 2 or 4 colours: {}", value)),
+        }
+        Ok(palette)
+    }
+
+    // Reads a palette file: name = value lines, where the names are preset (the palette the other
+    // lines change), background (or color0), foreground (or color1), color2 and color3. Lines
+    // starting with # and whatever follows the value are comments
+    pub fn read(path: &str) -> Result<Palette, String> {
+        let text = fs::read_to_string(path).map_err(|error| format!("{}: {}", path, error))?;
+        let mut palette = Palette::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: String| format!("{}:{}: {}", path, number + 1, message);
+
+            let mut parts = line.splitn(2, '=');
+            let name = parts.next().unwrap().trim();
+            let value = match parts.next() {
+                Some(value) => value.split_whitespace().next().unwrap_or(""),
+                None => return Err(error(format!("expected name = value: {}", line))),
+            };
+            let index = match name {
+                "preset" => {
+                    palette = Palette::from_name(value)
+                        .ok_or_else(|| error(format!("unknown preset: {}", value)))?;
+                    continue;
+                }
+                "background" | "color0" => 0,
+                "foreground" | "color1" => 1,
+                "color2" => 2,
+                "color3" => 3,
+                _ => return Err(error(format!("unknown name: {}", name))),
+            };
+            palette.colors[index] = parse_color(value).map_err(error)?;
+        }
+        Ok(palette)
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette { colors: PRESETS[0] }
+    }
+}
+
+// Parses RRGGBB or #RRGGBB
+fn parse_color(value: &str) -> Result<Rgb, String> {
+    let hex = value.strip_prefix('#').unwrap_or(value);
+    let component = |index: usize| {
+        hex.get(index * 2..index * 2 + 2)
+            .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+    };
+    match (component(0), component(1), component(2)) {
+        (Some(r), Some(g), Some(b))
+            if hex.len() == 6 && hex.chars().all(|digit| digit.is_ascii_hexdigit()) =>
+        {
+            Ok((r, g, b))
+        }
+        _ => Err(format!("invalid colour: {}", value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    const DEFAULT: [Rgb; 4] = PRESETS[0];
+
+    // Reads `text` from a palette file unique to the test
+    fn read(name: &str, text: &str) -> Result<Palette, String> {
+        let path = env::temp_dir().join(format!("chip8emu-{}-{}.palette", name, process::id()));
+        let path = path.to_str().unwrap();
+        fs::write(path, text).unwrap();
+        let palette = Palette::read(path);
+        fs::remove_file(path).unwrap();
+        palette.map_err(|error| error.replacen(path, "FILE", 1))
+    }
+
+    #[test]
+    fn parse_accepts_preset_names() {
+        assert_eq!(Palette::parse("default"), Ok(Palette::default()));
+        assert_eq!(Palette::parse("amber").unwrap().colors, PRESETS[1]);
+    }
+
+    #[test]
+    fn parse_keeps_the_plane_colours_with_two_colours() {
+        let palette = Palette::parse("102030,#A0b0C0").unwrap();
+        assert_eq!(
+            palette.colors,
+            [
+                (0x10, 0x20, 0x30),
+                (0xA0, 0xB0, 0xC0),
+                DEFAULT[2],
+                DEFAULT[3]
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_sets_every_colour_with_four_colours() {
+        let palette = Palette::parse("#000000, #ffffff, #ff0000, 00ff00").unwrap();
+        assert_eq!(
+            palette.colors,
+            [(0, 0, 0), (255, 255, 255), (255, 0, 0), (0, 255, 0)]
+        );
+    }
+
+    #[test]
+    fn parse_rejects_bad_input() {
+        assert_eq!(
+            Palette::parse("000000"),
+            Err("expected 2 or 4 colours: 000000".to_string())
+        );
+        assert_eq!(
+            Palette::parse("000000,ffffff,ff0000"),
+            Err("expected 2 or 4 colours: 000000,ffffff,ff0000".to_string())
+        );
+        assert_eq!(
+            Palette::parse("000000,fffff"),
+            Err("invalid colour: fffff".to_string())
+        );
+        assert_eq!(
+            Palette::parse("000000,+fffff"),
+            Err("invalid colour: +fffff".to_string())
+        );
+        assert_eq!(
+            Palette::parse("000000,##ffffff"),
+            Err("invalid colour: ##ffffff".to_string())
+        );
+        assert_eq!(
+            Palette::parse("purple"),
+            Err("invalid colour: purple".to_string())
+        );
+    }
+
+    #[test]
+    fn read_applies_the_lines_over_the_preset() {
+        let text = "# Amber with a black background\n\
+                    preset = amber\n\
+                    \n\
+                    background = #000000 ignored comment\n\
+                    color3 = 112233\n";
+        let palette = read("valid", text).unwrap();
+        assert_eq!(
+            palette.colors,
+            [(0, 0, 0), PRESETS[1][1], PRESETS[1][2], (0x11, 0x22, 0x33)]
+        );
+    }
+
+    #[test]
+    fn read_reports_the_line_of_errors() {
+        assert_eq!(
+            read("name", "foreground = ffffff\ncolour4 = 000000\n"),
+            Err("FILE:2: unknown name: colour4".to_string())
+        );
+        assert_eq!(
+            read("value", "\nbackground = 00000g\n"),
+            Err("FILE:2: invalid colour: 00000g".to_string())
+        );
+        assert_eq!(
+            read("preset", "preset = sepia\n"),
+            Err("FILE:1: unknown preset: sepia".to_string())
+        );
+    }
+}
@@ -1,4 +1,6 @@
 extern crate sdl2;
+use chip8emu::Screen;
+use renderers::graphics::palette::Palette;
 use renderers::graphics::Graphics;
 use sdl2::pixels::Color;
 use sdl2::rect::Point;
@@ -6,28 +8,31 @@ use sdl2::render::Canvas;
 use sdl2::video::Window;
 use sdl2::Sdl;
 
+const WINDOW_WIDTH: u32 = 640;
+const WINDOW_HEIGHT: u32 = 320;
+
 pub struct SdlGraphics {
     canvas: Canvas<Window>,
+    palette: Palette,
 }
 
 impl SdlGraphics {
-    pub fn new(sdl: &Sdl) -> Self {
+    pub fn new(sdl: &Sdl, palette: Palette) -> Self {
         let video_subsystem = sdl.video().unwrap();
         let window = video_subsystem
-            .window("Chip 8 Emulator", 640, 320)
+            .window("Chip 8 Emulator", WINDOW_WIDTH, WINDOW_HEIGHT)
             .position_centered()
             .build()
             .unwrap();
 
-        let mut canvas = window.into_canvas().build().unwrap();
-
-        canvas.set_scale(10.0, 10.0).unwrap();
+        let canvas = window.into_canvas().build().unwrap();
 
-        SdlGraphics { canvas }
+        SdlGraphics { canvas, palette }
     }
 
     fn clear_screen(&mut self) {
-        self.canvas.set_draw_color(Color::RGB(0, 0, 0));
+        let (r, g, b) = self.palette.colors[0];
+        self.canvas.set_draw_color(Color::RGB(r, g, b));
         self.canvas.clear();
     }
 }
@@ -38,20 +43,24 @@ impl Graphics for SdlGraphics {
         self.canvas.present();
     }
 
-    fn draw(&mut self, screen: [[u8; 8]; 32]) {
+    fn draw(&mut self, screen: Screen) {
+        // Scale the current resolution (64x32 or 128x64) to the window
+        let scale_x = WINDOW_WIDTH as f32 / screen.width() as f32;
+        let scale_y = WINDOW_HEIGHT as f32 / screen.height() as f32;
+        self.canvas.set_scale(scale_x, scale_y).unwrap();
+
         // Clear screen
         self.clear_screen();
 
-        // Sets white colro for drawing the pixels.
-        self.canvas.set_draw_color(Color::RGB(255, 255, 255));
-
-        // Draw the screen
-        for y in 0..32 {
-            for x in 0..8 {
-                for i in 0..8 {
-                    if ((screen[y as usize][x as usize] << i) & 0x80) == 0x80 {
-                        let x_coord = (x * 8) + i;
-                        self.canvas.draw_point(Point::new(x_coord, y)).unwrap();
+        // Draw the screen, one pass for each of the colours used by the bitplanes
+        for (color, &(r, g, b)) in self.palette.colors.iter().enumerate().skip(1) {
+            self.canvas.set_draw_color(Color::RGB(r, g, b));
+            for y in 0..screen.height() {
+                for x in 0..screen.width() {
+                    if screen.color(x, y) as usize == color {
+                        self.canvas
+                            .draw_point(Point::new(x as i32, y as i32))
+                            .unwrap();
                     }
                 }
             }
@@ -1,80 +1,174 @@
 extern crate termion;
+use chip8emu::screen::LORES_WIDTH;
+use chip8emu::Screen;
+use renderers::graphics::palette::{Palette, Rgb};
 use renderers::graphics::Graphics;
+use std::env;
 use std::io::{stdout, Stdout, Write};
 use termion::raw::IntoRawMode;
-use termion::{clear, cursor};
+use termion::{clear, color, cursor};
 
 pub struct TermionGraphics {
     output_stream: termion::raw::RawTerminal<Stdout>,
+    // Width in pixels of the mode the screen box was drawn for
+    current_width: usize,
+    // Escape codes selecting each colour of the palette as foreground and as background
+    foregrounds: [String; 4],
+    backgrounds: [String; 4],
 }
 
 impl TermionGraphics {
-    pub fn new() -> Self {
+    pub fn new(palette: Palette) -> Self {
+        // Terminals announce 24-bit colour support in COLORTERM, the others get the closest
+        // colours of the 256 colour palette
+        let truecolor = env::var("COLORTERM")
+            .map(|colorterm| colorterm == "truecolor" || colorterm == "24bit")
+            .unwrap_or(false);
+        let foreground = |&(r, g, b): &Rgb| {
+            if truecolor {
+                color::Fg(color::Rgb(r, g, b)).to_string()
+            } else {
+                color::Fg(color::AnsiValue(ansi_256(r, g, b))).to_string()
+            }
+        };
+        let background = |&(r, g, b): &Rgb| {
+            if truecolor {
+                color::Bg(color::Rgb(r, g, b)).to_string()
+            } else {
+                color::Bg(color::AnsiValue(ansi_256(r, g, b))).to_string()
+            }
+        };
+
         TermionGraphics {
             output_stream: stdout().into_raw_mode().unwrap(),
+            current_width: LORES_WIDTH,
+            foregrounds: palette.colors.map(|rgb| foreground(&rgb)),
+            backgrounds: palette.colors.map(|rgb| background(&rgb)),
         }
     }
-}
 
-impl Graphics for TermionGraphics {
-    fn initialize(&mut self) {
-        // Clear and hide cursor
-        write!(self.output_stream, "{}", clear::All).unwrap();
-        write!(self.output_stream, "{}", cursor::Hide).unwrap();
+    // Draws the box around a screen of width x height pixels. Every character holds two rows.
+    fn draw_box(&mut self, width: usize, height: usize) {
+        let right: u16 = width as u16 + 2;
+        let bottom: u16 = (height / 2) as u16 + 2;
 
-        // Draw screen box
         write!(self.output_stream, "{}", clear::All).unwrap();
         // Top row
         write!(self.output_stream, "{}┌", cursor::Goto(1, 1)).unwrap();
-        for i in 2..66 {
+        for i in 2..right {
             write!(self.output_stream, "{}─", cursor::Goto(i, 1)).unwrap();
         }
-        write!(self.output_stream, "{}┐", cursor::Goto(66, 1)).unwrap();
+        write!(self.output_stream, "{}┐", cursor::Goto(right, 1)).unwrap();
 
         // Vertical rows
-        for i in 2..18 {
+        for i in 2..bottom {
             write!(self.output_stream, "{}│", cursor::Goto(1, i)).unwrap();
-            write!(self.output_stream, "{}│", cursor::Goto(66, i)).unwrap();
+            write!(self.output_stream, "{}│", cursor::Goto(right, i)).unwrap();
         }
 
         // Bottom row
-        write!(self.output_stream, "{}└", cursor::Goto(1, 18)).unwrap();
-        for i in 2..66 {
-            write!(self.output_stream, "{}─", cursor::Goto(i, 18)).unwrap();
+        write!(self.output_stream, "{}└", cursor::Goto(1, bottom)).unwrap();
+        for i in 2..right {
+            write!(self.output_stream, "{}─", cursor::Goto(i, bottom)).unwrap();
         }
-        write!(self.output_stream, "{}┘", cursor::Goto(66, 18)).unwrap();
+        write!(self.output_stream, "{}┘", cursor::Goto(right, bottom)).unwrap();
+    }
+}
+
+impl Graphics for TermionGraphics {
+    fn initialize(&mut self) {
+        // Clear and hide cursor
+        write!(self.output_stream, "{}", clear::All).unwrap();
+        write!(self.output_stream, "{}", cursor::Hide).unwrap();
+
+        // Draw screen box
+        let screen = Screen::new();
+        self.draw_box(screen.width(), screen.height());
     }
 
-    fn draw(&mut self, screen: [[u8; 8]; 32]) {
+    fn draw(&mut self, screen: Screen) {
         const PADDING: u16 = 2;
-        for y in (0..16).map(|x| x * 2) {
-            for x in 0..8 {
-                for i in 0..8 {
-                    let top_square: bool = (screen[y][x as usize] << i) & 0x80 == 0x80;
-                    let bottom_square: bool = (screen[y + 1_usize][x as usize] << i) & 0x80 == 0x80;
-                    let x_coord = (x * 8) + i + PADDING;
-                    let y_coord: u16 = (y / 2) as u16 + PADDING;
-                    match (top_square, bottom_square) {
-                        (true, true) => {
-                            write!(self.output_stream, "{}█", cursor::Goto(x_coord, y_coord))
-                                .unwrap()
-                        }
-                        (true, false) => {
-                            write!(self.output_stream, "{}▀", cursor::Goto(x_coord, y_coord))
-                                .unwrap()
-                        }
-                        (false, true) => {
-                            write!(self.output_stream, "{}▄", cursor::Goto(x_coord, y_coord))
-                                .unwrap()
-                        }
-                        (false, false) => {
-                            write!(self.output_stream, "{} ", cursor::Goto(x_coord, y_coord))
-                                .unwrap()
-                        }
-                    }
+
+        // Redraw the box when switching between low and high resolution
+        if screen.width() != self.current_width {
+            self.current_width = screen.width();
+            self.draw_box(screen.width(), screen.height());
+        }
+
+        // Every character is an upper half block showing two pixels, the top one in the
+        // foreground colour and the bottom one in the background colour. Colours are only sent
+        // when they change
+        for y in (0..screen.height() / 2).map(|y| y * 2) {
+            let y_coord: u16 = (y / 2) as u16 + PADDING;
+            let mut line = cursor::Goto(PADDING, y_coord).to_string();
+            let mut current = None;
+            for x in 0..screen.width() {
+                let top_color = screen.color(x, y) as usize;
+                let bottom_color = screen.color(x, y + 1) as usize;
+                if current != Some((top_color, bottom_color)) {
+                    current = Some((top_color, bottom_color));
+                    line.push_str(&self.foregrounds[top_color]);
+                    line.push_str(&self.backgrounds[bottom_color]);
                 }
+                line.push('▀');
             }
+            write!(
+                self.output_stream,
+                "{}{}{}",
+                line,
+                color::Fg(color::Reset),
+                color::Bg(color::Reset)
+            )
+            .unwrap();
         }
         self.output_stream.flush().unwrap();
     }
+
+    fn suspend(&mut self) {
+        // Continue below the screen box with a visible cursor and line editing
+        let bottom: u16 = (self.current_width / 4) as u16 + 3;
+        write!(
+            self.output_stream,
+            "{}{}",
+            cursor::Goto(1, bottom),
+            cursor::Show
+        )
+        .unwrap();
+        self.output_stream.flush().unwrap();
+        self.output_stream.suspend_raw_mode().unwrap();
+    }
+
+    fn resume(&mut self) {
+        self.output_stream.activate_raw_mode().unwrap();
+        write!(self.output_stream, "{}", cursor::Hide).unwrap();
+        let width = self.current_width;
+        self.draw_box(width, width / 2);
+    }
+}
+
+// Closest colour of the 6x6x6 cube or of the grey ramp of the 256 colour palette
+fn ansi_256(r: u8, g: u8, b: u8) -> u8 {
+    const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+    let closest_level = |value: u8| {
+        (0..LEVELS.len())
+            .min_by_key(|&level| (LEVELS[level] as i32 - value as i32).abs())
+            .unwrap()
+    };
+    let distance = |(r2, g2, b2): Rgb| {
+        let square = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        square(r, r2) + square(g, g2) + square(b, b2)
+    };
+
+    let (cube_r, cube_g, cube_b) = (closest_level(r), closest_level(g), closest_level(b));
+    let cube = (LEVELS[cube_r], LEVELS[cube_g], LEVELS[cube_b]);
+    // Greys from 8 to 238 in steps of 10
+    let average = (r as u32 + g as u32 + b as u32) / 3;
+    let grey_index = (average.saturating_sub(3) / 10).min(23) as u8;
+    let grey = 8 + grey_index * 10;
+
+    if distance((grey, grey, grey)) < distance(cube) {
+        232 + grey_index
+    } else {
+        16 + 36 * cube_r as u8 + 6 * cube_g as u8 + cube_b as u8
+    }
 }
@@ -0,0 +1,14 @@
+use renderers::input::{Input, KeyboardCommand};
+
+// No key is ever pressed
+pub struct HeadlessInput;
+
+impl Input for HeadlessInput {
+    fn initialize(&mut self) {}
+
+    fn set_waiting_key(&mut self) {}
+
+    fn get_keyboard_state(&mut self) -> KeyboardCommand {
+        KeyboardCommand::KeypadState(0x0)
+    }
+}
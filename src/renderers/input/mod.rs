@@ -2,6 +2,12 @@ pub enum KeyboardCommand {
     KeypadState(u16),
     SingleKey(u8),
     Quit,
+    SaveState,
+    LoadState,
+    NextSlot,
+    Rewind,
+    Debug,
+    Mute,
 }
 
 pub trait Input {
@@ -10,8 +16,13 @@ pub trait Input {
     fn set_waiting_key(&mut self);
 
     fn get_keyboard_state(&mut self) -> KeyboardCommand;
+
+    // Forgets the keys pressed so far, e.g. the ones typed in the debugger prompt
+    fn reset(&mut self) {}
 }
 
+pub mod headless_input;
+
 pub mod sdl_input;
 
 pub mod termion_input;
@@ -8,6 +8,8 @@ use sdl2::Sdl;
 pub struct SdlInput {
     waiting_key: bool,
     keyboard_state: u32,
+    // Backspace is held down
+    rewinding: bool,
     event_pump: EventPump,
 }
 
@@ -16,15 +18,55 @@ impl SdlInput {
         SdlInput {
             waiting_key: false,
             keyboard_state: 0x0,
+            rewinding: false,
             event_pump: sdl.event_pump().unwrap(),
         }
     }
 
     fn check_pressed_keys(&mut self) -> KeyboardCommand {
         let events: Vec<Event> = self.event_pump.poll_iter().collect();
+        let mut hotkey = None;
 
         for event in events {
             match event {
+                // Save state hotkeys: F5 saves to the current slot, F7 loads from it and F6
+                // selects the next slot. Auto repeated presses are ignored
+                Event::KeyDown {
+                    keycode: Some(Keycode::F5),
+                    repeat: false,
+                    ..
+                } => hotkey = Some(KeyboardCommand::SaveState),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F6),
+                    repeat: false,
+                    ..
+                } => hotkey = Some(KeyboardCommand::NextSlot),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F7),
+                    repeat: false,
+                    ..
+                } => hotkey = Some(KeyboardCommand::LoadState),
+                // F8 opens the debugger prompt
+                Event::KeyDown {
+                    keycode: Some(Keycode::F8),
+                    repeat: false,
+                    ..
+                } => hotkey = Some(KeyboardCommand::Debug),
+                // M mutes and unmutes the sound
+                Event::KeyDown {
+                    keycode: Some(Keycode::M),
+                    repeat: false,
+                    ..
+                } => hotkey = Some(KeyboardCommand::Mute),
+                // Rewinds for as long as Backspace is held down
+                Event::KeyDown {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } => self.rewinding = true,
+                Event::KeyUp {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } => self.rewinding = false,
                 Event::KeyDown { keycode, .. } => match keycode {
                     Some(Keycode::Num1) => {
                         self.keyboard_state |= 0x1;
@@ -132,7 +174,11 @@ impl SdlInput {
             }
         }
 
-        KeyboardCommand::KeypadState(self.keyboard_state as u16)
+        match hotkey {
+            Some(command) => command,
+            None if self.rewinding => KeyboardCommand::Rewind,
+            None => KeyboardCommand::KeypadState(self.keyboard_state as u16),
+        }
     }
 
     fn get_single_key(&mut self) -> KeyboardCommand {
@@ -140,8 +186,24 @@ impl SdlInput {
         let mut result = KeyboardCommand::KeypadState(0x0);
 
         for event in events {
-            if let Event::KeyDown { keycode, .. } = event {
-                match keycode {
+            match event {
+                Event::KeyDown {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } => self.rewinding = true,
+                Event::KeyUp {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } => self.rewinding = false,
+                Event::KeyDown {
+                    keycode, repeat, ..
+                } => match keycode {
+                    // The key is still awaited after handling a save state hotkey
+                    Some(Keycode::F5) if !repeat => return KeyboardCommand::SaveState,
+                    Some(Keycode::F6) if !repeat => return KeyboardCommand::NextSlot,
+                    Some(Keycode::F7) if !repeat => return KeyboardCommand::LoadState,
+                    Some(Keycode::F8) if !repeat => return KeyboardCommand::Debug,
+                    Some(Keycode::M) if !repeat => return KeyboardCommand::Mute,
                     Some(Keycode::Num1) => {
                         result = KeyboardCommand::SingleKey(1);
                     }
@@ -194,7 +256,15 @@ impl SdlInput {
                         result = KeyboardCommand::Quit;
                     }
                     _ => {}
-                }
+                },
+                _ => {}
+            }
+        }
+
+        // The key is still awaited while rewinding
+        if self.rewinding {
+            if let KeyboardCommand::KeypadState(_) = result {
+                return KeyboardCommand::Rewind;
             }
         }
 
@@ -5,6 +5,21 @@ use renderers::input::{Input as KeyInput, KeyboardCommand};
 use std::sync::{Arc, Mutex};
 use std::thread;
 
+// Save state hotkeys (F5 save, F6 next slot, F7 load) are latched above the keypad and quit bits
+// until get_keyboard_state reports them
+const SAVE_STATE_KEY: u32 = 0x20000;
+const NEXT_SLOT_KEY: u32 = 0x40000;
+const LOAD_STATE_KEY: u32 = 0x80000;
+
+// F8, opens the debugger prompt
+const DEBUG_KEY: u32 = 0x200000;
+
+// M, mutes and unmutes the sound
+const MUTE_KEY: u32 = 0x400000;
+
+// Backspace, set for as long as it is held down
+const REWIND_KEY: u32 = 0x100000;
+
 fn set_keyboard_state(input: Input, keyboard_state: u32) -> u32 {
     let mut kb_state = keyboard_state;
     match input.event_type() {
@@ -58,6 +73,24 @@ fn set_keyboard_state(input: Input, keyboard_state: u32) -> u32 {
             Keys::KEY_O => {
                 kb_state |= 0x10000;
             }
+            Keys::KEY_F5 => {
+                kb_state |= SAVE_STATE_KEY;
+            }
+            Keys::KEY_F6 => {
+                kb_state |= NEXT_SLOT_KEY;
+            }
+            Keys::KEY_F7 => {
+                kb_state |= LOAD_STATE_KEY;
+            }
+            Keys::KEY_F8 => {
+                kb_state |= DEBUG_KEY;
+            }
+            Keys::KEY_M => {
+                kb_state |= MUTE_KEY;
+            }
+            Keys::KEY_BACKSPACE => {
+                kb_state |= REWIND_KEY;
+            }
             _ => {}
         },
         EventType::Release => match input.get_key() {
@@ -107,6 +140,9 @@ fn set_keyboard_state(input: Input, keyboard_state: u32) -> u32 {
             Keys::KEY_C => {
                 kb_state &= !0x8000;
             }
+            Keys::KEY_BACKSPACE => {
+                kb_state &= !REWIND_KEY;
+            }
             _ => {}
         },
         _ => {}
@@ -117,7 +153,7 @@ fn set_keyboard_state(input: Input, keyboard_state: u32) -> u32 {
 
 fn check_pressed_keys(keyboard_state: Arc<Mutex<u32>>) {
     let device_path: String = get_input_devices()
-        .get(0)
+        .first()
         .expect("There was an error initializing the keyboard.")
         .to_string();
     let mut input_stream = InputReader::new(device_path);
@@ -134,7 +170,7 @@ fn check_pressed_keys(keyboard_state: Arc<Mutex<u32>>) {
 
 fn wait_for_key() -> KeyboardCommand {
     let device_path: String = get_input_devices()
-        .get(0)
+        .first()
         .expect("There was an error initializing the keyboard.")
         .to_string();
     let mut input_stream = InputReader::new(device_path);
@@ -195,6 +231,24 @@ fn wait_for_key() -> KeyboardCommand {
                     Keys::KEY_O => {
                         return KeyboardCommand::Quit;
                     }
+                    Keys::KEY_F5 => {
+                        return KeyboardCommand::SaveState;
+                    }
+                    Keys::KEY_F6 => {
+                        return KeyboardCommand::NextSlot;
+                    }
+                    Keys::KEY_F7 => {
+                        return KeyboardCommand::LoadState;
+                    }
+                    Keys::KEY_F8 => {
+                        return KeyboardCommand::Debug;
+                    }
+                    Keys::KEY_M => {
+                        return KeyboardCommand::Mute;
+                    }
+                    Keys::KEY_BACKSPACE => {
+                        return KeyboardCommand::Rewind;
+                    }
                     _ => return KeyboardCommand::KeypadState(0x0),
                 },
                 _ => return KeyboardCommand::KeypadState(0x0),
@@ -218,7 +272,7 @@ impl TermionInput {
 
     fn wait_for_key(&mut self) -> KeyboardCommand {
         let wait = thread::spawn(wait_for_key);
-        return wait.join().unwrap();
+        wait.join().unwrap()
     }
 }
 
@@ -232,11 +286,31 @@ impl KeyInput for TermionInput {
         self.waiting_key = true;
     }
 
+    fn reset(&mut self) {
+        *self.keyboard_state.lock().unwrap() = 0x0;
+    }
+
     fn get_keyboard_state(&mut self) -> KeyboardCommand {
         if self.waiting_key {
             self.wait_for_key()
         } else {
-            let keyboard_state = self.keyboard_state.lock().unwrap();
+            let mut keyboard_state = self.keyboard_state.lock().unwrap();
+
+            for (key, command) in [
+                (SAVE_STATE_KEY, KeyboardCommand::SaveState),
+                (NEXT_SLOT_KEY, KeyboardCommand::NextSlot),
+                (LOAD_STATE_KEY, KeyboardCommand::LoadState),
+                (DEBUG_KEY, KeyboardCommand::Debug),
+                (MUTE_KEY, KeyboardCommand::Mute),
+            ] {
+                if *keyboard_state & key != 0 {
+                    *keyboard_state &= !key;
+                    return command;
+                }
+            }
+            if *keyboard_state & REWIND_KEY != 0 {
+                return KeyboardCommand::Rewind;
+            }
 
             #[allow(clippy::comparison_chain)]
             if *keyboard_state < 0x10000 {
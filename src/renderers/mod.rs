@@ -1,10 +1,19 @@
+pub mod audio;
 pub mod graphics;
 pub mod input;
 
+use renderers::audio::bell_audio::BellAudio;
+use renderers::audio::null_audio::NullAudio;
+use renderers::audio::sdl_audio::SdlAudio;
+use renderers::audio::Audio;
+
+use renderers::graphics::headless_graphics::HeadlessGraphics;
+use renderers::graphics::palette::Palette;
 use renderers::graphics::sdl_graphics::SdlGraphics;
 use renderers::graphics::termion_graphics::TermionGraphics;
 use renderers::graphics::Graphics;
 
+use renderers::input::headless_input::HeadlessInput;
 use renderers::input::sdl_input::SdlInput;
 use renderers::input::termion_input::TermionInput;
 use renderers::input::Input;
@@ -12,19 +21,34 @@ use renderers::input::Input;
 pub struct Renderer {
     pub graphics: Box<dyn Graphics>,
     pub input: Box<dyn Input>,
+    pub audio: Box<dyn Audio>,
+    // Whether the game runs at 60 frames per second, otherwise as fast as possible
+    pub realtime: bool,
 }
 
-pub fn get_renders(renderer: String) -> Renderer {
+pub fn get_renders(renderer: String, palette: Palette) -> Renderer {
     if renderer == "terminal" {
         return Renderer {
-            graphics: Box::new(TermionGraphics::new()),
+            graphics: Box::new(TermionGraphics::new(palette)),
             input: Box::new(TermionInput::new()),
+            audio: Box::new(BellAudio::new()),
+            realtime: true,
+        };
+    }
+    if renderer == "headless" {
+        return Renderer {
+            graphics: Box::new(HeadlessGraphics),
+            input: Box::new(HeadlessInput),
+            audio: Box::new(NullAudio::new()),
+            realtime: false,
         };
     }
     let sdl = sdl2::init().unwrap();
 
     Renderer {
-        graphics: Box::new(SdlGraphics::new(&sdl)),
+        graphics: Box::new(SdlGraphics::new(&sdl, palette)),
         input: Box::new(SdlInput::new(&sdl)),
+        audio: Box::new(SdlAudio::new(&sdl)),
+        realtime: true,
     }
 }
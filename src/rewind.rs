@@ -0,0 +1,214 @@
+use std::collections::VecDeque;
+
+/// Bounded history of save states (see `Machine::save_state`) to step backwards through.
+///
+/// Only the most recent state is kept whole. Every older one is stored as the difference with the
+/// state that followed it: the two states XORed together and run length encoded. Consecutive
+/// frames differ in a few bytes, so a delta is usually a handful of bytes long instead of the
+/// size of the whole memory.
+pub struct RewindBuffer {
+    capacity: usize,
+    current: Option<Vec<u8>>,
+    deltas: VecDeque<Vec<u8>>,
+}
+
+impl RewindBuffer {
+    /// Creates a buffer keeping at most `capacity` states. The oldest ones are dropped first.
+    pub fn new(capacity: usize) -> RewindBuffer {
+        RewindBuffer {
+            capacity,
+            current: None,
+            deltas: VecDeque::new(),
+        }
+    }
+
+    /// Records `state` as the most recent one.
+    pub fn push(&mut self, state: Vec<u8>) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if let Some(current) = self.current.take() {
+            if current.len() == state.len() {
+                self.deltas.push_back(encode_delta(&current, &state));
+                if self.deltas.len() >= self.capacity {
+                    self.deltas.pop_front();
+                }
+            } else {
+                // States of another machine can not be diffed against the history
+                self.deltas.clear();
+            }
+        }
+        self.current = Some(state);
+    }
+
+    /// Removes and returns the most recent state.
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let state = self.current.take()?;
+        if let Some(delta) = self.deltas.pop_back() {
+            let mut previous = state.clone();
+            apply_delta(&delta, &mut previous);
+            self.current = Some(previous);
+        }
+        Some(state)
+    }
+
+    /// Number of states stored.
+    pub fn len(&self) -> usize {
+        self.deltas.len() + self.current.is_some() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.current.is_none()
+    }
+
+    pub fn clear(&mut self) {
+        self.current = None;
+        self.deltas.clear();
+    }
+
+    /// Bytes used by the stored states.
+    pub fn size(&self) -> usize {
+        self.current.as_ref().map_or(0, Vec::len) + self.deltas.iter().map(Vec::len).sum::<usize>()
+    }
+}
+
+// The delta is a sequence of runs: the number of unchanged bytes and the number of changed bytes
+// (both LEB128 encoded) followed by the changed bytes XORed with their new value
+fn encode_delta(old: &[u8], new: &[u8]) -> Vec<u8> {
+    let mut delta = Vec::new();
+    let mut position = 0;
+
+    while position < old.len() {
+        let unchanged = old[position..]
+            .iter()
+            .zip(&new[position..])
+            .take_while(|&(a, b)| a == b)
+            .count();
+        position += unchanged;
+        if position == old.len() {
+            break;
+        }
+
+        let changed = old[position..]
+            .iter()
+            .zip(&new[position..])
+            .take_while(|&(a, b)| a != b)
+            .count();
+        put_varint(&mut delta, unchanged);
+        put_varint(&mut delta, changed);
+        for offset in position..(position + changed) {
+            delta.push(old[offset] ^ new[offset]);
+        }
+        position += changed;
+    }
+
+    delta
+}
+
+// XORs the runs of a delta into state, turning either state it was computed from into the other
+fn apply_delta(delta: &[u8], state: &mut [u8]) {
+    let mut input = 0;
+    let mut position = 0;
+
+    while input < delta.len() {
+        position += get_varint(delta, &mut input);
+        let changed = get_varint(delta, &mut input);
+        for byte in &delta[input..(input + changed)] {
+            state[position] ^= byte;
+            position += 1;
+        }
+        input += changed;
+    }
+}
+
+fn put_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push((value & 0x7F) as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn get_varint(data: &[u8], position: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[*position];
+        *position += 1;
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(old: &[u8], new: &[u8]) -> Vec<u8> {
+        let delta = encode_delta(old, new);
+        let mut restored = new.to_vec();
+        apply_delta(&delta, &mut restored);
+        assert_eq!(restored, old);
+        apply_delta(&delta, &mut restored);
+        assert_eq!(restored, new);
+        delta
+    }
+
+    #[test]
+    fn identical_states_have_an_empty_delta() {
+        let state = vec![0x5A; 4096];
+        assert!(round_trip(&state, &state).is_empty());
+    }
+
+    #[test]
+    fn fully_different_states_are_one_run() {
+        let old = vec![0x00; 300];
+        let new = vec![0xFF; 300];
+        let delta = round_trip(&old, &new);
+        // No unchanged bytes, 300 changed bytes in two varint bytes, then the bytes
+        assert_eq!(delta[..3], [0x00, 0xAC, 0x02]);
+        assert_eq!(delta.len(), 3 + 300);
+    }
+
+    #[test]
+    fn runs_longer_than_one_varint_byte() {
+        let old = vec![0x00; 70000];
+        let mut new = old.clone();
+        new[200..400].iter_mut().for_each(|byte| *byte = 0x01);
+        new[69999] = 0x02;
+        let delta = round_trip(&old, &new);
+        // 200 unchanged, 200 changed, then 69599 unchanged (three varint bytes) and 1 changed
+        assert_eq!(delta[..4], [0xC8, 0x01, 0xC8, 0x01]);
+        assert_eq!(delta[204..], [0xDF, 0x9F, 0x04, 0x01, 0x02]);
+    }
+
+    #[test]
+    fn pop_returns_the_states_from_the_most_recent() {
+        let mut buffer = RewindBuffer::new(10);
+        for frame in 0..5u8 {
+            buffer.push(vec![frame, 0x00, frame * 2, 0xFF]);
+        }
+        for frame in (0..5u8).rev() {
+            assert_eq!(buffer.pop(), Some(vec![frame, 0x00, frame * 2, 0xFF]));
+        }
+        assert_eq!(buffer.pop(), None);
+    }
+
+    #[test]
+    fn push_drops_the_oldest_state_when_full() {
+        let mut buffer = RewindBuffer::new(3);
+        for frame in 0..5u8 {
+            buffer.push(vec![frame; 8]);
+        }
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(buffer.pop(), Some(vec![4; 8]));
+        assert_eq!(buffer.pop(), Some(vec![3; 8]));
+        assert_eq!(buffer.pop(), Some(vec![2; 8]));
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.pop(), None);
+    }
+}
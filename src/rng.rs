@@ -0,0 +1,177 @@
+use rand::random;
+
+/// Source of the random numbers returned by `Cxkk`.
+pub trait RandomSource {
+    /// Returns the next random byte. `memory` is the machine main memory, for generators that
+    /// derive their numbers from it like the original interpreter did.
+    fn next_byte(&mut self, memory: &[u8]) -> u8;
+
+    /// Internal state of the generator, stored in save states. Generators without a reproducible
+    /// state return 0.
+    fn state(&self) -> u64 {
+        0
+    }
+
+    /// Restores a state returned by `state`.
+    fn set_state(&mut self, _state: u64) {}
+}
+
+/// Non reproducible numbers from the operating system seeded generator. This is the default.
+pub struct ThreadRandom;
+
+impl RandomSource for ThreadRandom {
+    fn next_byte(&mut self, _memory: &[u8]) -> u8 {
+        random::<u8>()
+    }
+}
+
+/// Deterministic generator (SplitMix64): the same seed always produces the same sequence.
+pub struct SeededRandom {
+    state: u64,
+}
+
+impl SeededRandom {
+    pub fn new(seed: u64) -> SeededRandom {
+        SeededRandom { state: seed }
+    }
+}
+
+impl RandomSource for SeededRandom {
+    fn next_byte(&mut self, _memory: &[u8]) -> u8 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        (z >> 56) as u8
+    }
+
+    fn state(&self) -> u64 {
+        self.state
+    }
+
+    fn set_state(&mut self, state: u64) {
+        self.state = state;
+    }
+}
+
+/// The RND routine of the COSMAC VIP interpreter.
+///
+/// The VIP kept a 16 bit seed in register R9. On every `Cxkk` the seed is incremented, the byte
+/// at the address given by its low byte is read from the first memory page and added to its high
+/// byte, and the sum becomes both the new high byte and the random number.
+///
+/// On the VIP the first page held the interpreter itself. Here it holds the fonts unless an image
+/// of the interpreter page is provided with `with_interpreter_page`, so the sequence follows the
+/// VIP algorithm but only matches real hardware when given that image.
+pub struct CosmacVipRandom {
+    seed: u16,
+    interpreter_page: Option<[u8; 256]>,
+}
+
+impl CosmacVipRandom {
+    pub fn new(seed: u16) -> CosmacVipRandom {
+        CosmacVipRandom {
+            seed,
+            interpreter_page: None,
+        }
+    }
+
+    /// Reads the bytes from `page` instead of the first page of the emulated memory.
+    pub fn with_interpreter_page(seed: u16, page: [u8; 256]) -> CosmacVipRandom {
+        CosmacVipRandom {
+            seed,
+            interpreter_page: Some(page),
+        }
+    }
+}
+
+impl RandomSource for CosmacVipRandom {
+    fn next_byte(&mut self, memory: &[u8]) -> u8 {
+        self.seed = self.seed.wrapping_add(1);
+        let low = (self.seed & 0xFF) as u8;
+        let high = (self.seed >> 8) as u8;
+
+        let byte = match self.interpreter_page {
+            Some(ref page) => page[low as usize],
+            None => memory[low as usize],
+        };
+        let number = byte.wrapping_add(high);
+
+        self.seed = (number as u16) << 8 | low as u16;
+        number
+    }
+
+    fn state(&self) -> u64 {
+        self.seed as u64
+    }
+
+    fn set_state(&mut self, state: u64) {
+        self.seed = state as u16;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8::DEFAULT_LOAD_ADDRESS;
+    use machine::Machine;
+    use platform::Platform;
+
+    // V0 = random byte, 16 times
+    fn cxkk_sequence(rng: Box<dyn RandomSource>) -> Vec<u8> {
+        let rom = [0xC0, 0xFF, 0x12, 0x00];
+        let mut machine = Machine::from_bytes(&rom, Platform::Chip8, DEFAULT_LOAD_ADDRESS).unwrap();
+        machine.set_rng(rng);
+        (0..16)
+            .map(|_| {
+                machine.step().unwrap();
+                machine.step().unwrap();
+                machine.registers()[0]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn machines_seeded_the_same_generate_the_same_numbers() {
+        let first = cxkk_sequence(Box::new(SeededRandom::new(1234)));
+        assert_eq!(first, cxkk_sequence(Box::new(SeededRandom::new(1234))));
+        assert_ne!(first, cxkk_sequence(Box::new(SeededRandom::new(1235))));
+    }
+
+    #[test]
+    fn seeded_state_restores_the_sequence() {
+        let mut rng = SeededRandom::new(99);
+        rng.next_byte(&[]);
+        let state = rng.state();
+        let expected: Vec<u8> = (0..8).map(|_| rng.next_byte(&[])).collect();
+
+        let mut restored = SeededRandom::new(0);
+        restored.set_state(state);
+        let numbers: Vec<u8> = (0..8).map(|_| restored.next_byte(&[])).collect();
+        assert_eq!(numbers, expected);
+    }
+
+    #[test]
+    fn cosmac_vip_adds_the_page_byte_to_the_high_byte_of_the_seed() {
+        let mut page = [0x0; 256];
+        for (index, byte) in page.iter_mut().enumerate() {
+            *byte = index as u8;
+        }
+        let mut rng = CosmacVipRandom::with_interpreter_page(0x0000, page);
+        let numbers: Vec<u8> = (0..4).map(|_| rng.next_byte(&[])).collect();
+        assert_eq!(numbers, [1, 3, 6, 10]);
+        assert_eq!(rng.state(), 0x0A04);
+    }
+
+    #[test]
+    fn cosmac_vip_reads_the_first_memory_page_without_an_interpreter_page() {
+        let mut memory = [0x0; 0x1000];
+        memory[0x00] = 0x40;
+        memory[0x01] = 0x10;
+        // The increment carries into the high byte: 0x1300 reads memory[0x00]
+        let mut rng = CosmacVipRandom::new(0x12FF);
+        assert_eq!(rng.next_byte(&memory), 0x53);
+        assert_eq!(rng.next_byte(&memory), 0x63);
+    }
+}
@@ -0,0 +1,362 @@
+use audio::PATTERN_SIZE;
+use chip8::State;
+use platform::Platform;
+use screen::{PLANES, SCREEN_HEIGHT};
+use std::error::Error;
+use std::fmt;
+
+// Identifies save state files
+const MAGIC: &[u8; 4] = b"C8ST";
+
+/// Version of the save state format written by `save`. Bumped whenever the layout changes.
+pub const VERSION: u8 = 1;
+
+/// Reasons a save state can not be restored.
+#[derive(Debug, PartialEq, Eq)]
+pub enum SaveStateError {
+    /// The data is not a save state.
+    NotASaveState,
+
+    /// The save state was written by an incompatible version of the emulator.
+    UnsupportedVersion(u8),
+
+    /// The save state is truncated or contains invalid values.
+    Corrupted,
+
+    /// The save state was taken while running a different ROM.
+    RomMismatch,
+
+    /// The save state was taken on a different platform.
+    PlatformMismatch { expected: Platform, found: Platform },
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SaveStateError::NotASaveState => write!(f, "not a save state"),
+            SaveStateError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported save state version {} (expected {})",
+                version, VERSION
+            ),
+            SaveStateError::Corrupted => write!(f, "corrupted save state"),
+            SaveStateError::RomMismatch => {
+                write!(f, "the save state belongs to a different ROM")
+            }
+            SaveStateError::PlatformMismatch { expected, found } => write!(
+                f,
+                "the save state was taken on {}, the machine is running {}",
+                found.name(),
+                expected.name()
+            ),
+        }
+    }
+}
+
+impl Error for SaveStateError {}
+
+/// Serializes everything that changes while a program runs. The configuration (quirks, timing
+/// mode and instructions per frame) is not part of the state.
+///
+/// The header (magic bytes, version, ROM hash, platform and load address) is followed by the
+/// machine state. Numbers are little endian.
+pub(crate) fn save(state: &State) -> Vec<u8> {
+    let mut out = Vec::with_capacity(state.memory.len() + 0x1000);
+
+    out.extend_from_slice(MAGIC);
+    out.push(VERSION);
+    put_u64(&mut out, state.rom_hash);
+    out.push(platform_code(state.platform));
+    put_u16(&mut out, state.load_address);
+
+    put_u32(&mut out, state.memory.len() as u32);
+    out.extend_from_slice(&state.memory);
+    out.extend_from_slice(&state.registers);
+    for address in state.stack.iter() {
+        put_u16(&mut out, *address);
+    }
+    put_u16(&mut out, state.index);
+    put_u16(&mut out, state.pc);
+    out.push(state.sp as u8);
+
+    out.push(state.screen.hires as u8);
+    out.push(state.screen.selected_planes);
+    for rows in state.screen.planes.iter() {
+        for row in rows.iter() {
+            out.extend_from_slice(&row.to_le_bytes());
+        }
+    }
+
+    put_u16(&mut out, state.keypad);
+    out.push(state.register_pressed_key);
+    out.push(state.waiting_key as u8);
+    out.push(state.delay_timer);
+    out.push(state.sound_timer);
+    out.extend_from_slice(&state.audio_pattern);
+    out.push(state.pitch);
+    out.extend_from_slice(&state.rpl_flags);
+    put_u64(&mut out, state.cycles);
+    put_u64(&mut out, state.rng.state());
+
+    out
+}
+
+/// Restores a state produced by `save`. The state is left untouched if `data` is rejected.
+pub(crate) fn load(state: &mut State, data: &[u8]) -> Result<(), SaveStateError> {
+    let mut reader = Reader { data, position: 0 };
+
+    if reader.bytes(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+        return Err(SaveStateError::NotASaveState);
+    }
+    let version = reader.u8()?;
+    if version != VERSION {
+        return Err(SaveStateError::UnsupportedVersion(version));
+    }
+    if reader.u64()? != state.rom_hash {
+        return Err(SaveStateError::RomMismatch);
+    }
+    let platform = platform_from_code(reader.u8()?)?;
+    if platform != state.platform {
+        return Err(SaveStateError::PlatformMismatch {
+            expected: state.platform,
+            found: platform,
+        });
+    }
+    if reader.u16()? != state.load_address {
+        return Err(SaveStateError::RomMismatch);
+    }
+
+    let memory_size = reader.u32()? as usize;
+    if memory_size != state.memory.len() {
+        return Err(SaveStateError::Corrupted);
+    }
+    let memory = reader.bytes(memory_size)?;
+
+    let mut registers = [0x0; 16];
+    registers.copy_from_slice(reader.bytes(16)?);
+    let mut stack = [0x0; 16];
+    for address in stack.iter_mut() {
+        *address = reader.u16()?;
+    }
+    let index = reader.u16()?;
+    let pc = reader.u16()?;
+    let sp = reader.u8()? as usize;
+    if sp > stack.len() {
+        return Err(SaveStateError::Corrupted);
+    }
+
+    let hires = reader.bool()?;
+    let selected_planes = reader.u8()?;
+    if selected_planes > 0x3 {
+        return Err(SaveStateError::Corrupted);
+    }
+    let mut planes = [[0x0; SCREEN_HEIGHT]; PLANES];
+    for rows in planes.iter_mut() {
+        for row in rows.iter_mut() {
+            let mut bytes = [0x0; 16];
+            bytes.copy_from_slice(reader.bytes(16)?);
+            *row = u128::from_le_bytes(bytes);
+        }
+    }
+
+    let keypad = reader.u16()?;
+    let register_pressed_key = reader.u8()?;
+    if register_pressed_key > 0xF {
+        return Err(SaveStateError::Corrupted);
+    }
+    let waiting_key = reader.bool()?;
+    let delay_timer = reader.u8()?;
+    let sound_timer = reader.u8()?;
+    let mut audio_pattern = [0x0; PATTERN_SIZE];
+    audio_pattern.copy_from_slice(reader.bytes(PATTERN_SIZE)?);
+    let pitch = reader.u8()?;
+    let mut rpl_flags = [0x0; 16];
+    rpl_flags.copy_from_slice(reader.bytes(16)?);
+    let cycles = reader.u64()?;
+    let rng_state = reader.u64()?;
+
+    if reader.position != data.len() {
+        return Err(SaveStateError::Corrupted);
+    }
+
+    // Everything was read, the state can be replaced
+    state.memory.copy_from_slice(memory);
+    state.registers = registers;
+    state.stack = stack;
+    state.index = index;
+    state.pc = pc;
+    state.instruction_pc = pc;
+    state.sp = sp;
+    state.screen.hires = hires;
+    state.screen.selected_planes = selected_planes;
+    state.screen.planes = planes;
+    state.keypad = keypad;
+    state.register_pressed_key = register_pressed_key;
+    state.waiting_key = waiting_key;
+    state.delay_timer = delay_timer;
+    state.sound_timer = sound_timer;
+    state.audio_pattern = audio_pattern;
+    state.pitch = pitch;
+    state.rpl_flags = rpl_flags;
+    state.cycles = cycles;
+    state.rng.set_state(rng_state);
+
+    Ok(())
+}
+
+fn platform_code(platform: Platform) -> u8 {
+    match platform {
+        Platform::Chip8 => 0,
+        Platform::SuperChip => 1,
+        Platform::XoChip => 2,
+    }
+}
+
+fn platform_from_code(code: u8) -> Result<Platform, SaveStateError> {
+    match code {
+        0 => Ok(Platform::Chip8),
+        1 => Ok(Platform::SuperChip),
+        2 => Ok(Platform::XoChip),
+        _ => Err(SaveStateError::Corrupted),
+    }
+}
+
+fn put_u16(out: &mut Vec<u8>, value: u16) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u64(out: &mut Vec<u8>, value: u64) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+// Reads the fields back in order, failing when the data runs out
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], SaveStateError> {
+        let end = self.position + count;
+        if end > self.data.len() {
+            return Err(SaveStateError::Corrupted);
+        }
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, SaveStateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn bool(&mut self) -> Result<bool, SaveStateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(SaveStateError::Corrupted),
+        }
+    }
+
+    fn u16(&mut self) -> Result<u16, SaveStateError> {
+        let mut bytes = [0x0; 2];
+        bytes.copy_from_slice(self.bytes(2)?);
+        Ok(u16::from_le_bytes(bytes))
+    }
+
+    fn u32(&mut self) -> Result<u32, SaveStateError> {
+        let mut bytes = [0x0; 4];
+        bytes.copy_from_slice(self.bytes(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn u64(&mut self) -> Result<u64, SaveStateError> {
+        let mut bytes = [0x0; 8];
+        bytes.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8::DEFAULT_LOAD_ADDRESS;
+    use machine::Machine;
+
+    // Draws the 0 of the font at the top left corner and loops
+    const ROM: [u8; 8] = [0x60, 0x00, 0xF0, 0x29, 0xD0, 0x05, 0x12, 0x06];
+
+    fn machine(rom: &[u8], platform: Platform) -> Machine {
+        Machine::from_bytes(rom, platform, DEFAULT_LOAD_ADDRESS).unwrap()
+    }
+
+    #[test]
+    fn load_restores_a_saved_state() {
+        let mut saved = machine(&ROM, Platform::Chip8);
+        for _ in 0..3 {
+            saved.step().unwrap();
+        }
+        saved.set_register(0xA, 0x42);
+        saved.memory_mut()[0x300] = 0x99;
+        saved.set_delay_timer(12);
+        saved.set_sound_timer(34);
+        let data = saved.save_state();
+
+        let mut loaded = machine(&ROM, Platform::Chip8);
+        loaded.load_state(&data).unwrap();
+        assert_eq!(loaded.registers(), saved.registers());
+        assert_eq!(loaded.memory(), saved.memory());
+        assert_eq!(loaded.screen().planes, saved.screen().planes);
+        assert!(loaded.screen().pixel(0, 0));
+        assert_eq!(loaded.index(), saved.index());
+        assert_eq!(loaded.pc(), saved.pc());
+        assert_eq!(loaded.delay_timer(), 12);
+        assert_eq!(loaded.sound_timer(), 34);
+        assert_eq!(loaded.save_state(), data);
+    }
+
+    #[test]
+    fn load_rejects_a_bad_magic_or_version() {
+        let mut machine = machine(&ROM, Platform::Chip8);
+        let mut data = machine.save_state();
+
+        data[MAGIC.len()] = VERSION + 1;
+        assert_eq!(
+            machine.load_state(&data),
+            Err(SaveStateError::UnsupportedVersion(VERSION + 1))
+        );
+        data[0] = b'X';
+        assert_eq!(
+            machine.load_state(&data),
+            Err(SaveStateError::NotASaveState)
+        );
+        assert_eq!(
+            machine.load_state(&data[..2]),
+            Err(SaveStateError::NotASaveState)
+        );
+    }
+
+    #[test]
+    fn load_rejects_a_state_of_another_rom_or_platform() {
+        let data = machine(&ROM, Platform::Chip8).save_state();
+
+        let mut other_rom = machine(&[0x12, 0x00], Platform::Chip8);
+        assert_eq!(
+            other_rom.load_state(&data),
+            Err(SaveStateError::RomMismatch)
+        );
+
+        let mut other_platform = machine(&ROM, Platform::SuperChip);
+        assert_eq!(
+            other_platform.load_state(&data),
+            Err(SaveStateError::PlatformMismatch {
+                expected: Platform::SuperChip,
+                found: Platform::Chip8,
+            })
+        );
+    }
+}
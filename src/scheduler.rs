@@ -0,0 +1,67 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Frames per second of the timers and the display.
+pub const FRAME_RATE: u32 = 60;
+
+// When the scheduler falls further behind than this many frames (e.g. the process was suspended)
+// it starts counting again from the current time instead of running the missed frames.
+const MAX_FRAMES_BEHIND: u32 = 5;
+
+/// Paces the main loop at `FRAME_RATE` frames per second.
+///
+/// Every deadline is exactly one frame after the previous one, not after the moment the previous
+/// wait returned, so sleeping too long in one frame is compensated by the next ones and the frame
+/// rate does not drift over time.
+pub struct FrameScheduler {
+    frame_duration: Duration,
+    next_frame: Instant,
+}
+
+impl FrameScheduler {
+    pub fn new() -> FrameScheduler {
+        FrameScheduler {
+            frame_duration: Duration::from_secs(1) / FRAME_RATE,
+            next_frame: Instant::now(),
+        }
+    }
+
+    /// Sleeps until the next frame is due.
+    pub fn wait_next_frame(&mut self) {
+        let now = Instant::now();
+        if self.next_frame > now {
+            thread::sleep(self.next_frame - now);
+        } else if now - self.next_frame > self.frame_duration * MAX_FRAMES_BEHIND {
+            self.next_frame = now;
+        }
+
+        self.next_frame += self.frame_duration;
+    }
+}
+
+impl Default for FrameScheduler {
+    fn default() -> FrameScheduler {
+        FrameScheduler::new()
+    }
+}
+
+/// Instructions per frame that run the CPU at approximately `clock_hz` instructions per second.
+pub fn instructions_per_frame(clock_hz: u32) -> usize {
+    (clock_hz.saturating_add(FRAME_RATE / 2) / FRAME_RATE).max(1) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instructions_per_frame_rounds_and_runs_at_least_one() {
+        assert_eq!(instructions_per_frame(0), 1);
+        assert_eq!(instructions_per_frame(500), 8);
+        assert_eq!(instructions_per_frame(540), 9);
+        assert_eq!(
+            instructions_per_frame(u32::MAX),
+            (u32::MAX / FRAME_RATE) as usize
+        );
+    }
+}
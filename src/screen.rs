@@ -0,0 +1,198 @@
+// Largest (SUPER-CHIP high resolution) display size
+pub const SCREEN_WIDTH: usize = 128;
+pub const SCREEN_HEIGHT: usize = 64;
+
+// Original CHIP-8 display size, used in low resolution mode
+pub const LORES_WIDTH: usize = 64;
+pub const LORES_HEIGHT: usize = 32;
+
+// XO-CHIP bitplanes
+pub const PLANES: usize = 2;
+
+/// Framebuffer supporting the 64x32 low resolution and the 128x64 SUPER-CHIP high resolution
+/// modes, with the two XO-CHIP bitplanes.
+///
+/// Every row is stored as an `u128` whose most significant bit is the leftmost pixel. In low
+/// resolution mode only the first 32 rows and the 64 most significant bits of each row are used.
+/// Drawing, clearing and scrolling only affect the selected planes (plane 1 unless an XO-CHIP
+/// program selects otherwise).
+#[derive(Clone, Copy)]
+pub struct Screen {
+    pub(crate) hires: bool,
+    pub(crate) planes: [[u128; SCREEN_HEIGHT]; PLANES],
+    pub(crate) selected_planes: u8,
+}
+
+impl Screen {
+    pub fn new() -> Screen {
+        Screen {
+            hires: false,
+            planes: [[0x0; SCREEN_HEIGHT]; PLANES],
+            selected_planes: 0x1,
+        }
+    }
+
+    /// Width in pixels of the current mode.
+    pub fn width(&self) -> usize {
+        if self.hires {
+            SCREEN_WIDTH
+        } else {
+            LORES_WIDTH
+        }
+    }
+
+    /// Height in pixels of the current mode.
+    pub fn height(&self) -> usize {
+        if self.hires {
+            SCREEN_HEIGHT
+        } else {
+            LORES_HEIGHT
+        }
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    /// Whether the pixel at (x, y) is lit in any plane. Coordinates outside the current mode are
+    /// never lit.
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.color(x, y) != 0
+    }
+
+    /// Colour index (0 to 3) of the pixel at (x, y): bit 0 is plane 1 and bit 1 is plane 2.
+    pub fn color(&self, x: usize, y: usize) -> u8 {
+        if x >= self.width() || y >= self.height() {
+            return 0;
+        }
+
+        let mut color = 0;
+        for (plane, rows) in self.planes.iter().enumerate() {
+            color |= (((rows[y] >> (SCREEN_WIDTH - 1 - x)) & 0x1) as u8) << plane;
+        }
+        color
+    }
+
+    /// Bitmask of the planes affected by drawing, clearing and scrolling.
+    pub fn selected_planes(&self) -> u8 {
+        self.selected_planes
+    }
+
+    pub fn select_planes(&mut self, planes: u8) {
+        self.selected_planes = planes & 0x3;
+    }
+
+    /// Clears the selected planes.
+    pub fn clear(&mut self) {
+        for plane in self.selected() {
+            self.planes[plane] = [0x0; SCREEN_HEIGHT];
+        }
+    }
+
+    /// Switches between low and high resolution. Every plane is cleared.
+    pub fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.planes = [[0x0; SCREEN_HEIGHT]; PLANES];
+    }
+
+    /// XORs one sprite row of `width` bits (most significant bit first) at (x, y) into `plane`.
+    /// Pixels falling outside the screen are clipped or wrapped around. Returns true if any lit
+    /// pixel was erased.
+    pub fn draw_sprite_row(
+        &mut self,
+        plane: usize,
+        x: usize,
+        y: usize,
+        bits: u16,
+        width: usize,
+        clip: bool,
+    ) -> bool {
+        let mut collision = false;
+
+        if y >= self.height() {
+            return false;
+        }
+
+        for column in 0..width {
+            if (bits >> (width - 1 - column)) & 0x1 == 0 {
+                continue;
+            }
+
+            let mut pixel_x = x + column;
+            if pixel_x >= self.width() {
+                if clip {
+                    break;
+                }
+                pixel_x %= self.width();
+            }
+
+            let mask: u128 = 1 << (SCREEN_WIDTH - 1 - pixel_x);
+            if self.planes[plane][y] & mask != 0 {
+                collision = true;
+            }
+            self.planes[plane][y] ^= mask;
+        }
+
+        collision
+    }
+
+    /// Scrolls the selected planes down `n` pixels.
+    pub fn scroll_down(&mut self, n: usize) {
+        let height = self.height();
+        for plane in self.selected() {
+            let rows = &mut self.planes[plane];
+            for y in (0..height).rev() {
+                rows[y] = if y >= n { rows[y - n] } else { 0x0 };
+            }
+        }
+    }
+
+    /// Scrolls the selected planes up `n` pixels.
+    pub fn scroll_up(&mut self, n: usize) {
+        let height = self.height();
+        for plane in self.selected() {
+            let rows = &mut self.planes[plane];
+            for y in 0..height {
+                rows[y] = if y + n < height { rows[y + n] } else { 0x0 };
+            }
+        }
+    }
+
+    /// Scrolls the selected planes right `n` pixels.
+    pub fn scroll_right(&mut self, n: usize) {
+        let mask = self.row_mask();
+        for plane in self.selected() {
+            for row in self.planes[plane].iter_mut() {
+                *row = (*row >> n) & mask;
+            }
+        }
+    }
+
+    /// Scrolls the selected planes left `n` pixels.
+    pub fn scroll_left(&mut self, n: usize) {
+        let mask = self.row_mask();
+        for plane in self.selected() {
+            for row in self.planes[plane].iter_mut() {
+                *row = (*row << n) & mask;
+            }
+        }
+    }
+
+    /// Indexes of the selected planes.
+    pub fn selected(&self) -> Vec<usize> {
+        (0..PLANES)
+            .filter(|plane| (self.selected_planes >> plane) & 0x1 == 1)
+            .collect()
+    }
+
+    // Bits of a row that are visible in the current mode
+    fn row_mask(&self) -> u128 {
+        !0u128 << (SCREEN_WIDTH - self.width())
+    }
+}
+
+impl Default for Screen {
+    fn default() -> Screen {
+        Screen::new()
+    }
+}
@@ -0,0 +1,86 @@
+/// How much work the machine does per 60 Hz frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TimingMode {
+    /// A fixed number of instructions per frame, whatever they are. This is the default.
+    #[default]
+    FreeRunning,
+
+    /// COSMAC VIP timing: every instruction costs the machine cycles the original interpreter
+    /// needed to execute it, a frame lasts as many cycles as the VIP had between two display
+    /// interrupts, and `Dxyn` stalls until the next vertical blank.
+    CosmacVip,
+}
+
+impl TimingMode {
+    /// Names accepted by `from_name`.
+    pub const NAMES: [&'static str; 2] = ["free", "vip"];
+
+    /// Returns the timing mode with the given name (see `NAMES`).
+    pub fn from_name(name: &str) -> Option<TimingMode> {
+        match name {
+            "free" => Some(TimingMode::FreeRunning),
+            "vip" => Some(TimingMode::CosmacVip),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TimingMode::FreeRunning => "free",
+            TimingMode::CosmacVip => "vip",
+        }
+    }
+}
+
+// The VIP CDP1802 ran at 1.7609 MHz with 8 clock periods per machine cycle: 3668 machine cycles per
+// 60 Hz frame. The CDP1861 display steals one cycle per byte it fetches through DMA (8 bytes for
+// each of the 128 visible lines) and the interrupt routine runs before the display starts.
+const VIP_CYCLES_PER_FIELD: u64 = 3668;
+const VIP_DMA_CYCLES: u64 = 1024;
+const VIP_INTERRUPT_CYCLES: u64 = 30;
+
+/// Machine cycles available to the interpreter in every frame.
+pub const VIP_CYCLES_PER_FRAME: u64 = VIP_CYCLES_PER_FIELD - VIP_DMA_CYCLES - VIP_INTERRUPT_CYCLES;
+
+// Fetching and decoding an instruction
+const VIP_FETCH_CYCLES: u64 = 40;
+
+/// Approximate machine cycles the COSMAC VIP interpreter takes to fetch, decode and execute
+/// `opcode`. Instructions that the VIP does not have are charged as a simple instruction.
+pub fn vip_cycles(opcode: u16) -> u64 {
+    let x = ((opcode >> 8) & 0xF) as u64;
+    let n = (opcode & 0xF) as u64;
+
+    let execute = match opcode >> 12 {
+        0x0 => match opcode {
+            // Clears the 256 bytes of display memory
+            0x00E0 => 1536,
+            _ => 10,
+        },
+        0x1 => 12,
+        0x2 => 26,
+        0x3 | 0x4 => 10,
+        0x5 | 0x9 => 18,
+        0x6 => 6,
+        0x7 => 10,
+        0x8 => 44,
+        0xA => 12,
+        0xB => 22,
+        0xC => 36,
+        // Sprite rows are shifted into place bit by bit
+        0xD => 26 + n * 68,
+        0xE => 18,
+        0xF => match opcode & 0xFF {
+            0x07 | 0x0A | 0x15 | 0x18 => 10,
+            0x1E => 16,
+            0x29 => 20,
+            // Repeated subtraction for each decimal digit
+            0x33 => 84 + 3 * 50,
+            0x55 | 0x65 => 14 + (x + 1) * 14,
+            _ => 10,
+        },
+        _ => 10,
+    };
+
+    VIP_FETCH_CYCLES + execute
+}
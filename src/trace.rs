@@ -0,0 +1,217 @@
+use instruction::Instruction;
+use std::fmt;
+use std::io::{self, BufRead};
+
+/// Selects the instructions that are traced. Empty lists select everything.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TraceFilter {
+    /// Address ranges, both ends inclusive, the instruction must be in one of.
+    pub ranges: Vec<(u16, u16)>,
+
+    /// Opcode classes, the first nibble of the opcode (`0xD` for `Dxyn`), the instruction must
+    /// belong to one of.
+    pub classes: Vec<u8>,
+}
+
+impl TraceFilter {
+    pub fn matches(&self, pc: u16, opcode: u16) -> bool {
+        let in_range = self.ranges.is_empty()
+            || self
+                .ranges
+                .iter()
+                .any(|&(start, end)| pc >= start && pc <= end);
+        let in_class = self.classes.is_empty() || self.classes.contains(&((opcode >> 12) as u8));
+        in_range && in_class
+    }
+}
+
+/// An executed instruction and the machine state it was executed in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TraceEntry {
+    /// Number of instructions executed before this one.
+    pub cycle: u64,
+    pub pc: u16,
+    pub opcode: u16,
+    pub instruction: Option<Instruction>,
+    pub registers: [u8; 16],
+    pub index: u16,
+    pub sp: usize,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+}
+
+/// Writes the entry on one line of space separated `name=value` fields, followed by the mnemonic
+/// as a comment:
+///
+/// ```text
+/// cycle=12 pc=0218 op=7a04 V0=00 ... VF=01 I=030c SP=1 DT=00 ST=00 ; ADD VA, 0x04
+/// ```
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "cycle={} pc={:04x} op={:04x}",
+            self.cycle, self.pc, self.opcode
+        )?;
+        for (x, value) in self.registers.iter().enumerate() {
+            write!(f, " V{:X}={:02x}", x, value)?;
+        }
+        write!(
+            f,
+            " I={:04x} SP={:x} DT={:02x} ST={:02x} ; ",
+            self.index, self.sp, self.delay_timer, self.sound_timer
+        )?;
+        match self.instruction {
+            Some(instruction) => write!(f, "{}", instruction),
+            None => f.write_str("(invalid opcode)"),
+        }
+    }
+}
+
+/// First line where two traces differ.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    /// Line number, counted from 1.
+    pub line: usize,
+
+    /// The lines of both traces, `None` for the one that ended first.
+    pub left: Option<String>,
+    pub right: Option<String>,
+
+    /// Names of the fields with different values, empty when a trace ended first.
+    pub fields: Vec<String>,
+}
+
+/// Compares two traces written with the `TraceEntry` format line by line and returns the first
+/// line where the machine states differ, ignoring the mnemonics.
+pub fn first_divergence<A: BufRead, B: BufRead>(
+    left: A,
+    right: B,
+) -> io::Result<Option<Divergence>> {
+    let mut left = left.lines();
+    let mut right = right.lines();
+    let mut line = 0;
+    loop {
+        line += 1;
+        let (left, right) = match (left.next().transpose()?, right.next().transpose()?) {
+            (None, None) => return Ok(None),
+            (Some(left), Some(right)) => (left, right),
+            (left, right) => {
+                return Ok(Some(Divergence {
+                    line,
+                    left,
+                    right,
+                    fields: Vec::new(),
+                }))
+            }
+        };
+
+        let left_fields = fields(&left);
+        let right_fields = fields(&right);
+        if left_fields == right_fields {
+            continue;
+        }
+        let mut names: Vec<String> = left_fields
+            .iter()
+            .zip(right_fields.iter())
+            .filter(|(left, right)| left != right)
+            .map(|((name, _), _)| name.to_string())
+            .collect();
+        // Lines of different formats differ in every field past the shorter one
+        if left_fields.len() != right_fields.len() {
+            names.push("format".to_string());
+        }
+        return Ok(Some(Divergence {
+            line,
+            left: Some(left),
+            right: Some(right),
+            fields: names,
+        }));
+    }
+}
+
+// The name=value fields before the mnemonic
+fn fields(line: &str) -> Vec<(&str, &str)> {
+    let state = line.split(';').next().unwrap_or("");
+    state
+        .split_whitespace()
+        .map(|field| {
+            let mut parts = field.splitn(2, '=');
+            let name = parts.next().unwrap_or("");
+            (name, parts.next().unwrap_or(""))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(cycle: u64, pc: u16) -> TraceEntry {
+        TraceEntry {
+            cycle,
+            pc,
+            opcode: 0x7A04,
+            instruction: Some(Instruction::AddByte(0xA, 0x04)),
+            registers: [0x0; 16],
+            index: 0x30C,
+            sp: 0,
+            delay_timer: 0,
+            sound_timer: 0,
+        }
+    }
+
+    fn divergence(left: &str, right: &str) -> Option<Divergence> {
+        first_divergence(left.as_bytes(), right.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn identical_traces_do_not_diverge() {
+        let trace = format!("{}\n{}\n", entry(0, 0x200), entry(1, 0x202));
+        assert_eq!(divergence(&trace, &trace), None);
+    }
+
+    #[test]
+    fn divergence_names_the_fields_that_differ_ignoring_the_mnemonics() {
+        let mut changed = entry(1, 0x202);
+        changed.registers[0xF] = 0x01;
+        changed.delay_timer = 0x3C;
+        changed.instruction = None;
+        let left = format!("{}\n{}\n", entry(0, 0x200), entry(1, 0x202));
+        let right = format!("{}\n{}\n", entry(0, 0x200), changed);
+
+        let found = divergence(&left, &right).unwrap();
+        assert_eq!(found.line, 2);
+        assert_eq!(found.fields, ["VF", "DT"]);
+        assert_eq!(found.right, Some(changed.to_string()));
+
+        // Only the mnemonics differ
+        let mut renamed = entry(0, 0x200);
+        renamed.instruction = None;
+        assert_eq!(
+            divergence(&entry(0, 0x200).to_string(), &renamed.to_string()),
+            None
+        );
+    }
+
+    #[test]
+    fn divergence_reports_the_trace_that_ended_first() {
+        let short = format!("{}\n", entry(0, 0x200));
+        let long = format!("{}\n{}\n", entry(0, 0x200), entry(1, 0x202));
+        assert_eq!(
+            divergence(&short, &long),
+            Some(Divergence {
+                line: 2,
+                left: None,
+                right: Some(entry(1, 0x202).to_string()),
+                fields: Vec::new(),
+            })
+        );
+    }
+
+    #[test]
+    fn lines_of_different_formats_differ_in_format() {
+        let found = divergence("cycle=0 pc=0200 ; CLS\n", "cycle=0 pc=0200 op=00e0\n");
+        assert_eq!(found.unwrap().fields, ["format"]);
+    }
+}
@@ -0,0 +1,98 @@
+use std::fmt;
+
+/// Kind of memory access made by an instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+/// Accesses a watchpoint triggers on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl WatchKind {
+    /// Names accepted by `from_name`.
+    pub const NAMES: [&'static str; 3] = ["r", "w", "rw"];
+
+    /// Returns the kind with the given name (see `NAMES`).
+    pub fn from_name(name: &str) -> Option<WatchKind> {
+        match name {
+            "r" => Some(WatchKind::Read),
+            "w" => Some(WatchKind::Write),
+            "rw" => Some(WatchKind::ReadWrite),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            WatchKind::Read => "r",
+            WatchKind::Write => "w",
+            WatchKind::ReadWrite => "rw",
+        }
+    }
+
+    fn includes(self, access: Access) -> bool {
+        match self {
+            WatchKind::Read => access == Access::Read,
+            WatchKind::Write => access == Access::Write,
+            WatchKind::ReadWrite => true,
+        }
+    }
+}
+
+/// Watches the memory from `start` to `end` inclusive for the accesses made by instructions:
+/// `Dxyn` sprite reads, `Fx33` and `Fx55` writes, `Fx65` reads and their XO-CHIP counterparts.
+/// Instruction fetches are not watched.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Watchpoint {
+    /// Number identifying the watchpoint in the hits it produces.
+    pub id: usize,
+    pub start: u16,
+    pub end: u16,
+    pub kind: WatchKind,
+}
+
+impl Watchpoint {
+    pub(crate) fn matches(&self, address: usize, access: Access) -> bool {
+        self.kind.includes(access) && address >= self.start as usize && address <= self.end as usize
+    }
+}
+
+/// An access that triggered a watchpoint. For reads `old` and `new` are both the value read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WatchHit {
+    /// Number of the watchpoint.
+    pub id: usize,
+
+    /// Address and opcode of the instruction that made the access.
+    pub pc: u16,
+    pub opcode: u16,
+
+    pub address: usize,
+    pub access: Access,
+    pub old: u8,
+    pub new: u8,
+}
+
+impl fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.access {
+            Access::Read => write!(
+                f,
+                "watchpoint {}: {:04x} at {:#05x} read {:#05x} ({:#04x})",
+                self.id, self.opcode, self.pc, self.address, self.new
+            ),
+            Access::Write => write!(
+                f,
+                "watchpoint {}: {:04x} at {:#05x} wrote {:#05x} ({:#04x} -> {:#04x})",
+                self.id, self.opcode, self.pc, self.address, self.old, self.new
+            ),
+        }
+    }
+}
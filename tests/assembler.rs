@@ -0,0 +1,31 @@
+extern crate chip8emu;
+
+use chip8emu::Assembler;
+use std::fs;
+use std::path::Path;
+
+// Sources written for CHIPPER, VBRIX.SRC uses the syntax of another assembler
+const CHIPPER_SOURCES: [&str; 9] = [
+    "15PUZZLE", "BLINKY", "BREAKOUT", "BRIX", "MAZE", "PONG", "PONG2", "SYZYGY", "UFO",
+];
+
+#[test]
+fn chipper_sources_assemble_to_the_shipped_roms() {
+    let games = Path::new(env!("CARGO_MANIFEST_DIR")).join("games");
+    for name in CHIPPER_SOURCES.iter() {
+        // The sources predate UTF-8, comments may hold other characters
+        let source = fs::read(games.join("SOURCES").join(format!("{}.SRC", name))).unwrap();
+        let source = String::from_utf8_lossy(&source);
+        let rom = fs::read(games.join(name)).unwrap();
+
+        let assembled = Assembler::new()
+            .assemble(&source)
+            .unwrap_or_else(|error| panic!("{}.SRC: {}", name, error));
+        assert!(
+            assembled == rom,
+            "{}.SRC does not assemble to games/{}",
+            name,
+            name
+        );
+    }
+}
@@ -0,0 +1,29 @@
+extern crate chip8emu;
+
+use chip8emu::{ControlFlowGraph, Platform, DEFAULT_LOAD_ADDRESS};
+
+#[test]
+fn dot_has_a_node_for_every_edge_target() {
+    // Calls the subroutine at 0x206 and jumps past the end of the ROM
+    let rom = [0x22, 0x06, 0x13, 0x00, 0x00, 0x00, 0x00, 0xEE];
+    let graph = ControlFlowGraph::new(&rom, DEFAULT_LOAD_ADDRESS, Platform::Chip8);
+    let mut dot = Vec::new();
+    graph.write_dot(&mut dot).unwrap();
+    let dot = String::from_utf8(dot).unwrap();
+
+    for line in dot.lines().filter(|line| line.contains("->")) {
+        let target = line.split("->").nth(1).unwrap();
+        let target = target
+            .split_whitespace()
+            .next()
+            .unwrap()
+            .trim_end_matches(';');
+        assert!(
+            dot.lines()
+                .any(|node| node.trim().starts_with(&format!("{} [", target))),
+            "no node for {}",
+            target
+        );
+    }
+    assert!(dot.contains("\"0x300\" [label=\"0x300: no code\", style=dashed];"));
+}
@@ -0,0 +1,82 @@
+extern crate chip8emu;
+
+use chip8emu::{Debugger, Machine, Platform, DEFAULT_LOAD_ADDRESS};
+
+fn execute(debugger: &mut Debugger, machine: &mut Machine, line: &str) -> String {
+    let mut out = Vec::new();
+    debugger.execute(machine, line, &mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+fn machine() -> Machine {
+    Machine::from_bytes(&[0x12, 0x00], Platform::Chip8, DEFAULT_LOAD_ADDRESS).unwrap()
+}
+
+#[test]
+fn set_rejects_values_wider_than_the_target() {
+    let mut debugger = Debugger::new();
+    let mut machine = machine();
+
+    assert!(execute(&mut debugger, &mut machine, "set v3 0x100").contains("does not fit"));
+    assert!(execute(&mut debugger, &mut machine, "set dt 256").contains("does not fit"));
+    assert!(execute(&mut debugger, &mut machine, "set i 0x10000").contains("does not fit"));
+    assert_eq!(machine.registers()[3], 0);
+    assert_eq!(machine.delay_timer(), 0);
+    assert_eq!(machine.index(), 0);
+
+    execute(&mut debugger, &mut machine, "set v3 0xff");
+    execute(&mut debugger, &mut machine, "set i 0xffff");
+    assert_eq!(machine.registers()[3], 0xff);
+    assert_eq!(machine.index(), 0xffff);
+}
+
+#[test]
+fn conditional_breakpoints_reject_values_wider_than_a_register() {
+    let mut debugger = Debugger::new();
+    let mut machine = machine();
+
+    let output = execute(&mut debugger, &mut machine, "break 0x200 if v0 == 0x101");
+    assert!(output.contains("does not fit"));
+    assert!(debugger.breakpoints().is_empty());
+}
+
+#[test]
+fn poke_writes_nothing_when_a_byte_is_invalid() {
+    let mut debugger = Debugger::new();
+    let mut machine = machine();
+
+    let output = execute(&mut debugger, &mut machine, "poke 0x300 1 2 0x300 4");
+    assert!(output.contains("does not fit"));
+    assert_eq!(machine.memory()[0x300..0x304], [0, 0, 0, 0]);
+
+    execute(&mut debugger, &mut machine, "poke 0x300 1 2 3 4");
+    assert_eq!(machine.memory()[0x300..0x304], [1, 2, 3, 4]);
+}
+
+#[test]
+fn examine_rejects_an_index_past_the_end_of_memory() {
+    let mut debugger = Debugger::new();
+    let mut machine = machine();
+
+    execute(&mut debugger, &mut machine, "set i 0x2000");
+    let output = execute(&mut debugger, &mut machine, "x");
+    assert!(output.contains("address out of memory bounds"));
+
+    execute(&mut debugger, &mut machine, "set i 0xff8");
+    let output = execute(&mut debugger, &mut machine, "x");
+    assert_eq!(output, "0x0ff8: 00 00 00 00 00 00 00 00\n");
+}
+
+#[test]
+fn set_and_poke_are_refused_while_recording() {
+    let mut debugger = Debugger::new();
+    let mut machine = machine();
+    debugger.set_recording(true);
+
+    let output = execute(&mut debugger, &mut machine, "set v0 1");
+    assert!(output.contains("disabled while recording"));
+    let output = execute(&mut debugger, &mut machine, "poke 0x300 1");
+    assert!(output.contains("disabled while recording"));
+    assert_eq!(machine.registers()[0], 0);
+    assert_eq!(machine.memory()[0x300], 0);
+}
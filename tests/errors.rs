@@ -0,0 +1,97 @@
+extern crate chip8emu;
+
+use chip8emu::{Chip8Error, Machine, Platform, DEFAULT_LOAD_ADDRESS};
+
+fn machine(rom: &[u8]) -> Machine {
+    Machine::from_bytes(rom, Platform::Chip8, DEFAULT_LOAD_ADDRESS).unwrap()
+}
+
+#[test]
+fn return_with_an_empty_stack_underflows() {
+    let mut machine = machine(&[0x00, 0xEE]);
+    assert!(matches!(
+        machine.step(),
+        Err(Chip8Error::StackUnderflow {
+            pc: 0x200,
+            opcode: 0x00EE
+        })
+    ));
+}
+
+#[test]
+fn seventeen_nested_calls_overflow_the_stack() {
+    // Calls itself forever
+    let mut machine = machine(&[0x22, 0x00]);
+    for _ in 0..16 {
+        machine.step().unwrap();
+    }
+    assert!(matches!(
+        machine.step(),
+        Err(Chip8Error::StackOverflow {
+            pc: 0x200,
+            opcode: 0x2200
+        })
+    ));
+}
+
+#[test]
+fn invalid_opcodes_are_rejected() {
+    let mut machine = machine(&[0x50, 0x01]);
+    assert!(matches!(
+        machine.step(),
+        Err(Chip8Error::InvalidOpcode {
+            pc: 0x200,
+            opcode: 0x5001
+        })
+    ));
+}
+
+#[test]
+fn saving_registers_past_the_end_of_memory_writes_nothing() {
+    // V0-V2 = 0x11, 0x22, 0x33 saved from I = 0xFFE
+    let mut machine = machine(&[0x60, 0x11, 0x61, 0x22, 0x62, 0x33, 0xAF, 0xFE, 0xF2, 0x55]);
+    for _ in 0..4 {
+        machine.step().unwrap();
+    }
+    assert!(matches!(
+        machine.step(),
+        Err(Chip8Error::MemoryOutOfBounds {
+            pc: 0x208,
+            opcode: 0xF255,
+            address: 0x1000
+        })
+    ));
+    assert_eq!(machine.memory()[0xFFE..], [0x00, 0x00]);
+}
+
+#[test]
+fn loading_registers_past_the_end_of_memory_loads_nothing() {
+    // V0 = 0x11, then V0-V1 loaded from I = 0xFFF
+    let mut machine = machine(&[0x60, 0x11, 0xAF, 0xFF, 0xF1, 0x65]);
+    machine.memory_mut()[0xFFF] = 0x99;
+    machine.step().unwrap();
+    machine.step().unwrap();
+    assert!(matches!(
+        machine.step(),
+        Err(Chip8Error::MemoryOutOfBounds {
+            address: 0x1000,
+            ..
+        })
+    ));
+    assert_eq!(machine.registers()[0], 0x11);
+}
+
+#[test]
+fn bcd_past_the_end_of_memory_writes_nothing() {
+    let mut machine = machine(&[0x60, 0xFF, 0xAF, 0xFE, 0xF0, 0x33]);
+    machine.step().unwrap();
+    machine.step().unwrap();
+    assert!(matches!(
+        machine.step(),
+        Err(Chip8Error::MemoryOutOfBounds {
+            address: 0x1000,
+            ..
+        })
+    ));
+    assert_eq!(machine.memory()[0xFFE..], [0x00, 0x00]);
+}
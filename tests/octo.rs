@@ -0,0 +1,53 @@
+extern crate chip8emu;
+
+use chip8emu::{octo, Machine, Platform, DEFAULT_LOAD_ADDRESS};
+
+// Runs an Octo program long enough to reach its final loop and returns V0-VF
+fn run(source: &str) -> [u8; 16] {
+    let rom = octo::compile(source).unwrap();
+    let mut machine = Machine::from_bytes(&rom, Platform::Chip8, DEFAULT_LOAD_ADDRESS).unwrap();
+    for _ in 0..100 {
+        machine.step().unwrap();
+    }
+    *machine.registers()
+}
+
+#[test]
+fn comparisons_with_equal_operands() {
+    let registers = run("
+        : main
+            v0 := 5
+            v5 := 5
+            if v0 >= 5 then v1 := 1
+            if v0 <= 5 then v2 := 1
+            if v0 > 5 then v3 := 1
+            if v0 < 5 then v4 := 1
+            if v0 >= v5 then v6 := 1
+            if v0 <= v5 then v7 := 1
+            if v0 > v5 then v8 := 1
+            if v0 < v5 then v9 := 1
+            loop again
+    ");
+    assert_eq!(&registers[1..5], &[1, 1, 0, 0]);
+    assert_eq!(&registers[6..10], &[1, 1, 0, 0]);
+}
+
+#[test]
+fn comparisons_with_different_operands() {
+    let registers = run("
+        : main
+            v0 := 3
+            if v0 >= 5 then v1 := 1
+            if v0 <= 5 then v2 := 1
+            if v0 > 5 then v3 := 1
+            if v0 < 5 then v4 := 1
+            v0 := 7
+            if v0 >= 5 then v5 := 1
+            if v0 <= 5 then v6 := 1
+            if v0 > 5 then v7 := 1
+            if v0 < 5 then v8 := 1
+            loop again
+    ");
+    assert_eq!(&registers[1..5], &[0, 1, 0, 1]);
+    assert_eq!(&registers[5..9], &[1, 0, 1, 0]);
+}
@@ -0,0 +1,29 @@
+extern crate chip8emu;
+
+use chip8emu::{Machine, Platform, Quirks, DEFAULT_LOAD_ADDRESS};
+
+// Index left by Fx55 and Fx65 with V0-V2 from I = 0x300 under the quirks of each preset
+fn indexes_after_save_and_load(quirks: Quirks) -> (u16, u16) {
+    let rom = [0xA3, 0x00, 0xF2, 0x55, 0xA3, 0x00, 0xF2, 0x65];
+    let mut machine = Machine::from_bytes(&rom, Platform::Chip8, DEFAULT_LOAD_ADDRESS).unwrap();
+    machine.set_quirks(quirks);
+    machine.step().unwrap();
+    machine.step().unwrap();
+    let saved = machine.index();
+    machine.step().unwrap();
+    machine.step().unwrap();
+    (saved, machine.index())
+}
+
+#[test]
+fn save_and_load_increment_the_index_like_each_interpreter() {
+    assert_eq!(
+        indexes_after_save_and_load(Quirks::COSMAC_VIP),
+        (0x303, 0x303)
+    );
+    assert_eq!(indexes_after_save_and_load(Quirks::CHIP_48), (0x302, 0x302));
+    assert_eq!(
+        indexes_after_save_and_load(Quirks::SUPER_CHIP),
+        (0x300, 0x300)
+    );
+}
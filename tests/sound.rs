@@ -0,0 +1,41 @@
+extern crate chip8emu;
+
+use chip8emu::{Machine, Platform, DEFAULT_LOAD_ADDRESS};
+
+const SAMPLE_RATE: u32 = 48000;
+const SAMPLES_PER_FRAME: usize = 800;
+
+// Sets the sound timer to 2 with the fourth of the 8 instructions of the first frame
+const ROM: [u8; 10] = [0x60, 0x02, 0x61, 0x00, 0x61, 0x00, 0xF0, 0x18, 0x12, 0x08];
+
+fn render_frame(machine: &mut Machine) -> Vec<f32> {
+    machine.run_frame().unwrap();
+    let mut samples = vec![0.0; SAMPLES_PER_FRAME];
+    machine.render_audio(SAMPLE_RATE, &mut samples);
+    samples
+}
+
+#[test]
+fn tone_starts_where_the_sound_timer_is_set_and_stops_when_it_runs_out() {
+    let mut machine = Machine::from_bytes(&ROM, Platform::Chip8, DEFAULT_LOAD_ADDRESS).unwrap();
+    machine.set_instructions_per_frame(8);
+
+    // Silent until the middle of the first frame
+    let first = render_frame(&mut machine);
+    assert!(first[..SAMPLES_PER_FRAME / 2]
+        .iter()
+        .all(|&sample| sample == 0.0));
+    assert!(first[SAMPLES_PER_FRAME / 2] != 0.0);
+
+    // The timer runs out with the tick at the end of the second frame
+    let second = render_frame(&mut machine);
+    assert!(second.iter().all(|&sample| sample != 0.0));
+    assert_eq!(machine.sound_timer(), 0);
+
+    // Only the 2 ms fade out is left
+    let third = render_frame(&mut machine);
+    assert!(third[0] != 0.0);
+    assert!(third[SAMPLE_RATE as usize / 500..]
+        .iter()
+        .all(|&sample| sample == 0.0));
+}